@@ -3,11 +3,27 @@ use std::marker::PhantomData;
 use std::ops::{RangeFrom, RangeInclusive};
 use std::str;
 
-use crate::types::{AttrMacro, Attribute, State};
+use crate::parser::core::{is_atom_char, is_text_char};
+use crate::types::{
+    AclIdentifier, AclModifier, AclRight, AttrMacro, Attribute, Metadata, QuotaResourceName,
+    SpecialUse, State, UidSetMember,
+};
 
+/// Builds the commands sent by a client
+///
+/// Unless documented otherwise, string arguments are sent as quoted strings,
+/// and the builders panic if one contains CR or LF.
 pub struct CommandBuilder {}
 
 impl CommandBuilder {
+    pub fn capability() -> Command {
+        let args = b"CAPABILITY".to_vec();
+        Command {
+            args,
+            next_state: None,
+        }
+    }
+
     pub fn check() -> Command {
         let args = b"CHECK".to_vec();
         Command {
@@ -24,6 +40,60 @@ impl CommandBuilder {
         }
     }
 
+    /// Start compressing all data in both directions with DEFLATE (RFC 4978)
+    pub fn compress_deflate() -> Command {
+        let args = b"COMPRESS DEFLATE".to_vec();
+        Command {
+            args,
+            next_state: None,
+        }
+    }
+
+    pub fn create(mailbox: &str) -> CreateCommand {
+        let args = format!("CREATE \"{}\"", quoted_string(mailbox).unwrap()).into_bytes();
+        CreateCommand {
+            args,
+            uses: Vec::new(),
+        }
+    }
+
+    /// Remove all rights of an identifier on a mailbox (RFC 4314)
+    pub fn delete_acl(mailbox: &str, identifier: &AclIdentifier<'_>) -> Command {
+        let args = format!(
+            "DELETEACL \"{}\" \"{}\"",
+            quoted_string(mailbox).unwrap(),
+            acl_identifier(identifier)
+        )
+        .into_bytes();
+        Command {
+            args,
+            next_state: None,
+        }
+    }
+
+    /// Enable extensions on the server (RFC 5161)
+    ///
+    /// Panics if no capabilities are given, or if one is not a valid atom.
+    pub fn enable<'a, I>(capabilities: I) -> Command
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        let mut args = b"ENABLE".to_vec();
+        for capability in capabilities {
+            assert!(
+                !capability.is_empty() && capability.bytes().all(is_atom_char),
+                "invalid capability"
+            );
+            args.push(b' ');
+            args.extend(capability.as_bytes());
+        }
+        assert!(args.len() > b"ENABLE".len(), "no capabilities to enable");
+        Command {
+            args,
+            next_state: None,
+        }
+    }
+
     pub fn examine(mailbox: &str) -> SelectCommand<select::NoParams> {
         let args = format!("EXAMINE \"{}\"", quoted_string(mailbox).unwrap()).into_bytes();
         SelectCommand {
@@ -32,6 +102,14 @@ impl CommandBuilder {
         }
     }
 
+    pub fn expunge() -> Command {
+        let args = b"EXPUNGE".to_vec();
+        Command {
+            args,
+            next_state: None,
+        }
+    }
+
     pub fn fetch() -> FetchCommand<fetch::Empty> {
         FetchCommand {
             args: b"FETCH ".to_vec(),
@@ -39,6 +117,90 @@ impl CommandBuilder {
         }
     }
 
+    /// Request the access control list of a mailbox (RFC 4314)
+    pub fn get_acl(mailbox: &str) -> Command {
+        let args = format!("GETACL \"{}\"", quoted_string(mailbox).unwrap()).into_bytes();
+        Command {
+            args,
+            next_state: None,
+        }
+    }
+
+    /// Request the values of metadata entries of a mailbox (RFC 5464)
+    ///
+    /// Use an empty mailbox name for server metadata.
+    pub fn get_metadata(mailbox: &str, entries: &[&str]) -> GetMetadataCommand {
+        let mut args = format!("\"{}\" (", quoted_string(mailbox).unwrap()).into_bytes();
+        for (i, entry) in entries.iter().enumerate() {
+            if i > 0 {
+                args.push(b' ');
+            }
+            args.extend(format!("\"{}\"", quoted_string(entry).unwrap()).as_bytes());
+        }
+        args.push(b')');
+        GetMetadataCommand {
+            args,
+            max_size: None,
+            depth: None,
+        }
+    }
+
+    /// Request the usage and limits of a quota root (RFC 9208)
+    pub fn get_quota(root: &str) -> Command {
+        let args = format!("GETQUOTA \"{}\"", quoted_string(root).unwrap()).into_bytes();
+        Command {
+            args,
+            next_state: None,
+        }
+    }
+
+    /// Request the quota roots of a mailbox and their quotas (RFC 9208)
+    pub fn get_quota_root(mailbox: &str) -> Command {
+        let args = format!("GETQUOTAROOT \"{}\"", quoted_string(mailbox).unwrap()).into_bytes();
+        Command {
+            args,
+            next_state: None,
+        }
+    }
+
+    /// Identify the client to the server (RFC 2971)
+    ///
+    /// Parameters are sent in the given order; a value of `None` is sent as NIL.
+    /// Without any parameters, the parameter list is sent as NIL. Panics if a
+    /// key or value contains CR or LF.
+    pub fn id<'a, I>(params: I) -> Command
+    where
+        I: IntoIterator<Item = (&'a str, Option<&'a str>)>,
+    {
+        let mut list = Vec::new();
+        for (key, value) in params {
+            if !list.is_empty() {
+                list.push(b' ');
+            }
+            list.extend(format!("\"{}\" ", quoted_string(key).unwrap()).as_bytes());
+            match value {
+                Some(value) => {
+                    list.extend(format!("\"{}\"", quoted_string(value).unwrap()).as_bytes())
+                }
+                None => list.extend(b"NIL"),
+            }
+        }
+
+        let mut args = b"ID ".to_vec();
+        match list.is_empty() {
+            true => args.extend(b"NIL"),
+            false => {
+                args.push(b'(');
+                args.extend(list);
+                args.push(b')');
+            }
+        }
+        Command {
+            args,
+            next_state: None,
+        }
+    }
+
     pub fn list(reference: &str, glob: &str) -> Command {
         let args = format!(
             "LIST \"{}\" \"{}\"",
@@ -52,6 +214,20 @@ impl CommandBuilder {
         }
     }
 
+    /// Request the rights that can be granted to an identifier on a mailbox (RFC 4314)
+    pub fn list_rights(mailbox: &str, identifier: &AclIdentifier<'_>) -> Command {
+        let args = format!(
+            "LISTRIGHTS \"{}\" \"{}\"",
+            quoted_string(mailbox).unwrap(),
+            acl_identifier(identifier)
+        )
+        .into_bytes();
+        Command {
+            args,
+            next_state: None,
+        }
+    }
+
     pub fn login(user_name: &str, password: &str) -> Command {
         let args = format!(
             "LOGIN \"{}\" \"{}\"",
@@ -65,6 +241,41 @@ impl CommandBuilder {
         }
     }
 
+    /// End the session; the server responds with `BYE` and closes the connection
+    pub fn logout() -> Command {
+        let args = b"LOGOUT".to_vec();
+        Command {
+            args,
+            next_state: Some(State::Logout),
+        }
+    }
+
+    /// Request the rights of the current user on a mailbox (RFC 4314)
+    pub fn my_rights(mailbox: &str) -> Command {
+        let args = format!("MYRIGHTS \"{}\"", quoted_string(mailbox).unwrap()).into_bytes();
+        Command {
+            args,
+            next_state: None,
+        }
+    }
+
+    /// Request the namespaces of the server (RFC 2342)
+    pub fn namespace() -> Command {
+        let args = b"NAMESPACE".to_vec();
+        Command {
+            args,
+            next_state: None,
+        }
+    }
+
+    pub fn noop() -> Command {
+        let args = b"NOOP".to_vec();
+        Command {
+            args,
+            next_state: None,
+        }
+    }
+
     pub fn select(mailbox: &str) -> SelectCommand<select::NoParams> {
         let args = format!("SELECT \"{}\"", quoted_string(mailbox).unwrap()).into_bytes();
         SelectCommand {
@@ -73,12 +284,159 @@ impl CommandBuilder {
         }
     }
 
+    /// Change the rights of an identifier on a mailbox (RFC 4314)
+    pub fn set_acl(
+        mailbox: &str,
+        identifier: &AclIdentifier<'_>,
+        modifier: AclModifier,
+        rights: &[AclRight],
+    ) -> Command {
+        let mut mod_rights = match modifier {
+            AclModifier::Replace => String::new(),
+            AclModifier::Add => "+".to_owned(),
+            AclModifier::Remove => "-".to_owned(),
+        };
+        mod_rights.extend(rights.iter().map(|&r| char::from(r)));
+        let args = format!(
+            "SETACL \"{}\" \"{}\" \"{}\"",
+            quoted_string(mailbox).unwrap(),
+            acl_identifier(identifier),
+            quoted_string(&mod_rights).unwrap()
+        )
+        .into_bytes();
+        Command {
+            args,
+            next_state: None,
+        }
+    }
+
+    /// Set or remove (with a `None` value) metadata entries of a mailbox (RFC 5464)
+    ///
+    /// Values that cannot be sent as quoted strings, like binary values, are
+    /// sent as synchronizing literals; the client must wait for a continuation
+    /// request before sending their data. Values containing NUL bytes are sent
+    /// as `literal8`, which RFC 5464 allows for this.
+    pub fn set_metadata(mailbox: &str, values: &[Metadata<'_>]) -> Command {
+        let mut args =
+            format!("SETMETADATA \"{}\" (", quoted_string(mailbox).unwrap()).into_bytes();
+        for (i, metadata) in values.iter().enumerate() {
+            if i > 0 {
+                args.push(b' ');
+            }
+            args.extend(format!("\"{}\" ", quoted_string(&metadata.entry).unwrap()).as_bytes());
+            match metadata.value.as_deref() {
+                Some(value) if value.iter().all(|&c| is_text_char(c)) => {
+                    args.push(b'"');
+                    for &c in value {
+                        if c == b'\\' || c == b'"' {
+                            args.push(b'\\');
+                        }
+                        args.push(c);
+                    }
+                    args.push(b'"');
+                }
+                Some(value) => {
+                    if value.contains(&0) {
+                        args.push(b'~');
+                    }
+                    args.extend(format!("{{{}}}\r\n", value.len()).as_bytes());
+                    args.extend(value);
+                }
+                None => args.extend(b"NIL"),
+            }
+        }
+        args.push(b')');
+        Command {
+            args,
+            next_state: None,
+        }
+    }
+
+    /// Set the limits of a quota root (RFC 9208)
+    ///
+    /// Resources that are not given lose their limit. Panics if the name of an
+    /// `Atom` resource is not a valid atom.
+    pub fn set_quota<'a, I>(root: &str, limits: I) -> Command
+    where
+        I: IntoIterator<Item = (QuotaResourceName<'a>, u64)>,
+    {
+        let mut args = format!("SETQUOTA \"{}\" (", quoted_string(root).unwrap()).into_bytes();
+        for (i, (name, limit)) in limits.into_iter().enumerate() {
+            if i > 0 {
+                args.push(b' ');
+            }
+            let name = match &name {
+                QuotaResourceName::Storage => "STORAGE",
+                QuotaResourceName::Message => "MESSAGE",
+                QuotaResourceName::Mailbox => "MAILBOX",
+                QuotaResourceName::AnnotationStorage => "ANNOTATION-STORAGE",
+                QuotaResourceName::Atom(name) => {
+                    assert!(
+                        !name.is_empty() && name.bytes().all(is_atom_char),
+                        "invalid quota resource name"
+                    );
+                    name
+                }
+            };
+            args.extend(format!("{name} {limit}").as_bytes());
+        }
+        args.push(b')');
+        Command {
+            args,
+            next_state: None,
+        }
+    }
+
+    /// Expunge only the given messages marked `\Deleted` (RFC 4315)
+    ///
+    /// Panics if `uids` is empty.
+    pub fn uid_expunge(uids: &[UidSetMember]) -> Command {
+        assert!(!uids.is_empty(), "empty UID set");
+        let mut args = b"UID EXPUNGE ".to_vec();
+        for (i, member) in uids.iter().enumerate() {
+            if i > 0 {
+                args.push(b',');
+            }
+            match member {
+                UidSetMember::Uid(uid) => sequence_num(&mut args, *uid),
+                UidSetMember::UidRange(range) => sequence_range(&mut args, range.clone()),
+            }
+        }
+        Command {
+            args,
+            next_state: None,
+        }
+    }
+
     pub fn uid_fetch() -> FetchCommand<fetch::Empty> {
         FetchCommand {
             args: b"UID FETCH ".to_vec(),
             state: PhantomData,
         }
     }
+
+    /// Close the selected mailbox without expunging it (RFC 3691)
+    pub fn unselect() -> Command {
+        let args = b"UNSELECT".to_vec();
+        Command {
+            args,
+            next_state: Some(State::Authenticated),
+        }
+    }
+
+    /// List mailboxes with their special uses, on servers that predate SPECIAL-USE
+    pub fn xlist(reference: &str, glob: &str) -> Command {
+        let args = format!(
+            "XLIST \"{}\" \"{}\"",
+            quoted_string(reference).unwrap(),
+            quoted_string(glob).unwrap()
+        )
+        .into_bytes();
+        Command {
+            args,
+            next_state: None,
+        }
+    }
 }
 
 pub struct Command {
@@ -126,6 +484,97 @@ pub mod select {
     pub struct Params;
 }
 
+pub struct CreateCommand {
+    args: Vec<u8>,
+    uses: Vec<SpecialUse>,
+}
+
+impl CreateCommand {
+    /// Mark the new mailbox for a special use (RFC 6154 CREATE-SPECIAL-USE)
+    pub fn special_use(mut self, special_use: SpecialUse) -> Self {
+        self.uses.push(special_use);
+        self
+    }
+}
+
+impl From<CreateCommand> for Command {
+    fn from(mut cmd: CreateCommand) -> Command {
+        if !cmd.uses.is_empty() {
+            let uses = cmd.uses.iter().map(|u| u.as_str()).collect::<Vec<_>>();
+            cmd.args
+                .extend(format!(" (USE ({}))", uses.join(" ")).as_bytes());
+        }
+        Command {
+            args: cmd.args,
+            next_state: None,
+        }
+    }
+}
+
+pub struct GetMetadataCommand {
+    /// The mailbox and entries
+    args: Vec<u8>,
+    max_size: Option<u64>,
+    depth: Option<MetadataDepth>,
+}
+
+impl GetMetadataCommand {
+    /// Only return values of at most `size` octets
+    ///
+    /// The completion response has a `METADATA LONGENTRIES` code with the size
+    /// of the largest value that was left out.
+    pub fn max_size(mut self, size: u64) -> Self {
+        self.max_size = Some(size);
+        self
+    }
+
+    /// Also return entries below the requested ones
+    pub fn depth(mut self, depth: MetadataDepth) -> Self {
+        self.depth = Some(depth);
+        self
+    }
+}
+
+impl From<GetMetadataCommand> for Command {
+    fn from(cmd: GetMetadataCommand) -> Command {
+        let mut options = Vec::new();
+        if let Some(size) = cmd.max_size {
+            options.push(format!("MAXSIZE {size}"));
+        }
+        if let Some(depth) = cmd.depth {
+            options.push(format!(
+                "DEPTH {}",
+                match depth {
+                    MetadataDepth::Zero => "0",
+                    MetadataDepth::One => "1",
+                    MetadataDepth::Infinity => "infinity",
+                }
+            ));
+        }
+
+        let mut args = b"GETMETADATA ".to_vec();
+        if !options.is_empty() {
+            args.extend(format!("({}) ", options.join(" ")).as_bytes());
+        }
+        args.extend(cmd.args);
+        Command {
+            args,
+            next_state: None,
+        }
+    }
+}
+
+/// How far below the requested entries GETMETADATA returns entries (RFC 5464)
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MetadataDepth {
+    /// Only the requested entries
+    Zero,
+    /// The requested entries and their immediate children
+    One,
+    /// The requested entries and all their descendants
+    Infinity,
+}
+
 pub mod fetch {
     pub struct Empty;
     pub struct Messages;
@@ -293,6 +742,15 @@ fn changed_since(cmd: &mut Vec<u8>, seq: u64) {
     cmd.push(b')');
 }
 
+// The identifier as the contents of a quoted string
+fn acl_identifier(identifier: &AclIdentifier<'_>) -> String {
+    let name = quoted_string(&identifier.name).unwrap();
+    match identifier.negative {
+        true => format!("-{name}"),
+        false => name.into_owned(),
+    }
+}
+
 /// Returns an escaped string if necessary for use as a "quoted" string per
 /// the IMAPv4 RFC. Return value does not include surrounding quote characters.
 /// Will return Err if the argument contains illegal characters.
@@ -338,7 +796,11 @@ fn quoted_string(s: &str) -> Result<Cow<'_, str>, &'static str> {
 
 #[cfg(test)]
 mod tests {
-    use super::{quoted_string, Attribute, Command, CommandBuilder};
+    use super::{
+        quoted_string, AclIdentifier, AclModifier, AclRight, Attribute, Command, CommandBuilder,
+        Metadata, MetadataDepth, QuotaResourceName, SpecialUse, State, UidSetMember,
+    };
+    use std::borrow::Cow;
 
     #[test]
     fn login() {
@@ -352,6 +814,160 @@ mod tests {
         );
     }
 
+    #[test]
+    fn id() {
+        let cmd = CommandBuilder::id([("name", Some("ourapp")), ("version", Some("1.2"))]);
+        assert_eq!(cmd.args, br#"ID ("name" "ourapp" "version" "1.2")"#);
+        let cmd = CommandBuilder::id([("os", None), ("vendor", Some("a \"b\""))]);
+        assert_eq!(cmd.args, br#"ID ("os" NIL "vendor" "a \"b\"")"#);
+        assert_eq!(CommandBuilder::id([]).args, b"ID NIL");
+    }
+
+    #[test]
+    #[should_panic(expected = "CR and LF not allowed")]
+    fn id_newline() {
+        CommandBuilder::id([("name", Some("our\r\napp"))]);
+    }
+
+    #[test]
+    fn acl() {
+        let fred = AclIdentifier::new("fred");
+        let cmd = CommandBuilder::set_acl(
+            "Shared",
+            &fred,
+            AclModifier::Add,
+            &[AclRight::Read, AclRight::Write],
+        );
+        assert_eq!(cmd.args, br#"SETACL "Shared" "fred" "+rw""#);
+        let cmd = CommandBuilder::set_acl(
+            "Shared",
+            &AclIdentifier::negative("fred"),
+            AclModifier::Replace,
+            &[AclRight::Expunge],
+        );
+        assert_eq!(cmd.args, br#"SETACL "Shared" "-fred" "e""#);
+        let cmd = CommandBuilder::set_acl(
+            "Shared",
+            &AclIdentifier::anyone(),
+            AclModifier::Remove,
+            &[AclRight::Lookup],
+        );
+        assert_eq!(cmd.args, br#"SETACL "Shared" "anyone" "-l""#);
+
+        let cmd = CommandBuilder::delete_acl("Shared", &AclIdentifier::negative("fred"));
+        assert_eq!(cmd.args, br#"DELETEACL "Shared" "-fred""#);
+        assert_eq!(
+            CommandBuilder::get_acl("Shared").args,
+            br#"GETACL "Shared""#
+        );
+        let cmd = CommandBuilder::list_rights("Shared", &fred);
+        assert_eq!(cmd.args, br#"LISTRIGHTS "Shared" "fred""#);
+        assert_eq!(
+            CommandBuilder::my_rights("Shared").args,
+            br#"MYRIGHTS "Shared""#
+        );
+    }
+
+    #[test]
+    fn simple() {
+        assert_eq!(CommandBuilder::capability().args, b"CAPABILITY");
+        assert_eq!(CommandBuilder::noop().args, b"NOOP");
+        assert_eq!(CommandBuilder::expunge().args, b"EXPUNGE");
+        let cmd = CommandBuilder::logout();
+        assert_eq!(cmd.args, b"LOGOUT");
+        assert_eq!(cmd.next_state, Some(State::Logout));
+        let cmd = CommandBuilder::unselect();
+        assert_eq!(cmd.args, b"UNSELECT");
+        assert_eq!(cmd.next_state, Some(State::Authenticated));
+    }
+
+    #[test]
+    fn enable() {
+        let cmd = CommandBuilder::enable(["CONDSTORE", "UTF8=ACCEPT"]);
+        assert_eq!(cmd.args, b"ENABLE CONDSTORE UTF8=ACCEPT");
+    }
+
+    #[test]
+    fn uid_expunge() {
+        let cmd = CommandBuilder::uid_expunge(&[UidSetMember::Uid(3), (5..=9).into()]);
+        assert_eq!(cmd.args, b"UID EXPUNGE 3,5:9");
+    }
+
+    #[test]
+    fn create() {
+        let cmd = CommandBuilder::create("Archive/2024");
+        assert_eq!(Command::from(cmd).args, br#"CREATE "Archive/2024""#);
+        let cmd = CommandBuilder::create("Sent")
+            .special_use(SpecialUse::Sent)
+            .special_use(SpecialUse::Archive);
+        assert_eq!(
+            Command::from(cmd).args,
+            br#"CREATE "Sent" (USE (\Sent \Archive))"#
+        );
+        let cmd = CommandBuilder::xlist("", "*");
+        assert_eq!(cmd.args, br#"XLIST "" "*""#);
+    }
+
+    #[test]
+    fn metadata() {
+        let cmd = CommandBuilder::get_metadata("INBOX", &["/private/comment"]);
+        assert_eq!(
+            Command::from(cmd).args,
+            br#"GETMETADATA "INBOX" ("/private/comment")"#
+        );
+        let cmd = CommandBuilder::get_metadata("", &["/shared/comment", "/shared/admin"])
+            .max_size(1024)
+            .depth(MetadataDepth::Infinity);
+        assert_eq!(
+            Command::from(cmd).args,
+            br#"GETMETADATA (MAXSIZE 1024 DEPTH infinity) "" ("/shared/comment" "/shared/admin")"#
+        );
+
+        let cmd = CommandBuilder::set_metadata(
+            "INBOX",
+            &[
+                Metadata {
+                    entry: "/private/comment".into(),
+                    value: Some(Cow::Borrowed(br#"My "own" mailbox"#)),
+                },
+                Metadata {
+                    entry: "/shared/vendor/acme/blob".into(),
+                    value: Some(Cow::Borrowed(b"\xff\r\n")),
+                },
+                Metadata {
+                    entry: "/shared/comment".into(),
+                    value: None,
+                },
+                Metadata {
+                    entry: "/shared/vendor/acme/key".into(),
+                    value: Some(Cow::Borrowed(b"\0\x01")),
+                },
+            ],
+        );
+        assert_eq!(
+            cmd.args,
+            &b"SETMETADATA \"INBOX\" (\"/private/comment\" \"My \\\"own\\\" mailbox\" \
+               \"/shared/vendor/acme/blob\" {3}\r\n\xff\r\n \"/shared/comment\" NIL \
+               \"/shared/vendor/acme/key\" ~{2}\r\n\0\x01)"[..]
+        );
+    }
+
+    #[test]
+    fn quota() {
+        assert_eq!(CommandBuilder::get_quota("").args, br#"GETQUOTA """#);
+        let cmd = CommandBuilder::get_quota_root("INBOX");
+        assert_eq!(cmd.args, br#"GETQUOTAROOT "INBOX""#);
+        let cmd = CommandBuilder::set_quota(
+            "",
+            [
+                (QuotaResourceName::Storage, 512),
+                (QuotaResourceName::Atom("X-DAILY".into()), 100),
+            ],
+        );
+        assert_eq!(cmd.args, br#"SETQUOTA "" (STORAGE 512 X-DAILY 100)"#);
+        assert_eq!(CommandBuilder::set_quota("", []).args, br#"SETQUOTA "" ()"#);
+    }
+
     #[test]
     fn select() {
         let cmd = Command::from(CommandBuilder::select("INBOX"));
@@ -0,0 +1,1782 @@
+use std::borrow::Cow;
+use std::error::Error;
+use std::fmt;
+
+use crate::parser::core::{is_astring_char, is_atom_char, is_value_char};
+use crate::types::*;
+
+/// Error returned when a `Response` cannot be serialized
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum EncodeError {
+    /// Human-readable text (`resp-text`) may not contain CR or LF
+    InvalidText,
+    /// The response contains an item that must be sent as an atom, but isn't one
+    InvalidAtom(String),
+    /// The response contains a string that must be sent as a quoted string,
+    /// but contains characters that cannot be quoted
+    InvalidQuoted(String),
+    /// The arguments of a response code contain `]`, CR or LF
+    InvalidCodeArgs(String),
+    /// Serialization of this kind of response is not implemented yet
+    Unsupported(&'static str),
+}
+
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EncodeError::InvalidText => write!(f, "response text may not contain CR or LF"),
+            EncodeError::InvalidAtom(s) => write!(f, "invalid atom {s:?}"),
+            EncodeError::InvalidQuoted(s) => write!(f, "cannot send {s:?} as a quoted string"),
+            EncodeError::InvalidCodeArgs(s) => write!(f, "invalid response code arguments {s:?}"),
+            EncodeError::Unsupported(what) => write!(f, "encoding {what} is not supported"),
+        }
+    }
+}
+
+impl Error for EncodeError {}
+
+impl<'a> Response<'a> {
+    /// Serialize the response, including the trailing CRLF, into `dst`
+    ///
+    /// Strings are sent as quoted strings where possible and as literals
+    /// otherwise, so that the output can always be parsed back with
+    /// `Response::from_bytes()`. On error, `dst` may contain a partial response.
+    pub fn encode(&self, dst: &mut Vec<u8>) -> Result<(), EncodeError> {
+        match self {
+            Response::Capabilities(caps) => {
+                dst.extend(b"* CAPABILITY");
+                for cap in caps {
+                    dst.push(b' ');
+                    capability(dst, cap)?;
+                }
+            }
+            Response::Continue { code, information } => {
+                dst.extend(b"+ ");
+                resp_text(dst, code, information)?;
+            }
+            Response::Done {
+                tag,
+                status: st,
+                code,
+                information,
+            } => {
+                dst.extend(tag.as_bytes());
+                dst.push(b' ');
+                status(dst, st);
+                trailing_resp_text(dst, code, information)?;
+            }
+            Response::Data {
+                status: st,
+                code,
+                information,
+            } => {
+                dst.extend(b"* ");
+                status(dst, st);
+                trailing_resp_text(dst, code, information)?;
+            }
+            Response::Expunge(seq) => {
+                dst.extend(format!("* {seq} EXPUNGE").as_bytes());
+            }
+            Response::Vanished { earlier, uids } => {
+                dst.extend(b"* VANISHED ");
+                if *earlier {
+                    dst.extend(b"(EARLIER) ");
+                }
+                for (i, range) in uids.iter().enumerate() {
+                    if i > 0 {
+                        dst.push(b',');
+                    }
+                    if range.start() == range.end() {
+                        dst.extend(range.start().to_string().as_bytes());
+                    } else {
+                        dst.extend(format!("{}:{}", range.start(), range.end()).as_bytes());
+                    }
+                }
+            }
+            Response::Fetch(seq, attrs) => {
+                dst.extend(format!("* {seq} FETCH (").as_bytes());
+                for (i, attr) in attrs.iter().enumerate() {
+                    if i > 0 {
+                        dst.push(b' ');
+                    }
+                    attribute_value(dst, attr)?;
+                }
+                dst.push(b')');
+            }
+            Response::MailboxData(datum) => {
+                dst.extend(b"* ");
+                mailbox_datum(dst, datum)?;
+            }
+            Response::Quota(quota) => {
+                dst.extend(b"* QUOTA ");
+                astring(dst, quota.root_name.as_bytes());
+                dst.extend(b" (");
+                for (i, resource) in quota.resources.iter().enumerate() {
+                    if i > 0 {
+                        dst.push(b' ');
+                    }
+                    match &resource.name {
+                        QuotaResourceName::Storage => dst.extend(b"STORAGE"),
+                        QuotaResourceName::Message => dst.extend(b"MESSAGE"),
+                        QuotaResourceName::Mailbox => dst.extend(b"MAILBOX"),
+                        QuotaResourceName::AnnotationStorage => dst.extend(b"ANNOTATION-STORAGE"),
+                        QuotaResourceName::Atom(name) => atom(dst, name)?,
+                    }
+                    dst.extend(format!(" {} {}", resource.usage, resource.limit).as_bytes());
+                }
+                dst.push(b')');
+            }
+            Response::QuotaRoot(root) => {
+                dst.extend(b"* QUOTAROOT ");
+                astring(dst, root.mailbox_name.as_bytes());
+                for name in &root.quota_root_names {
+                    dst.push(b' ');
+                    astring(dst, name.as_bytes());
+                }
+            }
+            Response::Id(params) => {
+                dst.extend(b"* ID ");
+                match params {
+                    // The parser doesn't accept an empty list, so send NIL instead
+                    Some(params) if !params.is_empty() => {
+                        dst.push(b'(');
+                        for (i, (key, value)) in params.iter().enumerate() {
+                            if i > 0 {
+                                dst.push(b' ');
+                            }
+                            string(dst, key.as_bytes());
+                            dst.push(b' ');
+                            string(dst, value.as_bytes());
+                        }
+                        dst.push(b')');
+                    }
+                    _ => dst.extend(b"NIL"),
+                }
+            }
+            Response::Acl(acl) => {
+                dst.extend(b"* ACL ");
+                astring(dst, acl.mailbox.as_bytes());
+                for entry in &acl.acls {
+                    dst.push(b' ');
+                    astring(dst, entry.identifier.as_bytes());
+                    dst.push(b' ');
+                    rights(dst, &entry.rights);
+                }
+            }
+            Response::ListRights(list) => {
+                dst.extend(b"* LISTRIGHTS ");
+                astring(dst, list.mailbox.as_bytes());
+                dst.push(b' ');
+                astring(dst, list.identifier.as_bytes());
+                dst.push(b' ');
+                rights(dst, &list.required);
+                for right in &list.optional {
+                    dst.push(b' ');
+                    rights(dst, &[*right]);
+                }
+            }
+            Response::MyRights(my) => {
+                dst.extend(b"* MYRIGHTS ");
+                astring(dst, my.mailbox.as_bytes());
+                dst.push(b' ');
+                rights(dst, &my.rights);
+            }
+            Response::Namespace(namespaces) => {
+                dst.extend(b"* NAMESPACE ");
+                namespace_list(dst, &namespaces.personal)?;
+                dst.push(b' ');
+                namespace_list(dst, &namespaces.other_users)?;
+                dst.push(b' ');
+                namespace_list(dst, &namespaces.shared)?;
+            }
+            // Already includes the final CRLF
+            Response::Unparsed { raw, .. } => {
+                dst.extend(raw.iter());
+                return Ok(());
+            }
+        }
+        dst.extend(b"\r\n");
+        Ok(())
+    }
+
+    /// Serialize the response into a newly allocated buffer
+    pub fn to_bytes(&self) -> Result<Vec<u8>, EncodeError> {
+        let mut buf = Vec::new();
+        self.encode(&mut buf)?;
+        Ok(buf)
+    }
+}
+
+fn status(dst: &mut Vec<u8>, status: &Status) {
+    dst.extend(match status {
+        Status::Ok => &b"OK"[..],
+        Status::No => b"NO",
+        Status::Bad => b"BAD",
+        Status::PreAuth => b"PREAUTH",
+        Status::Bye => b"BYE",
+    });
+}
+
+// The parser accepts a missing resp-text at the end of status responses,
+// so leave out the separating space entirely if there's nothing to send.
+fn trailing_resp_text(
+    dst: &mut Vec<u8>,
+    code: &Option<ResponseCode<'_>>,
+    information: &Option<Cow<'_, str>>,
+) -> Result<(), EncodeError> {
+    if code.is_none() && information.is_none() {
+        return Ok(());
+    }
+    dst.push(b' ');
+    resp_text(dst, code, information)
+}
+
+// resp-text = ["[" resp-text-code "]" SP] text
+fn resp_text(
+    dst: &mut Vec<u8>,
+    code: &Option<ResponseCode<'_>>,
+    information: &Option<Cow<'_, str>>,
+) -> Result<(), EncodeError> {
+    if let Some(code) = code {
+        dst.push(b'[');
+        response_code(dst, code)?;
+        dst.push(b']');
+        if information.is_some() {
+            dst.push(b' ');
+        }
+    }
+    if let Some(text) = information {
+        if text.bytes().any(|b| b == b'\r' || b == b'\n') {
+            return Err(EncodeError::InvalidText);
+        }
+        dst.extend(text.as_bytes());
+    }
+    Ok(())
+}
+
+fn response_code(dst: &mut Vec<u8>, code: &ResponseCode<'_>) -> Result<(), EncodeError> {
+    match code {
+        ResponseCode::Alert => dst.extend(b"ALERT"),
+        ResponseCode::BadCharset(charsets) => {
+            dst.extend(b"BADCHARSET");
+            if let Some(charsets) = charsets {
+                dst.extend(b" (");
+                for (i, charset) in charsets.iter().enumerate() {
+                    if i > 0 {
+                        dst.push(b' ');
+                    }
+                    astring(dst, charset.as_bytes());
+                }
+                dst.push(b')');
+            }
+        }
+        ResponseCode::Capabilities(caps) => {
+            dst.extend(b"CAPABILITY");
+            for cap in caps {
+                dst.push(b' ');
+                capability(dst, cap)?;
+            }
+        }
+        ResponseCode::HighestModSeq(v) => dst.extend(format!("HIGHESTMODSEQ {v}").as_bytes()),
+        ResponseCode::Parse => dst.extend(b"PARSE"),
+        ResponseCode::PermanentFlags(flags) => {
+            dst.extend(b"PERMANENTFLAGS ");
+            flag_list(dst, flags)?;
+        }
+        ResponseCode::ReadOnly => dst.extend(b"READ-ONLY"),
+        ResponseCode::ReadWrite => dst.extend(b"READ-WRITE"),
+        ResponseCode::TryCreate => dst.extend(b"TRYCREATE"),
+        ResponseCode::UidNext(v) => dst.extend(format!("UIDNEXT {v}").as_bytes()),
+        ResponseCode::UidValidity(v) => dst.extend(format!("UIDVALIDITY {v}").as_bytes()),
+        ResponseCode::Unseen(v) => dst.extend(format!("UNSEEN {v}").as_bytes()),
+        ResponseCode::AppendUid(validity, uids) => {
+            dst.extend(format!("APPENDUID {validity} ").as_bytes());
+            uid_set(dst, uids);
+        }
+        ResponseCode::CopyUid(validity, from, to) => {
+            dst.extend(format!("COPYUID {validity} ").as_bytes());
+            uid_set(dst, from);
+            dst.push(b' ');
+            uid_set(dst, to);
+        }
+        ResponseCode::UidNotSticky => dst.extend(b"UIDNOTSTICKY"),
+        ResponseCode::MetadataLongEntries(v) => {
+            dst.extend(format!("METADATA LONGENTRIES {v}").as_bytes())
+        }
+        ResponseCode::MetadataMaxSize(v) => dst.extend(format!("METADATA MAXSIZE {v}").as_bytes()),
+        ResponseCode::MetadataTooMany => dst.extend(b"METADATA TOOMANY"),
+        ResponseCode::MetadataNoPrivate => dst.extend(b"METADATA NOPRIVATE"),
+        ResponseCode::Other { name, args } => {
+            atom(dst, name)?;
+            match args {
+                OtherArgs::Values(values) => {
+                    for value in values {
+                        dst.push(b' ');
+                        imap_value(dst, value)?;
+                    }
+                }
+                OtherArgs::Raw(raw) => {
+                    if raw.is_empty() || raw.contains([']', '\r', '\n']) {
+                        return Err(EncodeError::InvalidCodeArgs(raw.to_string()));
+                    }
+                    dst.push(b' ');
+                    dst.extend(raw.as_bytes());
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// An empty list is sent as NIL, since the grammar doesn't allow "()"
+fn namespace_list(dst: &mut Vec<u8>, namespaces: &[Namespace<'_>]) -> Result<(), EncodeError> {
+    if namespaces.is_empty() {
+        dst.extend(b"NIL");
+        return Ok(());
+    }
+
+    dst.push(b'(');
+    for namespace in namespaces {
+        dst.push(b'(');
+        string(dst, namespace.prefix.as_bytes());
+        dst.push(b' ');
+        // The delimiter is a QUOTED-CHAR, so it can't be sent as a literal
+        match &namespace.delimiter {
+            Some(delimiter) => quoted(dst, delimiter)?,
+            None => dst.extend(b"NIL"),
+        }
+        for extension in &namespace.extensions {
+            dst.push(b' ');
+            string(dst, extension.name.as_bytes());
+            dst.extend(b" (");
+            for (i, value) in extension.values.iter().enumerate() {
+                if i > 0 {
+                    dst.push(b' ');
+                }
+                string(dst, value.as_bytes());
+            }
+            dst.push(b')');
+        }
+        dst.push(b')');
+    }
+    dst.push(b')');
+    Ok(())
+}
+
+fn uid_set(dst: &mut Vec<u8>, uids: &[UidSetMember]) {
+    for (i, member) in uids.iter().enumerate() {
+        if i > 0 {
+            dst.push(b',');
+        }
+        match member {
+            UidSetMember::Uid(uid) => dst.extend(uid.to_string().as_bytes()),
+            UidSetMember::UidRange(range) => {
+                dst.extend(format!("{}:{}", range.start(), range.end()).as_bytes())
+            }
+        }
+    }
+}
+
+fn capability(dst: &mut Vec<u8>, cap: &Capability<'_>) -> Result<(), EncodeError> {
+    match cap {
+        Capability::Imap4rev1 => dst.extend(b"IMAP4rev1"),
+        Capability::Auth(mechanism) => {
+            dst.extend(b"AUTH=");
+            atom(dst, mechanism)?;
+        }
+        Capability::Atom(name) => atom(dst, name)?,
+    }
+    Ok(())
+}
+
+fn mailbox_datum(dst: &mut Vec<u8>, datum: &MailboxDatum<'_>) -> Result<(), EncodeError> {
+    match datum {
+        MailboxDatum::Exists(n) => dst.extend(format!("{n} EXISTS").as_bytes()),
+        MailboxDatum::Recent(n) => dst.extend(format!("{n} RECENT").as_bytes()),
+        MailboxDatum::Flags(flags) => {
+            dst.extend(b"FLAGS ");
+            flag_list(dst, flags)?;
+        }
+        MailboxDatum::List {
+            name_attributes,
+            delimiter,
+            name,
+        } => {
+            dst.extend(b"LIST (");
+            for (i, attr) in name_attributes.iter().enumerate() {
+                if i > 0 {
+                    dst.push(b' ');
+                }
+                name_attribute(dst, attr)?;
+            }
+            dst.extend(b") ");
+            match delimiter {
+                Some(delimiter) => string(dst, delimiter.as_bytes()),
+                None => dst.extend(b"NIL"),
+            }
+            dst.push(b' ');
+            astring(dst, name.as_bytes());
+        }
+        MailboxDatum::Search(ids) => {
+            dst.extend(b"SEARCH");
+            for id in ids {
+                dst.extend(format!(" {id}").as_bytes());
+            }
+        }
+        MailboxDatum::Sort(ids) => {
+            dst.extend(b"SORT");
+            for id in ids {
+                dst.extend(format!(" {id}").as_bytes());
+            }
+        }
+        MailboxDatum::Status { mailbox, status } => {
+            dst.extend(b"STATUS ");
+            astring(dst, mailbox.as_bytes());
+            dst.extend(b" (");
+            for (i, attr) in status.iter().enumerate() {
+                if i > 0 {
+                    dst.push(b' ');
+                }
+                let (name, value) = match attr {
+                    StatusAttribute::HighestModSeq(v) => ("HIGHESTMODSEQ", *v),
+                    StatusAttribute::Messages(v) => ("MESSAGES", u64::from(*v)),
+                    StatusAttribute::Recent(v) => ("RECENT", u64::from(*v)),
+                    StatusAttribute::UidNext(v) => ("UIDNEXT", u64::from(*v)),
+                    StatusAttribute::UidValidity(v) => ("UIDVALIDITY", u64::from(*v)),
+                    StatusAttribute::Unseen(v) => ("UNSEEN", u64::from(*v)),
+                    StatusAttribute::DeletedStorage(v) => ("DELETED-STORAGE", *v),
+                };
+                dst.extend(format!("{name} {value}").as_bytes());
+            }
+            dst.push(b')');
+        }
+        MailboxDatum::MetadataSolicited { mailbox, values } => {
+            dst.extend(b"METADATA ");
+            astring(dst, mailbox.as_bytes());
+            dst.extend(b" (");
+            for (i, metadata) in values.iter().enumerate() {
+                if i > 0 {
+                    dst.push(b' ');
+                }
+                astring(dst, metadata.entry.as_bytes());
+                dst.push(b' ');
+                nstring(dst, metadata.value.as_deref());
+            }
+            dst.push(b')');
+        }
+        MailboxDatum::MetadataUnsolicited { mailbox, values } => {
+            dst.extend(b"METADATA ");
+            astring(dst, mailbox.as_bytes());
+            for entry in values {
+                dst.push(b' ');
+                astring(dst, entry.as_bytes());
+            }
+        }
+        MailboxDatum::GmailLabels(labels) => {
+            dst.extend(b"X-GM-LABELS ");
+            gmail_labels(dst, labels)?;
+        }
+        MailboxDatum::GmailMsgId(id) => dst.extend(format!("X-GM-MSGID {id}").as_bytes()),
+        MailboxDatum::GmailThrId(id) => dst.extend(format!("X-GM-THRID {id}").as_bytes()),
+    }
+    Ok(())
+}
+
+fn name_attribute(dst: &mut Vec<u8>, attr: &NameAttribute<'_>) -> Result<(), EncodeError> {
+    match attr {
+        NameAttribute::NoInferiors => dst.extend(b"\\Noinferiors"),
+        NameAttribute::NoSelect => dst.extend(b"\\Noselect"),
+        NameAttribute::Marked => dst.extend(b"\\Marked"),
+        NameAttribute::Unmarked => dst.extend(b"\\Unmarked"),
+        NameAttribute::All => dst.extend(b"\\All"),
+        NameAttribute::Archive => dst.extend(b"\\Archive"),
+        NameAttribute::Drafts => dst.extend(b"\\Drafts"),
+        NameAttribute::Flagged => dst.extend(b"\\Flagged"),
+        NameAttribute::Junk => dst.extend(b"\\Junk"),
+        NameAttribute::Sent => dst.extend(b"\\Sent"),
+        NameAttribute::Trash => dst.extend(b"\\Trash"),
+        NameAttribute::Extension(s) => flag(dst, s)?,
+    }
+    Ok(())
+}
+
+fn attribute_value(dst: &mut Vec<u8>, attr: &AttributeValue<'_>) -> Result<(), EncodeError> {
+    match attr {
+        AttributeValue::BodySection {
+            section,
+            index,
+            data,
+        } => {
+            dst.extend(b"BODY[");
+            if let Some(section) = section {
+                section_path(dst, section);
+            }
+            dst.push(b']');
+            if let Some(index) = index {
+                dst.extend(format!("<{index}>").as_bytes());
+            }
+            dst.push(b' ');
+            nstring(dst, data.as_deref());
+        }
+        AttributeValue::Flags(flags) => {
+            dst.extend(b"FLAGS ");
+            flag_list(dst, flags)?;
+        }
+        AttributeValue::InternalDate(date) => {
+            dst.extend(b"INTERNALDATE ");
+            string(dst, date.as_bytes());
+        }
+        AttributeValue::ModSeq(v) => dst.extend(format!("MODSEQ ({v})").as_bytes()),
+        AttributeValue::Rfc822(data) => {
+            dst.extend(b"RFC822 ");
+            nstring(dst, data.as_deref());
+        }
+        AttributeValue::Rfc822Header(data) => {
+            dst.extend(b"RFC822.HEADER ");
+            nstring(dst, data.as_deref());
+        }
+        AttributeValue::Rfc822Size(v) => dst.extend(format!("RFC822.SIZE {v}").as_bytes()),
+        AttributeValue::Rfc822Text(data) => {
+            dst.extend(b"RFC822.TEXT ");
+            nstring(dst, data.as_deref());
+        }
+        AttributeValue::Uid(v) => dst.extend(format!("UID {v}").as_bytes()),
+        AttributeValue::EmailId(id) => {
+            dst.extend(b"EMAILID (");
+            atom(dst, id)?;
+            dst.push(b')');
+        }
+        AttributeValue::ThreadId(id) => {
+            dst.extend(b"THREADID ");
+            match id {
+                Some(id) => {
+                    dst.push(b'(');
+                    atom(dst, id)?;
+                    dst.push(b')');
+                }
+                None => dst.extend(b"NIL"),
+            }
+        }
+        AttributeValue::BodyStructure(body) => {
+            dst.extend(b"BODYSTRUCTURE ");
+            body_structure(dst, body);
+        }
+        AttributeValue::Envelope(env) => {
+            dst.extend(b"ENVELOPE ");
+            envelope(dst, env);
+        }
+        AttributeValue::GmailLabels(labels) => {
+            dst.extend(b"X-GM-LABELS ");
+            gmail_labels(dst, labels)?;
+        }
+        AttributeValue::GmailMsgId(id) => dst.extend(format!("X-GM-MSGID {id}").as_bytes()),
+        AttributeValue::GmailThrId(id) => dst.extend(format!("X-GM-THRID {id}").as_bytes()),
+        AttributeValue::Unknown { name, value } => {
+            if name.is_empty() || !name.bytes().all(is_astring_char) {
+                return Err(EncodeError::InvalidAtom(name.to_string()));
+            }
+            dst.extend(name.as_bytes());
+            dst.push(b' ');
+            imap_value(dst, value)?;
+        }
+    }
+    Ok(())
+}
+
+fn section_path(dst: &mut Vec<u8>, section: &SectionPath) {
+    let (part, text) = match section {
+        SectionPath::Full(text) => (&[][..], Some(text)),
+        SectionPath::Part(part, text) => (&part[..], text.as_ref()),
+    };
+    for (i, num) in part.iter().enumerate() {
+        if i > 0 {
+            dst.push(b'.');
+        }
+        dst.extend(num.to_string().as_bytes());
+    }
+    if let Some(text) = text {
+        if !part.is_empty() {
+            dst.push(b'.');
+        }
+        dst.extend(match text {
+            MessageSection::Header => &b"HEADER"[..],
+            MessageSection::Mime => b"MIME",
+            MessageSection::Text => b"TEXT",
+        });
+    }
+}
+
+fn gmail_labels(dst: &mut Vec<u8>, labels: &[Cow<'_, str>]) -> Result<(), EncodeError> {
+    dst.push(b'(');
+    for (i, label) in labels.iter().enumerate() {
+        if i > 0 {
+            dst.push(b' ');
+        }
+        // Labels are sent as flags where possible, as Gmail itself does for system labels
+        let name = label.strip_prefix('\\').unwrap_or(label);
+        if !name.is_empty() && name.bytes().all(is_atom_char) {
+            dst.extend(label.as_bytes());
+        } else {
+            string(dst, label.as_bytes());
+        }
+    }
+    dst.push(b')');
+    Ok(())
+}
+
+fn rights(dst: &mut Vec<u8>, rights: &[AclRight]) {
+    let rights = rights.iter().map(|&r| char::from(r)).collect::<String>();
+    astring(dst, rights.as_bytes());
+}
+
+// body = "(" (body-type-1part / body-type-mpart) ")"
+fn body_structure(dst: &mut Vec<u8>, body: &BodyStructure<'_>) {
+    dst.push(b'(');
+    match body {
+        BodyStructure::Basic {
+            common,
+            other,
+            extension,
+        } => {
+            string(dst, common.ty.ty.as_bytes());
+            dst.push(b' ');
+            string(dst, common.ty.subtype.as_bytes());
+            dst.push(b' ');
+            body_fields(dst, &common.ty.params, other);
+            body_ext_1part(dst, common, other, extension);
+        }
+        BodyStructure::Text {
+            common,
+            other,
+            lines,
+            extension,
+        } => {
+            dst.extend(b"\"TEXT\" ");
+            string(dst, common.ty.subtype.as_bytes());
+            dst.push(b' ');
+            body_fields(dst, &common.ty.params, other);
+            dst.extend(format!(" {lines}").as_bytes());
+            body_ext_1part(dst, common, other, extension);
+        }
+        BodyStructure::Message {
+            common,
+            other,
+            envelope: env,
+            body,
+            lines,
+            extension,
+        } => {
+            dst.extend(b"\"MESSAGE\" \"RFC822\" ");
+            body_fields(dst, &common.ty.params, other);
+            dst.push(b' ');
+            envelope(dst, env);
+            dst.push(b' ');
+            body_structure(dst, body);
+            dst.extend(format!(" {lines}").as_bytes());
+            body_ext_1part(dst, common, other, extension);
+        }
+        BodyStructure::Multipart {
+            common,
+            bodies,
+            extension,
+        } => {
+            for body in bodies {
+                body_structure(dst, body);
+            }
+            dst.push(b' ');
+            string(dst, common.ty.subtype.as_bytes());
+            // body-ext-mpart = body-fld-param [SP body-fld-dsp [SP body-fld-lang
+            //                  [SP body-fld-loc *(SP body-extension)]]]
+            let last = if extension.is_some() {
+                5
+            } else if common.location.is_some() {
+                4
+            } else if common.language.is_some() {
+                3
+            } else if common.disposition.is_some() {
+                2
+            } else if common.ty.params.is_some() {
+                1
+            } else {
+                0
+            };
+            if last >= 1 {
+                dst.push(b' ');
+                body_params(dst, &common.ty.params);
+            }
+            body_ext_common(dst, common, extension, last);
+        }
+    }
+    dst.push(b')');
+}
+
+// body-fields = body-fld-param SP body-fld-id SP body-fld-desc SP
+//               body-fld-enc SP body-fld-octets
+fn body_fields(dst: &mut Vec<u8>, params: &BodyParams<'_>, other: &BodyContentSinglePart<'_>) {
+    body_params(dst, params);
+    dst.push(b' ');
+    nstring(dst, other.id.as_ref().map(|s| s.as_bytes()));
+    dst.push(b' ');
+    nstring(dst, other.description.as_ref().map(|s| s.as_bytes()));
+    dst.push(b' ');
+    match &other.transfer_encoding {
+        ContentEncoding::SevenBit => dst.extend(b"\"7BIT\""),
+        ContentEncoding::EightBit => dst.extend(b"\"8BIT\""),
+        ContentEncoding::Binary => dst.extend(b"\"BINARY\""),
+        ContentEncoding::Base64 => dst.extend(b"\"BASE64\""),
+        ContentEncoding::QuotedPrintable => dst.extend(b"\"QUOTED-PRINTABLE\""),
+        ContentEncoding::Other(enc) => string(dst, enc.as_bytes()),
+    }
+    dst.extend(format!(" {}", other.octets).as_bytes());
+}
+
+// body-ext-1part = body-fld-md5 [SP body-fld-dsp [SP body-fld-lang
+//                  [SP body-fld-loc *(SP body-extension)]]]
+//
+// Trailing fields that are absent are left out, any absent fields in between
+// are sent as NIL.
+fn body_ext_1part(
+    dst: &mut Vec<u8>,
+    common: &BodyContentCommon<'_>,
+    other: &BodyContentSinglePart<'_>,
+    extension: &Option<BodyExtension<'_>>,
+) {
+    let last = if extension.is_some() {
+        5
+    } else if common.location.is_some() {
+        4
+    } else if common.language.is_some() {
+        3
+    } else if common.disposition.is_some() {
+        2
+    } else if other.md5.is_some() {
+        1
+    } else {
+        0
+    };
+    if last >= 1 {
+        dst.push(b' ');
+        nstring(dst, other.md5.as_ref().map(|s| s.as_bytes()));
+    }
+    body_ext_common(dst, common, extension, last);
+}
+
+fn body_ext_common(
+    dst: &mut Vec<u8>,
+    common: &BodyContentCommon<'_>,
+    extension: &Option<BodyExtension<'_>>,
+    last: u8,
+) {
+    if last >= 2 {
+        dst.push(b' ');
+        match &common.disposition {
+            Some(disposition) => {
+                dst.push(b'(');
+                string(dst, disposition.ty.as_bytes());
+                dst.push(b' ');
+                body_params(dst, &disposition.params);
+                dst.push(b')');
+            }
+            None => dst.extend(b"NIL"),
+        }
+    }
+    if last >= 3 {
+        dst.push(b' ');
+        match common.language.as_deref() {
+            Some([language]) => string(dst, language.as_bytes()),
+            Some(languages) if !languages.is_empty() => {
+                dst.push(b'(');
+                for (i, language) in languages.iter().enumerate() {
+                    if i > 0 {
+                        dst.push(b' ');
+                    }
+                    string(dst, language.as_bytes());
+                }
+                dst.push(b')');
+            }
+            _ => dst.extend(b"NIL"),
+        }
+    }
+    if last >= 4 {
+        dst.push(b' ');
+        nstring(dst, common.location.as_ref().map(|s| s.as_bytes()));
+    }
+    if let Some(extension) = extension {
+        dst.push(b' ');
+        body_extension(dst, extension);
+    }
+}
+
+fn body_extension(dst: &mut Vec<u8>, extension: &BodyExtension<'_>) {
+    match extension {
+        BodyExtension::Num(num) => dst.extend(num.to_string().as_bytes()),
+        BodyExtension::Str(s) => nstring(dst, s.as_ref().map(|s| s.as_bytes())),
+        BodyExtension::List(list) if list.is_empty() => dst.extend(b"NIL"),
+        BodyExtension::List(list) => {
+            dst.push(b'(');
+            for (i, ext) in list.iter().enumerate() {
+                if i > 0 {
+                    dst.push(b' ');
+                }
+                body_extension(dst, ext);
+            }
+            dst.push(b')');
+        }
+    }
+}
+
+// body-fld-param = "(" string SP string *(SP string SP string) ")" / nil
+fn body_params(dst: &mut Vec<u8>, params: &BodyParams<'_>) {
+    match params {
+        Some(params) if !params.is_empty() => {
+            dst.push(b'(');
+            for (i, (key, value)) in params.iter().enumerate() {
+                if i > 0 {
+                    dst.push(b' ');
+                }
+                string(dst, key.as_bytes());
+                dst.push(b' ');
+                string(dst, value.as_bytes());
+            }
+            dst.push(b')');
+        }
+        _ => dst.extend(b"NIL"),
+    }
+}
+
+// envelope = "(" env-date SP env-subject SP env-from SP env-sender SP
+//            env-reply-to SP env-to SP env-cc SP env-bcc SP
+//            env-in-reply-to SP env-message-id ")"
+fn envelope(dst: &mut Vec<u8>, env: &Envelope<'_>) {
+    dst.push(b'(');
+    nstring(dst, env.date.as_deref());
+    dst.push(b' ');
+    nstring(dst, env.subject.as_deref());
+    for addresses in [
+        &env.from,
+        &env.sender,
+        &env.reply_to,
+        &env.to,
+        &env.cc,
+        &env.bcc,
+    ] {
+        dst.push(b' ');
+        match addresses {
+            Some(addresses) if !addresses.is_empty() => {
+                dst.push(b'(');
+                for address in addresses {
+                    dst.push(b'(');
+                    nstring(dst, address.name.as_deref());
+                    dst.push(b' ');
+                    nstring(dst, address.adl.as_deref());
+                    dst.push(b' ');
+                    nstring(dst, address.mailbox.as_deref());
+                    dst.push(b' ');
+                    nstring(dst, address.host.as_deref());
+                    dst.push(b')');
+                }
+                dst.push(b')');
+            }
+            _ => dst.extend(b"NIL"),
+        }
+    }
+    dst.push(b' ');
+    nstring(dst, env.in_reply_to.as_deref());
+    dst.push(b' ');
+    nstring(dst, env.message_id.as_deref());
+    dst.push(b')');
+}
+
+fn flag_list(dst: &mut Vec<u8>, flags: &[Cow<'_, str>]) -> Result<(), EncodeError> {
+    dst.push(b'(');
+    for (i, f) in flags.iter().enumerate() {
+        if i > 0 {
+            dst.push(b' ');
+        }
+        flag(dst, f)?;
+    }
+    dst.push(b')');
+    Ok(())
+}
+
+// flag = "\Answered" / ... / flag-keyword / flag-extension
+fn flag(dst: &mut Vec<u8>, flag: &str) -> Result<(), EncodeError> {
+    let name = flag.strip_prefix('\\').unwrap_or(flag);
+    if name != "*" && (name.is_empty() || !name.bytes().all(is_atom_char)) {
+        return Err(EncodeError::InvalidAtom(flag.to_owned()));
+    }
+    dst.extend(flag.as_bytes());
+    Ok(())
+}
+
+fn atom(dst: &mut Vec<u8>, s: &str) -> Result<(), EncodeError> {
+    if s.is_empty() || !s.bytes().all(is_atom_char) {
+        return Err(EncodeError::InvalidAtom(s.to_owned()));
+    }
+    dst.extend(s.as_bytes());
+    Ok(())
+}
+
+// Atoms that would be read back as NIL or as a number are rejected.
+fn imap_value(dst: &mut Vec<u8>, value: &ImapValue<'_>) -> Result<(), EncodeError> {
+    match value {
+        ImapValue::Nil => dst.extend(b"NIL"),
+        ImapValue::Number(n) => dst.extend(n.to_string().as_bytes()),
+        ImapValue::Atom(s) => {
+            let valid = !s.is_empty()
+                && s.bytes().all(is_value_char)
+                && !s.bytes().all(|b| b.is_ascii_digit())
+                && !s.eq_ignore_ascii_case("NIL");
+            if !valid {
+                return Err(EncodeError::InvalidAtom(s.to_string()));
+            }
+            dst.extend(s.as_bytes());
+        }
+        ImapValue::String(s) => string(dst, s),
+        ImapValue::List(items) => {
+            dst.push(b'(');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    dst.push(b' ');
+                }
+                imap_value(dst, item)?;
+            }
+            dst.push(b')');
+        }
+    }
+    Ok(())
+}
+
+// astring = 1*ASTRING-CHAR / string
+fn astring(dst: &mut Vec<u8>, s: &[u8]) {
+    if !s.is_empty() && s.iter().all(|&b| is_astring_char(b)) && !s.eq_ignore_ascii_case(b"NIL") {
+        dst.extend(s);
+    } else {
+        string(dst, s);
+    }
+}
+
+// nstring = string / nil
+fn nstring(dst: &mut Vec<u8>, s: Option<&[u8]>) {
+    match s {
+        Some(s) => string(dst, s),
+        None => dst.extend(b"NIL"),
+    }
+}
+
+// quoted = DQUOTE *QUOTED-CHAR DQUOTE
+//
+// For the few places where the grammar doesn't allow a literal.
+fn quoted(dst: &mut Vec<u8>, s: &str) -> Result<(), EncodeError> {
+    if s.bytes().any(|b| matches!(b, b'\0' | b'\r' | b'\n')) {
+        return Err(EncodeError::InvalidQuoted(s.to_owned()));
+    }
+    dst.push(b'"');
+    for b in s.bytes() {
+        if b == b'"' || b == b'\\' {
+            dst.push(b'\\');
+        }
+        dst.push(b);
+    }
+    dst.push(b'"');
+    Ok(())
+}
+
+// string = quoted / literal
+//
+// The response parser hands out the contents of quoted strings without
+// unescaping them, so anything that would need escaping is sent as a
+// literal instead, which makes sure it can be read back unchanged.
+fn string(dst: &mut Vec<u8>, s: &[u8]) {
+    let quotable = s
+        .iter()
+        .all(|&b| b.is_ascii() && !matches!(b, b'\0' | b'\r' | b'\n' | b'"' | b'\\'));
+    if quotable {
+        dst.push(b'"');
+        dst.extend(s);
+        dst.push(b'"');
+    } else {
+        dst.extend(format!("{{{}}}\r\n", s.len()).as_bytes());
+        dst.extend(s);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(response: &Response<'_>) -> Vec<u8> {
+        response.to_bytes().unwrap()
+    }
+
+    #[test]
+    fn status_responses() {
+        let rsp = Response::Done {
+            tag: RequestId("A0001".into()),
+            status: Status::Ok,
+            code: Some(ResponseCode::ReadWrite),
+            information: Some(Cow::Borrowed("SELECT completed")),
+        };
+        assert_eq!(encode(&rsp), b"A0001 OK [READ-WRITE] SELECT completed\r\n");
+
+        let rsp = Response::Data {
+            status: Status::Ok,
+            code: Some(ResponseCode::PermanentFlags(vec![
+                Cow::Borrowed("\\Seen"),
+                Cow::Borrowed("\\*"),
+            ])),
+            information: None,
+        };
+        assert_eq!(encode(&rsp), b"* OK [PERMANENTFLAGS (\\Seen \\*)]\r\n");
+
+        let rsp = Response::Continue {
+            code: None,
+            information: Some(Cow::Borrowed("idling")),
+        };
+        assert_eq!(encode(&rsp), b"+ idling\r\n");
+
+        let rsp = Response::Data {
+            status: Status::Bye,
+            code: None,
+            information: Some(Cow::Borrowed("bad\r\n")),
+        };
+        assert_eq!(rsp.to_bytes(), Err(EncodeError::InvalidText));
+    }
+
+    #[test]
+    fn mailbox_data() {
+        let rsp = Response::MailboxData(MailboxDatum::List {
+            name_attributes: vec![NameAttribute::NoSelect],
+            delimiter: Some(Cow::Borrowed("/")),
+            name: Cow::Borrowed("Public Folders"),
+        });
+        assert_eq!(
+            encode(&rsp),
+            b"* LIST (\\Noselect) \"/\" \"Public Folders\"\r\n"
+        );
+
+        let rsp = Response::MailboxData(MailboxDatum::Status {
+            mailbox: Cow::Borrowed("INBOX"),
+            status: vec![StatusAttribute::Messages(3), StatusAttribute::UidNext(9)],
+        });
+        assert_eq!(encode(&rsp), b"* STATUS INBOX (MESSAGES 3 UIDNEXT 9)\r\n");
+    }
+
+    #[test]
+    fn fetch() {
+        let rsp = Response::Fetch(
+            12,
+            vec![
+                AttributeValue::Uid(4),
+                AttributeValue::Flags(vec![Cow::Borrowed("\\Seen")]),
+                AttributeValue::BodySection {
+                    section: None,
+                    index: None,
+                    data: Some(Cow::Borrowed(b"Subject: hi\r\n\r\n")),
+                },
+            ],
+        );
+        assert_eq!(
+            encode(&rsp),
+            &b"* 12 FETCH (UID 4 FLAGS (\\Seen) BODY[] {15}\r\nSubject: hi\r\n\r\n)\r\n"[..]
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let responses = [
+            Response::Capabilities(vec![
+                Capability::Imap4rev1,
+                Capability::Auth(Cow::Borrowed("PLAIN")),
+                Capability::Atom(Cow::Borrowed("IDLE")),
+            ]),
+            Response::Expunge(3),
+            Response::Vanished {
+                earlier: true,
+                uids: vec![1..=3, 5..=5],
+            },
+            Response::MailboxData(MailboxDatum::Exists(18)),
+            Response::MailboxData(MailboxDatum::Search(vec![2, 84, 882])),
+            Response::Fetch(
+                1,
+                vec![AttributeValue::Rfc822(Some(Cow::Borrowed(
+                    b"a \"quoted\" \\ thing",
+                )))],
+            ),
+            Response::Done {
+                tag: RequestId("a1".into()),
+                status: Status::No,
+                code: Some(ResponseCode::CopyUid(
+                    38505,
+                    vec![UidSetMember::UidRange(304..=306), UidSetMember::Uid(319)],
+                    vec![UidSetMember::UidRange(3956..=3959)],
+                )),
+                information: Some(Cow::Borrowed("Done")),
+            },
+        ];
+
+        for rsp in responses {
+            let bytes = encode(&rsp);
+            let (rest, parsed) = Response::from_bytes(&bytes).unwrap();
+            assert!(rest.is_empty());
+            assert_eq!(parsed, rsp);
+        }
+    }
+
+    #[test]
+    fn unknown_attribute() {
+        let rsp = Response::Fetch(
+            1,
+            vec![AttributeValue::Unknown {
+                name: Cow::Borrowed("X-FOO"),
+                value: ImapValue::List(vec![
+                    ImapValue::Atom(Cow::Borrowed("\\Bar")),
+                    ImapValue::Number(3),
+                    ImapValue::List(vec![
+                        ImapValue::Nil,
+                        ImapValue::String(Cow::Borrowed(b"a\"b")),
+                    ]),
+                ]),
+            }],
+        );
+        let bytes = encode(&rsp);
+        assert_eq!(bytes, b"* 1 FETCH (X-FOO (\\Bar 3 (NIL {3}\r\na\"b)))\r\n");
+        assert_eq!(Response::from_bytes(&bytes).unwrap().1, rsp);
+
+        let rsp = Response::Fetch(
+            1,
+            vec![AttributeValue::Unknown {
+                name: Cow::Borrowed("X-FOO"),
+                value: ImapValue::Atom(Cow::Borrowed("123")),
+            }],
+        );
+        assert_eq!(rsp.to_bytes(), Err(EncodeError::InvalidAtom("123".into())));
+    }
+
+    #[test]
+    fn unparsed() {
+        let raw = &b"* XFOO ({3}\r\nbar)\r\n"[..];
+        let rsp = Response::Unparsed {
+            tag: None,
+            raw: Cow::Borrowed(raw),
+        };
+        assert_eq!(rsp.to_bytes().unwrap(), raw);
+    }
+
+    #[test]
+    fn body_structure() {
+        let rsp = Response::Fetch(
+            1,
+            vec![AttributeValue::BodyStructure(BodyStructure::Multipart {
+                common: BodyContentCommon {
+                    ty: ContentType {
+                        ty: Cow::Borrowed("MULTIPART"),
+                        subtype: Cow::Borrowed("MIXED"),
+                        params: None,
+                    },
+                    disposition: None,
+                    language: None,
+                    location: None,
+                },
+                bodies: vec![BodyStructure::Text {
+                    common: BodyContentCommon {
+                        ty: ContentType {
+                            ty: Cow::Borrowed("TEXT"),
+                            subtype: Cow::Borrowed("PLAIN"),
+                            params: Some(vec![(Cow::Borrowed("CHARSET"), Cow::Borrowed("UTF-8"))]),
+                        },
+                        disposition: None,
+                        language: Some(vec![Cow::Borrowed("en")]),
+                        location: None,
+                    },
+                    other: BodyContentSinglePart {
+                        id: None,
+                        md5: None,
+                        description: None,
+                        transfer_encoding: ContentEncoding::SevenBit,
+                        octets: 42,
+                    },
+                    lines: 2,
+                    extension: None,
+                }],
+                extension: None,
+            })],
+        );
+        assert_eq!(
+            encode(&rsp),
+            &b"* 1 FETCH (BODYSTRUCTURE ((\"TEXT\" \"PLAIN\" (\"CHARSET\" \"UTF-8\") NIL NIL \"7BIT\" 42 2 NIL NIL \"en\") \"MIXED\"))\r\n"[..]
+        );
+    }
+
+    mod round_trip {
+        use super::*;
+        use proptest::collection::vec;
+        use proptest::option;
+        use proptest::prelude::*;
+        use proptest::strategy::LazyJust;
+
+        fn cow_str(re: &'static str) -> impl Strategy<Value = Cow<'static, str>> {
+            re.prop_map(Cow::Owned)
+        }
+
+        fn cow_bytes() -> impl Strategy<Value = Cow<'static, [u8]>> {
+            vec(any::<u8>(), 0..40).prop_map(Cow::Owned)
+        }
+
+        fn any_str() -> impl Strategy<Value = Cow<'static, str>> {
+            any::<String>().prop_map(Cow::Owned)
+        }
+
+        fn mailbox() -> impl Strategy<Value = Cow<'static, str>> {
+            any::<String>()
+                .prop_filter("INBOX is case-insensitive", |s| {
+                    !s.eq_ignore_ascii_case("INBOX")
+                })
+                .prop_map(Cow::Owned)
+        }
+
+        fn text() -> impl Strategy<Value = Option<Cow<'static, str>>> {
+            option::of(cow_str("[a-zA-Z0-9.,!?-][a-zA-Z0-9 .,!?-]{0,30}"))
+        }
+
+        fn flag() -> impl Strategy<Value = Cow<'static, str>> {
+            cow_str("\\\\?[a-zA-Z$][a-zA-Z0-9$_-]{0,10}")
+        }
+
+        fn status() -> impl Strategy<Value = Status> {
+            prop_oneof![
+                LazyJust::new(|| Status::Ok),
+                LazyJust::new(|| Status::No),
+                LazyJust::new(|| Status::Bad),
+                LazyJust::new(|| Status::PreAuth),
+                LazyJust::new(|| Status::Bye),
+            ]
+        }
+
+        fn uid_set() -> impl Strategy<Value = Vec<UidSetMember>> {
+            vec(
+                prop_oneof![
+                    any::<u32>().prop_map(UidSetMember::Uid),
+                    (any::<u32>(), 1..100u32).prop_map(|(start, len)| {
+                        UidSetMember::UidRange(start..=start.saturating_add(len))
+                    }),
+                ],
+                1..4,
+            )
+        }
+
+        fn response_code() -> impl Strategy<Value = ResponseCode<'static>> {
+            prop_oneof![
+                LazyJust::new(|| ResponseCode::Alert),
+                option::of(vec(cow_str("[A-Z0-9-]{1,10}"), 1..3))
+                    .prop_map(ResponseCode::BadCharset),
+                any::<u64>().prop_map(ResponseCode::HighestModSeq),
+                LazyJust::new(|| ResponseCode::Parse),
+                vec(flag(), 0..4).prop_map(ResponseCode::PermanentFlags),
+                LazyJust::new(|| ResponseCode::ReadOnly),
+                LazyJust::new(|| ResponseCode::ReadWrite),
+                LazyJust::new(|| ResponseCode::TryCreate),
+                any::<u32>().prop_map(ResponseCode::UidNext),
+                any::<u32>().prop_map(ResponseCode::UidValidity),
+                any::<u32>().prop_map(ResponseCode::Unseen),
+                (any::<u32>(), uid_set()).prop_map(|(v, s)| ResponseCode::AppendUid(v, s)),
+                (any::<u32>(), uid_set(), uid_set())
+                    .prop_map(|(v, a, b)| ResponseCode::CopyUid(v, a, b)),
+                LazyJust::new(|| ResponseCode::UidNotSticky),
+                any::<u64>().prop_map(ResponseCode::MetadataLongEntries),
+                any::<u64>().prop_map(ResponseCode::MetadataMaxSize),
+                LazyJust::new(|| ResponseCode::MetadataTooMany),
+                LazyJust::new(|| ResponseCode::MetadataNoPrivate),
+                (
+                    cow_str("X[A-Z0-9-]{0,10}"),
+                    cow_str("\"[ -!#-\\[^-~]{0,20}")
+                )
+                    .prop_map(|(name, raw)| ResponseCode::Other {
+                        name,
+                        args: OtherArgs::Raw(raw),
+                    }),
+                (cow_str("X[A-Z0-9-]{0,10}"), vec(code_value(), 0..4)).prop_map(
+                    |(name, values)| ResponseCode::Other {
+                        name,
+                        args: OtherArgs::Values(values),
+                    }
+                ),
+            ]
+        }
+
+        fn address() -> impl Strategy<Value = Address<'static>> {
+            (
+                option::of(cow_bytes()),
+                option::of(cow_bytes()),
+                option::of(cow_bytes()),
+                option::of(cow_bytes()),
+            )
+                .prop_map(|(name, adl, mailbox, host)| Address {
+                    name,
+                    adl,
+                    mailbox,
+                    host,
+                })
+        }
+
+        fn addresses() -> impl Strategy<Value = Option<Vec<Address<'static>>>> {
+            option::of(vec(address(), 1..3))
+        }
+
+        fn envelope() -> impl Strategy<Value = Envelope<'static>> {
+            (
+                option::of(cow_bytes()),
+                option::of(cow_bytes()),
+                (addresses(), addresses(), addresses()),
+                (addresses(), addresses(), addresses()),
+                option::of(cow_bytes()),
+                option::of(cow_bytes()),
+            )
+                .prop_map(
+                    |(
+                        date,
+                        subject,
+                        (from, sender, reply_to),
+                        (to, cc, bcc),
+                        in_reply_to,
+                        message_id,
+                    )| {
+                        Envelope {
+                            date,
+                            subject,
+                            from,
+                            sender,
+                            reply_to,
+                            to,
+                            cc,
+                            bcc,
+                            in_reply_to,
+                            message_id,
+                        }
+                    },
+                )
+        }
+
+        fn body_params() -> impl Strategy<Value = BodyParams<'static>> {
+            option::of(vec((any_str(), any_str()), 1..3))
+        }
+
+        fn body_extension() -> impl Strategy<Value = BodyExtension<'static>> {
+            let leaf = prop_oneof![
+                any::<u32>().prop_map(BodyExtension::Num),
+                option::of(any_str()).prop_map(BodyExtension::Str),
+            ];
+            leaf.prop_recursive(2, 8, 3, |inner| {
+                vec(inner, 1..3).prop_map(BodyExtension::List)
+            })
+        }
+
+        fn common(ty: &'static str) -> impl Strategy<Value = BodyContentCommon<'static>> {
+            (
+                cow_str("[A-Z]{1,10}"),
+                body_params(),
+                option::of((any_str(), body_params())),
+                option::of(vec(any_str(), 1..3)),
+                option::of(any_str()),
+            )
+                .prop_map(move |(subtype, params, disposition, language, location)| {
+                    BodyContentCommon {
+                        ty: ContentType {
+                            ty: Cow::Borrowed(ty),
+                            subtype,
+                            params,
+                        },
+                        disposition: disposition
+                            .map(|(ty, params)| ContentDisposition { ty, params }),
+                        language,
+                        location,
+                    }
+                })
+        }
+
+        fn single_part() -> impl Strategy<Value = BodyContentSinglePart<'static>> {
+            (
+                option::of(any_str()),
+                option::of(any_str()),
+                option::of(any_str()),
+                prop_oneof![
+                    LazyJust::new(|| ContentEncoding::SevenBit),
+                    LazyJust::new(|| ContentEncoding::EightBit),
+                    LazyJust::new(|| ContentEncoding::Binary),
+                    LazyJust::new(|| ContentEncoding::Base64),
+                    LazyJust::new(|| ContentEncoding::QuotedPrintable),
+                    cow_str("X-[A-Z]{1,8}").prop_map(ContentEncoding::Other),
+                ],
+                any::<u32>(),
+            )
+                .prop_map(|(id, md5, description, transfer_encoding, octets)| {
+                    BodyContentSinglePart {
+                        id,
+                        md5,
+                        description,
+                        transfer_encoding,
+                        octets,
+                    }
+                })
+        }
+
+        fn body_structure() -> impl Strategy<Value = BodyStructure<'static>> {
+            let leaf = prop_oneof![
+                (
+                    common("APPLICATION"),
+                    single_part(),
+                    option::of(body_extension())
+                )
+                    .prop_map(|(common, other, extension)| BodyStructure::Basic {
+                        common,
+                        other,
+                        extension,
+                    }),
+                (
+                    common("TEXT"),
+                    single_part(),
+                    any::<u32>(),
+                    option::of(body_extension())
+                )
+                    .prop_map(|(common, other, lines, extension)| {
+                        BodyStructure::Text {
+                            common,
+                            other,
+                            lines,
+                            extension,
+                        }
+                    }),
+            ];
+            leaf.prop_recursive(2, 6, 3, |inner| {
+                prop_oneof![
+                    (
+                        common("MULTIPART"),
+                        vec(inner.clone(), 1..3),
+                        option::of(body_extension())
+                    )
+                        .prop_map(|(common, bodies, extension)| {
+                            BodyStructure::Multipart {
+                                common,
+                                bodies,
+                                extension,
+                            }
+                        }),
+                    (
+                        common("MESSAGE"),
+                        single_part(),
+                        envelope(),
+                        inner,
+                        any::<u32>(),
+                        option::of(body_extension())
+                    )
+                        .prop_map(
+                            |(mut common, other, envelope, body, lines, extension)| {
+                                common.ty.subtype = Cow::Borrowed("RFC822");
+                                BodyStructure::Message {
+                                    common,
+                                    other,
+                                    envelope,
+                                    body: Box::new(body),
+                                    lines,
+                                    extension,
+                                }
+                            }
+                        ),
+                ]
+            })
+        }
+
+        fn section_path() -> impl Strategy<Value = Option<SectionPath>> {
+            let text = prop_oneof![
+                LazyJust::new(|| MessageSection::Header),
+                LazyJust::new(|| MessageSection::Mime),
+                LazyJust::new(|| MessageSection::Text),
+            ];
+            option::of(prop_oneof![
+                LazyJust::new(|| SectionPath::Full(MessageSection::Header)),
+                LazyJust::new(|| SectionPath::Full(MessageSection::Text)),
+                (vec(1..100u32, 1..4), option::of(text))
+                    .prop_map(|(part, text)| SectionPath::Part(part, text)),
+            ])
+        }
+
+        fn attribute_value() -> impl Strategy<Value = AttributeValue<'static>> {
+            prop_oneof![
+                (
+                    section_path(),
+                    option::of(any::<u32>()),
+                    option::of(cow_bytes())
+                )
+                    .prop_map(|(section, index, data)| {
+                        AttributeValue::BodySection {
+                            section,
+                            index,
+                            data,
+                        }
+                    }),
+                body_structure().prop_map(AttributeValue::BodyStructure),
+                envelope().prop_map(|e| AttributeValue::Envelope(Box::new(e))),
+                vec(flag(), 0..4).prop_map(AttributeValue::Flags),
+                cow_str("[0-9]{2}-[A-Z][a-z]{2}-[0-9]{4} [0-9:]{8} [+-][0-9]{4}")
+                    .prop_map(AttributeValue::InternalDate),
+                any::<u64>().prop_map(AttributeValue::ModSeq),
+                option::of(cow_bytes()).prop_map(AttributeValue::Rfc822),
+                option::of(cow_bytes()).prop_map(AttributeValue::Rfc822Header),
+                any::<u32>().prop_map(AttributeValue::Rfc822Size),
+                option::of(cow_bytes()).prop_map(AttributeValue::Rfc822Text),
+                any::<u32>().prop_map(AttributeValue::Uid),
+                vec(prop_oneof![flag(), any_str()], 0..3).prop_map(AttributeValue::GmailLabels),
+                any::<u64>().prop_map(AttributeValue::GmailMsgId),
+                any::<u64>().prop_map(AttributeValue::GmailThrId),
+                cow_str("[a-zA-Z0-9_-]{1,20}").prop_map(AttributeValue::EmailId),
+                option::of(cow_str("[a-zA-Z0-9_-]{1,20}")).prop_map(AttributeValue::ThreadId),
+                (cow_str("X-[A-Z]{1,10}"), generic_value())
+                    .prop_map(|(name, value)| { AttributeValue::Unknown { name, value } }),
+            ]
+        }
+
+        fn generic_value() -> impl Strategy<Value = ImapValue<'static>> {
+            let leaf = prop_oneof![
+                LazyJust::new(|| ImapValue::Nil),
+                any::<u64>().prop_map(ImapValue::Number),
+                cow_str("[A-Za-z\\\\][A-Za-z0-9.:-]{0,10}")
+                    .prop_filter("NIL is not an atom", |s| !s.eq_ignore_ascii_case("NIL"))
+                    .prop_map(ImapValue::Atom),
+                cow_bytes().prop_map(ImapValue::String),
+            ];
+            leaf.prop_recursive(3, 16, 4, |inner| vec(inner, 0..4).prop_map(ImapValue::List))
+        }
+
+        // Values that can be sent as response code arguments, without literals
+        fn code_value() -> impl Strategy<Value = ImapValue<'static>> {
+            let leaf = prop_oneof![
+                LazyJust::new(|| ImapValue::Nil),
+                any::<u64>().prop_map(ImapValue::Number),
+                cow_str("[A-Za-z\\\\][A-Za-z0-9.:-]{0,10}")
+                    .prop_filter("NIL is not an atom", |s| !s.eq_ignore_ascii_case("NIL"))
+                    .prop_map(ImapValue::Atom),
+                "[ !#-\\[^-~]{0,10}".prop_map(|s| ImapValue::String(Cow::Owned(s.into_bytes()))),
+            ];
+            leaf.prop_recursive(3, 16, 4, |inner| vec(inner, 0..4).prop_map(ImapValue::List))
+        }
+
+        fn name_attribute() -> impl Strategy<Value = NameAttribute<'static>> {
+            prop_oneof![
+                LazyJust::new(|| NameAttribute::NoInferiors),
+                LazyJust::new(|| NameAttribute::NoSelect),
+                LazyJust::new(|| NameAttribute::Marked),
+                LazyJust::new(|| NameAttribute::Unmarked),
+                LazyJust::new(|| NameAttribute::All),
+                LazyJust::new(|| NameAttribute::Archive),
+                LazyJust::new(|| NameAttribute::Drafts),
+                LazyJust::new(|| NameAttribute::Flagged),
+                LazyJust::new(|| NameAttribute::Junk),
+                LazyJust::new(|| NameAttribute::Sent),
+                LazyJust::new(|| NameAttribute::Trash),
+                cow_str("\\\\X-[a-zA-Z]{1,10}").prop_map(NameAttribute::Extension),
+            ]
+        }
+
+        fn status_attribute() -> impl Strategy<Value = StatusAttribute> {
+            prop_oneof![
+                any::<u64>().prop_map(StatusAttribute::HighestModSeq),
+                any::<u32>().prop_map(StatusAttribute::Messages),
+                any::<u32>().prop_map(StatusAttribute::Recent),
+                any::<u32>().prop_map(StatusAttribute::UidNext),
+                any::<u32>().prop_map(StatusAttribute::UidValidity),
+                any::<u32>().prop_map(StatusAttribute::Unseen),
+                any::<u64>().prop_map(StatusAttribute::DeletedStorage),
+            ]
+        }
+
+        fn metadata_entry() -> impl Strategy<Value = String> {
+            "/(private|shared)(/comment|/vendor/[a-z]{1,8})(/[a-z]{1,8}){0,2}"
+        }
+
+        fn mailbox_datum() -> impl Strategy<Value = MailboxDatum<'static>> {
+            prop_oneof![
+                any::<u32>().prop_map(MailboxDatum::Exists),
+                vec(flag(), 0..4).prop_map(MailboxDatum::Flags),
+                (
+                    vec(name_attribute(), 0..3),
+                    option::of(prop_oneof![cow_str("/"), cow_str("[.]")]),
+                    mailbox()
+                )
+                    .prop_map(|(name_attributes, delimiter, name)| {
+                        MailboxDatum::List {
+                            name_attributes,
+                            delimiter,
+                            name,
+                        }
+                    }),
+                vec(any::<u32>(), 0..5).prop_map(MailboxDatum::Search),
+                vec(any::<u32>(), 0..5).prop_map(MailboxDatum::Sort),
+                (mailbox(), vec(status_attribute(), 0..4))
+                    .prop_map(|(mailbox, status)| MailboxDatum::Status { mailbox, status }),
+                any::<u32>().prop_map(MailboxDatum::Recent),
+                (
+                    mailbox(),
+                    vec((metadata_entry(), option::of(cow_bytes())), 1..3)
+                )
+                    .prop_map(|(mailbox, values)| {
+                        MailboxDatum::MetadataSolicited {
+                            mailbox,
+                            values: values
+                                .into_iter()
+                                .map(|(entry, value)| Metadata {
+                                    entry: Cow::Owned(entry),
+                                    value,
+                                })
+                                .collect(),
+                        }
+                    }),
+                (mailbox(), vec(metadata_entry().prop_map(Cow::Owned), 1..3)).prop_map(
+                    |(mailbox, values)| { MailboxDatum::MetadataUnsolicited { mailbox, values } }
+                ),
+                vec(prop_oneof![flag(), any_str()], 0..3).prop_map(MailboxDatum::GmailLabels),
+                any::<u64>().prop_map(MailboxDatum::GmailMsgId),
+                any::<u64>().prop_map(MailboxDatum::GmailThrId),
+            ]
+        }
+
+        fn acl_rights() -> impl Strategy<Value = Vec<AclRight>> {
+            "[a-z0-9]{0,6}".prop_map(|s| s.chars().map(AclRight::from).collect())
+        }
+
+        fn response() -> impl Strategy<Value = Response<'static>> {
+            prop_oneof![
+                vec(cow_str("X-[A-Z0-9]{1,8}"), 0..3).prop_map(|caps| {
+                    let mut caps = caps.into_iter().map(Capability::Atom).collect::<Vec<_>>();
+                    caps.insert(0, Capability::Imap4rev1);
+                    caps.push(Capability::Auth(Cow::Borrowed("PLAIN")));
+                    Response::Capabilities(caps)
+                }),
+                (option::of(response_code()), text())
+                    .prop_map(|(code, information)| Response::Continue { code, information }),
+                (
+                    "[a-zA-Z0-9]{1,8}",
+                    status(),
+                    option::of(response_code()),
+                    text()
+                )
+                    .prop_map(|(tag, status, code, information)| Response::Done {
+                        tag: RequestId(tag),
+                        status,
+                        code,
+                        information,
+                    }),
+                (status(), option::of(response_code()), text()).prop_map(
+                    |(status, code, information)| Response::Data {
+                        status,
+                        code,
+                        information,
+                    }
+                ),
+                any::<u32>().prop_map(Response::Expunge),
+                (any::<bool>(), vec((any::<u32>(), 0..10u32), 1..4)).prop_map(|(earlier, uids)| {
+                    Response::Vanished {
+                        earlier,
+                        uids: uids
+                            .into_iter()
+                            .map(|(start, len)| start..=start.saturating_add(len))
+                            .collect(),
+                    }
+                }),
+                (any::<u32>(), vec(attribute_value(), 1..4))
+                    .prop_map(|(seq, attrs)| Response::Fetch(seq, attrs)),
+                mailbox_datum().prop_map(Response::MailboxData),
+                (
+                    any_str(),
+                    vec(
+                        (
+                            prop_oneof![
+                                LazyJust::new(|| QuotaResourceName::Storage),
+                                LazyJust::new(|| QuotaResourceName::Message),
+                                LazyJust::new(|| QuotaResourceName::Mailbox),
+                                LazyJust::new(|| QuotaResourceName::AnnotationStorage),
+                                cow_str("X-[A-Z]{1,8}").prop_map(QuotaResourceName::Atom),
+                            ],
+                            any::<u64>(),
+                            any::<u64>()
+                        ),
+                        0..3
+                    )
+                )
+                    .prop_map(|(root_name, resources)| Response::Quota(Quota {
+                        root_name,
+                        resources: resources
+                            .into_iter()
+                            .map(|(name, usage, limit)| QuotaResource { name, usage, limit })
+                            .collect(),
+                    })),
+                (mailbox(), vec(any_str(), 0..3)).prop_map(|(mailbox_name, quota_root_names)| {
+                    Response::QuotaRoot(QuotaRoot {
+                        mailbox_name,
+                        quota_root_names,
+                    })
+                }),
+                option::of(vec((any_str(), any_str()), 1..4)).prop_map(Response::Id),
+                (mailbox(), vec((any_str(), acl_rights()), 0..3)).prop_map(|(mailbox, acls)| {
+                    Response::Acl(Acl {
+                        mailbox,
+                        acls: acls
+                            .into_iter()
+                            .map(|(identifier, rights)| AclEntry { identifier, rights })
+                            .collect(),
+                    })
+                }),
+                (mailbox(), any_str(), acl_rights(), acl_rights()).prop_map(
+                    |(mailbox, identifier, required, optional)| {
+                        Response::ListRights(ListRights {
+                            mailbox,
+                            identifier,
+                            required,
+                            optional,
+                        })
+                    }
+                ),
+                (mailbox(), acl_rights())
+                    .prop_map(|(mailbox, rights)| Response::MyRights(MyRights { mailbox, rights })),
+                (
+                    vec(namespace(), 0..3),
+                    vec(namespace(), 0..3),
+                    vec(namespace(), 0..3)
+                )
+                    .prop_map(|(personal, other_users, shared)| {
+                        Response::Namespace(Namespaces {
+                            personal,
+                            other_users,
+                            shared,
+                        })
+                    }),
+            ]
+        }
+
+        fn namespace() -> impl Strategy<Value = Namespace<'static>> {
+            (
+                any_str(),
+                option::of(cow_str("[/.\\\\]")),
+                vec((any_str(), vec(any_str(), 1..3)), 0..2),
+            )
+                .prop_map(|(prefix, delimiter, extensions)| Namespace {
+                    prefix,
+                    delimiter,
+                    extensions: extensions
+                        .into_iter()
+                        .map(|(name, values)| NamespaceExtension { name, values })
+                        .collect(),
+                })
+        }
+
+        proptest! {
+            #[test]
+            fn encode_then_parse(rsp in response()) {
+                let bytes = encode(&rsp);
+                let (rest, parsed) = Response::from_bytes(&bytes).unwrap();
+                prop_assert!(rest.is_empty());
+                prop_assert_eq!(parsed, rsp);
+            }
+        }
+    }
+}
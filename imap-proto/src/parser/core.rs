@@ -11,6 +11,8 @@ use nom::{
 use std::borrow::Cow;
 use std::str::{from_utf8, FromStr};
 
+use crate::types::ImapValue;
+
 // ----- number -----
 
 // number          = 1*DIGIT
@@ -93,6 +95,15 @@ pub fn quoted_utf8(i: &[u8]) -> IResult<&[u8], Cow<'_, str>> {
     map(quoted, lossy_str)(i)
 }
 
+// quoted bytes as utf8 with the escaping removed, for the few places where
+// callers can't be expected to unescape (like a single delimiter character)
+pub(crate) fn quoted_unescaped_utf8(i: &[u8]) -> IResult<&[u8], Cow<'_, str>> {
+    map(quoted, |s| match unescape(s) {
+        Cow::Borrowed(s) => lossy_str(s),
+        Cow::Owned(s) => Cow::Owned(String::from_utf8_lossy(&s).into_owned()),
+    })(i)
+}
+
 // quoted-specials = DQUOTE / "\"
 pub fn is_quoted_specials(c: u8) -> bool {
     c == b'"' || c == b'\\'
@@ -246,6 +257,81 @@ where
     )
 }
 
+// ----- generic values -----
+
+// Maximum nesting of lists in `imap_value()`, to bound recursion
+const MAX_VALUE_DEPTH: usize = 32;
+
+/// Characters allowed in a bare token in `imap_value()`
+///
+/// These are the ATOM-CHARs, plus the backslash and list wildcards so that
+/// flags (`\\Seen`) and mailbox patterns are read as a single atom. Like
+/// ATOM-CHAR, this excludes `]`, so values can be used inside response codes.
+pub fn is_value_char(c: u8) -> bool {
+    is_atom_char(c) || c == b'\\' || is_list_wildcards(c)
+}
+
+/// Parse a generic value: `NIL`, a number, a bare atom, a string or a
+/// parenthesized list of values
+///
+/// This is useful to handle extensions that do not have a dedicated parser,
+/// since most of them build their data from these elements. Quoted strings are
+/// unescaped, literals are returned as-is. Lists can be nested up to 32 levels
+/// deep; deeper nesting fails to parse.
+pub fn imap_value(i: &[u8]) -> IResult<&[u8], ImapValue<'_>> {
+    imap_value_nested(i, 0)
+}
+
+fn imap_value_nested(i: &[u8], depth: usize) -> IResult<&[u8], ImapValue<'_>> {
+    if depth > MAX_VALUE_DEPTH {
+        return Err(nom::Err::Failure(nom::error::Error::new(
+            i,
+            nom::error::ErrorKind::TooLarge,
+        )));
+    }
+    alt((
+        map(
+            parenthesized_list(move |i| imap_value_nested(i, depth + 1)),
+            ImapValue::List,
+        ),
+        map(quoted, |s| ImapValue::String(unescape(s))),
+        map(literal, |s| ImapValue::String(Cow::Borrowed(s))),
+        map(map_res(take_while1(is_value_char), from_utf8), value_token),
+    ))(i)
+}
+
+fn value_token(token: &str) -> ImapValue<'_> {
+    if token.eq_ignore_ascii_case("NIL") {
+        return ImapValue::Nil;
+    }
+    match token.bytes().all(|b| b.is_ascii_digit()) {
+        true => match token.parse() {
+            Ok(n) => ImapValue::Number(n),
+            Err(_) => ImapValue::Atom(Cow::Borrowed(token)),
+        },
+        false => ImapValue::Atom(Cow::Borrowed(token)),
+    }
+}
+
+// Remove the escaping from the contents of a quoted string
+pub(crate) fn unescape(s: &[u8]) -> Cow<'_, [u8]> {
+    if !s.contains(&b'\\') {
+        return Cow::Borrowed(s);
+    }
+    let mut out = Vec::with_capacity(s.len());
+    let mut escaped = false;
+    for &b in s {
+        match (escaped, b) {
+            (false, b'\\') => escaped = true,
+            _ => {
+                out.push(b);
+                escaped = false;
+            }
+        }
+    }
+    Cow::Owned(out)
+}
+
 pub fn opt_opt<'a, F, O, E>(mut f: F) -> impl FnMut(&'a [u8]) -> IResult<&'a [u8], Option<O>, E>
 where
     F: FnMut(&'a [u8]) -> IResult<&'a [u8], Option<O>, E>,
@@ -362,4 +448,32 @@ mod tests {
             rsp => panic!("Unexpected response {rsp:?}"),
         }
     }
+
+    #[test]
+    fn test_imap_value() {
+        let (rest, value) =
+            imap_value(b"(\\Seen 4294967296 nil \"a\\\\b\" {2}\r\nxy ()) ").unwrap();
+        assert_eq!(rest, b" ");
+        assert_eq!(
+            value,
+            ImapValue::List(vec![
+                ImapValue::Atom(Cow::Borrowed("\\Seen")),
+                ImapValue::Number(4294967296),
+                ImapValue::Nil,
+                ImapValue::String(Cow::Owned(b"a\\b".to_vec())),
+                ImapValue::String(Cow::Borrowed(b"xy")),
+                ImapValue::List(vec![]),
+            ])
+        );
+
+        // Numbers too large for a u64 are kept as atoms
+        assert_matches!(
+            imap_value(b"99999999999999999999]"),
+            Ok((_, ImapValue::Atom(Cow::Borrowed("99999999999999999999"))))
+        );
+
+        assert_matches!(imap_value(b"(a (b"), Err(nom::Err::Incomplete(_)));
+        let deep = [&[b'('; 40][..], &[b')'; 40][..]].concat();
+        assert_matches!(imap_value(&deep), Err(nom::Err::Failure(_)));
+    }
 }
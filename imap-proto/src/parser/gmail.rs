@@ -8,13 +8,13 @@ use nom::IResult;
 
 use crate::{AttributeValue, MailboxDatum};
 
-use super::core::{number_64, parenthesized_list, quoted_utf8};
+use super::core::{number_64, parenthesized_list, string_utf8};
 use super::rfc3501::flag;
 
 pub(crate) fn gmail_label_list(i: &[u8]) -> IResult<&[u8], Vec<Cow<'_, str>>> {
     preceded(
         tag_no_case("X-GM-LABELS "),
-        parenthesized_list(alt((map(flag, Cow::Borrowed), quoted_utf8))),
+        parenthesized_list(alt((map(flag, Cow::Borrowed), string_utf8))),
     )(i)
 }
 
@@ -74,6 +74,17 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_gmail_labels_literal() {
+        let env = b"X-GM-LABELS (\\Inbox {8}\r\nsay \"hi\") ";
+        match super::msg_att_gmail_labels(env) {
+            Ok((_, AttributeValue::GmailLabels(labels))) => {
+                assert_eq!(["\\Inbox", "say \"hi\""].to_vec(), labels);
+            }
+            rsp => panic!("unexpected response {rsp:?}"),
+        }
+    }
+
     #[test]
     fn test_gmail_msgid() {
         let env = br#"X-GM-MSGID 1278455344230334865 "#;
@@ -1,4 +1,4 @@
-use crate::types::Response;
+use crate::types::{Command, Response};
 use nom::{branch::alt, IResult};
 
 pub mod core;
@@ -6,6 +6,7 @@ pub mod core;
 pub mod bodystructure;
 pub mod gmail;
 pub mod rfc2087;
+pub mod rfc2342;
 pub mod rfc2971;
 pub mod rfc3501;
 pub mod rfc4314;
@@ -27,4 +28,9 @@ pub fn parse_response(msg: &[u8]) -> ParseResult<'_> {
     ))(msg)
 }
 
+/// Parse a single client command, as received by a server.
+pub fn parse_command(msg: &[u8]) -> IResult<&[u8], Command<'_>> {
+    rfc3501::command::command(msg)
+}
+
 pub type ParseResult<'a> = IResult<&'a [u8], Response<'a>>;
@@ -63,6 +63,10 @@ pub(crate) fn quota_resource_name(i: &[u8]) -> IResult<&[u8], QuotaResourceName<
     alt((
         map(tag_no_case("STORAGE"), |_| QuotaResourceName::Storage),
         map(tag_no_case("MESSAGE"), |_| QuotaResourceName::Message),
+        map(tag_no_case("MAILBOX"), |_| QuotaResourceName::Mailbox),
+        map(tag_no_case("ANNOTATION-STORAGE"), |_| {
+            QuotaResourceName::AnnotationStorage
+        }),
         map(astring_utf8, QuotaResourceName::Atom),
     ))(i)
 }
@@ -190,6 +194,27 @@ mod tests {
             }
         );
 
+        assert_matches!(
+            quota_list(b"(MAILBOX 12 100 ANNOTATION-STORAGE 3 64)"),
+            Ok((_, r)) => {
+                assert_eq!(
+                    r,
+                    vec![
+                        QuotaResource {
+                            name: QuotaResourceName::Mailbox,
+                            usage: 12,
+                            limit: 100
+                        },
+                        QuotaResource {
+                            name: QuotaResourceName::AnnotationStorage,
+                            usage: 3,
+                            limit: 64
+                        }
+                    ]
+                );
+            }
+        );
+
         assert_matches!(
             quota_list(b"(DAILY 55 200)"),
             Ok((_, r)) => {
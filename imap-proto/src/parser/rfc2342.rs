@@ -0,0 +1,158 @@
+//!
+//! https://tools.ietf.org/html/rfc2342
+//!
+//! IMAP4 Namespace
+//!
+
+use nom::{
+    branch::alt,
+    bytes::streaming::{tag, tag_no_case},
+    character::streaming::{space0, space1},
+    combinator::map,
+    multi::{many0, many1, separated_list1},
+    sequence::{delimited, preceded, terminated, tuple},
+    IResult,
+};
+
+use crate::parser::core::{nil, quoted_unescaped_utf8, string_utf8};
+use crate::types::*;
+
+/// ```ignore
+/// Namespace_Response ::= "NAMESPACE" SP Namespace SP Namespace SP Namespace
+/// ```
+pub(crate) fn namespace(i: &[u8]) -> IResult<&[u8], Response<'_>> {
+    let (rest, (_, personal, _, other_users, _, shared)) = tuple((
+        tag_no_case("NAMESPACE "),
+        namespace_list,
+        space1,
+        namespace_list,
+        space1,
+        namespace_list,
+    ))(i)?;
+
+    Ok((
+        rest,
+        Response::Namespace(Namespaces {
+            personal,
+            other_users,
+            shared,
+        }),
+    ))
+}
+
+/// ```ignore
+/// Namespace ::= nil / "(" 1*( "(" string SP (<"> QUOTED_CHAR <"> / nil)
+///               *(Namespace_Response_Extension) ")" ) ")"
+/// ```
+///
+/// Some servers separate the namespace descriptions with spaces.
+fn namespace_list(i: &[u8]) -> IResult<&[u8], Vec<Namespace<'_>>> {
+    alt((
+        delimited(
+            tag("("),
+            many1(terminated(namespace_description, space0)),
+            tag(")"),
+        ),
+        map(nil, |_| Vec::new()),
+    ))(i)
+}
+
+fn namespace_description(i: &[u8]) -> IResult<&[u8], Namespace<'_>> {
+    let (rest, (_, prefix, _, delimiter, extensions, _)) = tuple((
+        tag("("),
+        string_utf8,
+        space1,
+        alt((map(quoted_unescaped_utf8, Some), map(nil, |_| None))),
+        many0(namespace_extension),
+        tag(")"),
+    ))(i)?;
+
+    Ok((
+        rest,
+        Namespace {
+            prefix,
+            delimiter,
+            extensions,
+        },
+    ))
+}
+
+/// ```ignore
+/// Namespace_Response_Extension ::= SP string SP "(" string *(SP string) ")"
+/// ```
+fn namespace_extension(i: &[u8]) -> IResult<&[u8], NamespaceExtension<'_>> {
+    let (rest, (name, values)) = tuple((
+        preceded(space1, string_utf8),
+        preceded(
+            space1,
+            delimited(tag("("), separated_list1(space1, string_utf8), tag(")")),
+        ),
+    ))(i)?;
+
+    Ok((rest, NamespaceExtension { name, values }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::borrow::Cow;
+
+    #[test]
+    fn test_namespace() {
+        let input = b"* NAMESPACE ((\"\" \"/\")) NIL ((\"Shared/\" \"/\"))\r\n";
+        let (rest, rsp) = crate::parser::parse_response(input).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(
+            rsp,
+            Response::Namespace(Namespaces {
+                personal: vec![Namespace {
+                    prefix: Cow::Borrowed(""),
+                    delimiter: Some(Cow::Borrowed("/")),
+                    extensions: vec![],
+                }],
+                other_users: vec![],
+                shared: vec![Namespace {
+                    prefix: Cow::Borrowed("Shared/"),
+                    delimiter: Some(Cow::Borrowed("/")),
+                    extensions: vec![],
+                }],
+            })
+        );
+    }
+
+    #[test]
+    fn test_namespace_extensions() {
+        let (_, rsp) = namespace(
+            b"NAMESPACE ((\"\" \"/\" \"X-PARAM\" (\"FLAG1\" \"FLAG2\"))) \
+              ((\"~\" NIL) (\"#users.\" \".\")) NIL\r\n",
+        )
+        .unwrap();
+        match rsp {
+            Response::Namespace(namespaces) => {
+                assert_eq!(
+                    namespaces.personal[0].extensions,
+                    [NamespaceExtension {
+                        name: Cow::Borrowed("X-PARAM"),
+                        values: vec![Cow::Borrowed("FLAG1"), Cow::Borrowed("FLAG2")],
+                    }]
+                );
+                assert_eq!(namespaces.other_users.len(), 2);
+                assert_eq!(namespaces.other_users[0].delimiter, None);
+                assert_eq!(namespaces.other_users[1].prefix, "#users.");
+                assert!(namespaces.shared.is_empty());
+            }
+            rsp => panic!("unexpected response {rsp:?}"),
+        }
+    }
+
+    #[test]
+    fn test_namespace_escaped_delimiter() {
+        let (_, rsp) = namespace(b"NAMESPACE ((\"\" \"\\\\\")) NIL NIL\r\n").unwrap();
+        match rsp {
+            Response::Namespace(namespaces) => {
+                assert_eq!(namespaces.personal[0].delimiter.as_deref(), Some("\\"));
+            }
+            rsp => panic!("unexpected response {rsp:?}"),
+        }
+    }
+}
@@ -5,7 +5,7 @@
 //! The IMAP4 ID extension
 //!
 
-use std::{borrow::Cow, collections::HashMap};
+use std::borrow::Cow;
 
 use nom::{
     branch::alt,
@@ -33,7 +33,8 @@ fn id_param(i: &[u8]) -> IResult<&[u8], (Cow<'_, str>, Option<Cow<'_, str>>)> {
 // The non-nil case of id parameter list.
 // Format: "(" #(string SPACE nstring) ")"
 // [RFC2971 - Formal Syntax](https://tools.ietf.org/html/rfc2971#section-4)
-fn id_param_list_not_nil(i: &[u8]) -> IResult<&[u8], HashMap<Cow<'_, str>, Cow<'_, str>>> {
+#[allow(clippy::type_complexity)]
+fn id_param_list_not_nil(i: &[u8]) -> IResult<&[u8], Vec<(Cow<'_, str>, Cow<'_, str>)>> {
     map(
         tuple((
             char('('),
@@ -51,7 +52,7 @@ fn id_param_list_not_nil(i: &[u8]) -> IResult<&[u8], HashMap<Cow<'_, str>, Cow<'
                 .into_iter()
                 .filter(|(_k, v)| v.is_some())
                 .map(|(k, v)| (k, v.unwrap()))
-                .collect()
+                .collect::<Vec<_>>()
         },
     )(i)
 }
@@ -60,7 +61,7 @@ fn id_param_list_not_nil(i: &[u8]) -> IResult<&[u8], HashMap<Cow<'_, str>, Cow<'
 // id_params_list ::= "(" #(string SPACE nstring) ")" / nil
 // [RFC2971 - Formal Syntax](https://tools.ietf.org/html/rfc2971#section-4)
 #[allow(clippy::type_complexity)]
-fn id_param_list(i: &[u8]) -> IResult<&[u8], Option<HashMap<Cow<'_, str>, Cow<'_, str>>>> {
+fn id_param_list(i: &[u8]) -> IResult<&[u8], Option<Vec<(Cow<'_, str>, Cow<'_, str>)>>> {
     alt((map(id_param_list_not_nil, Some), map(nil, |_| None)))(i)
 }
 
@@ -114,7 +115,7 @@ mod tests {
                         ("support-url", "mailto:cyrus-bugs+@andrew.cmu.edu"),
                     ].into_iter()
                     .map(|(k, v)| (Cow::Borrowed(k), Cow::Borrowed(v)))
-                    .collect()
+                    .collect::<Vec<_>>()
                 );
             }
         );
@@ -135,7 +136,7 @@ mod tests {
                         ("support-url", "mailto:cyrus-bugs+@andrew.cmu.edu"),
                     ].into_iter()
                     .map(|(k, v)| (Cow::Borrowed(k), Cow::Borrowed(v)))
-                    .collect()
+                    .collect::<Vec<_>>()
                 );
             }
         );
@@ -163,7 +164,7 @@ mod tests {
                         ("support-url", "mailto:cyrus-bugs+@andrew.cmu.edu"),
                     ].into_iter()
                     .map(|(k, v)| (Cow::Borrowed(k), Cow::Borrowed(v)))
-                    .collect()
+                    .collect::<Vec<_>>()
                 );
             }
         );
@@ -181,7 +182,7 @@ mod tests {
                         ("support-url", "mailto:cyrus-bugs+@andrew.cmu.edu"),
                     ].into_iter()
                     .map(|(k, v)| (Cow::Borrowed(k), Cow::Borrowed(v)))
-                    .collect()
+                    .collect::<Vec<_>>()
                 );
             }
         );
@@ -206,7 +207,7 @@ mod tests {
                         ("release-url", "http://archiveopteryx.org/3.2.0"),
                     ].into_iter()
                     .map(|(k, v)| (Cow::Borrowed(k), Cow::Borrowed(v)))
-                    .collect()
+                    .collect::<Vec<_>>()
                 );
             }
         );
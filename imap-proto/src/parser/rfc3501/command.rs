@@ -0,0 +1,954 @@
+//!
+//! https://tools.ietf.org/html/rfc3501#section-9
+//!
+//! Client command syntax, for use by servers and proxies
+//!
+
+use std::borrow::Cow;
+use std::str::from_utf8;
+
+use nom::{
+    branch::alt,
+    bytes::streaming::{escaped, tag, tag_no_case, take, take_while1},
+    character::streaming::{char, one_of},
+    combinator::{map, map_res, opt, value},
+    multi::{many1, separated_list1},
+    sequence::{delimited, pair, preceded, terminated, tuple},
+    IResult,
+};
+
+use crate::{
+    parser::{
+        core::{
+            is_astring_char, is_atom_char, is_quoted_specials, is_text_char, number, number_64,
+            paren_delimited, parenthesized_list,
+        },
+        rfc3501::{flag, imap_tag},
+    },
+    types::*,
+};
+
+// quoted = DQUOTE *QUOTED-CHAR DQUOTE
+//
+// Unlike `core::quoted`, this returns the unescaped contents: a server needs
+// the actual mailbox name or password rather than its wire representation.
+fn quoted(i: &[u8]) -> IResult<&[u8], Cow<'_, [u8]>> {
+    map(
+        delimited(
+            char('"'),
+            opt(escaped(
+                take_while1(|byte| is_text_char(byte) && !is_quoted_specials(byte)),
+                '\\',
+                one_of("\\\""),
+            )),
+            char('"'),
+        ),
+        |raw: Option<&[u8]>| unescape(raw.unwrap_or_default()),
+    )(i)
+}
+
+fn unescape(raw: &[u8]) -> Cow<'_, [u8]> {
+    if !raw.contains(&b'\\') {
+        return Cow::Borrowed(raw);
+    }
+
+    let mut out = Vec::with_capacity(raw.len());
+    let mut escaped = false;
+    for &b in raw {
+        if b == b'\\' && !escaped {
+            escaped = true;
+            continue;
+        }
+        escaped = false;
+        out.push(b);
+    }
+    Cow::Owned(out)
+}
+
+// literal = "{" number ["+"] "}" CRLF *CHAR8
+//
+// The "+" marks a non-synchronizing literal (RFC 7888, LITERAL+).
+fn literal(i: &[u8]) -> IResult<&[u8], &[u8]> {
+    let (i, (_, count, _, _, _)) =
+        tuple((tag("{"), number, opt(tag("+")), tag("}"), tag("\r\n")))(i)?;
+    take(count)(i)
+}
+
+// string = quoted / literal
+fn string(i: &[u8]) -> IResult<&[u8], Cow<'_, [u8]>> {
+    alt((quoted, map(literal, Cow::Borrowed)))(i)
+}
+
+fn string_utf8(i: &[u8]) -> IResult<&[u8], Cow<'_, str>> {
+    map(string, lossy)(i)
+}
+
+// astring = 1*ASTRING-CHAR / string
+fn astring(i: &[u8]) -> IResult<&[u8], Cow<'_, str>> {
+    alt((
+        map(take_while1(is_astring_char), |s| lossy(Cow::Borrowed(s))),
+        string_utf8,
+    ))(i)
+}
+
+fn lossy(bytes: Cow<'_, [u8]>) -> Cow<'_, str> {
+    match bytes {
+        Cow::Borrowed(b) => String::from_utf8_lossy(b),
+        Cow::Owned(b) => match String::from_utf8(b) {
+            Ok(s) => Cow::Owned(s),
+            Err(e) => Cow::Owned(String::from_utf8_lossy(e.as_bytes()).into_owned()),
+        },
+    }
+}
+
+fn atom(i: &[u8]) -> IResult<&[u8], Cow<'_, str>> {
+    map(map_res(take_while1(is_atom_char), from_utf8), Cow::Borrowed)(i)
+}
+
+// mailbox = "INBOX" / astring
+fn mailbox(i: &[u8]) -> IResult<&[u8], Cow<'_, str>> {
+    map(astring, |s| {
+        if s.eq_ignore_ascii_case("INBOX") {
+            Cow::Borrowed("INBOX")
+        } else {
+            s
+        }
+    })(i)
+}
+
+// list-mailbox = 1*list-char / string
+// list-char    = ATOM-CHAR / list-wildcards / resp-specials
+fn list_mailbox(i: &[u8]) -> IResult<&[u8], Cow<'_, str>> {
+    alt((
+        map(
+            map_res(
+                take_while1(|c| is_astring_char(c) || c == b'%' || c == b'*'),
+                from_utf8,
+            ),
+            Cow::Borrowed,
+        ),
+        string_utf8,
+    ))(i)
+}
+
+// seq-number = nz-number / "*"
+fn seq_number(i: &[u8]) -> IResult<&[u8], SequenceNumber> {
+    alt((
+        map(number, SequenceNumber::Number),
+        value(SequenceNumber::Largest, tag("*")),
+    ))(i)
+}
+
+// sequence-set = (seq-number / seq-range) *("," sequence-set)
+pub(crate) fn sequence_set(i: &[u8]) -> IResult<&[u8], Vec<SequenceItem>> {
+    separated_list1(
+        tag(","),
+        map(
+            pair(seq_number, opt(preceded(tag(":"), seq_number))),
+            |(start, end)| match end {
+                Some(end) => SequenceItem::Range(start, end),
+                None => SequenceItem::Single(start),
+            },
+        ),
+    )(i)
+}
+
+fn flag_list(i: &[u8]) -> IResult<&[u8], Vec<Cow<'_, str>>> {
+    parenthesized_list(map(flag, Cow::Borrowed))(i)
+}
+
+// date-time = DQUOTE date-day-fixed "-" date-month "-" date-year SP time SP zone DQUOTE
+fn date_time(i: &[u8]) -> IResult<&[u8], Cow<'_, str>> {
+    map(quoted, lossy)(i)
+}
+
+// date = date-text / DQUOTE date-text DQUOTE
+fn date(i: &[u8]) -> IResult<&[u8], Cow<'_, str>> {
+    alt((
+        map(quoted, lossy),
+        map(
+            map_res(
+                take_while1(|c: u8| c.is_ascii_alphanumeric() || c == b'-'),
+                from_utf8,
+            ),
+            Cow::Borrowed,
+        ),
+    ))(i)
+}
+
+fn command_any(i: &[u8]) -> IResult<&[u8], CommandBody<'_>> {
+    alt((
+        value(CommandBody::Capability, tag_no_case("CAPABILITY")),
+        value(CommandBody::Logout, tag_no_case("LOGOUT")),
+        value(CommandBody::Noop, tag_no_case("NOOP")),
+    ))(i)
+}
+
+fn authenticate(i: &[u8]) -> IResult<&[u8], CommandBody<'_>> {
+    map(
+        tuple((
+            tag_no_case("AUTHENTICATE "),
+            atom,
+            opt(preceded(
+                tag(" "),
+                map(
+                    map_res(
+                        take_while1(|c: u8| c.is_ascii_alphanumeric() || b"+/=".contains(&c)),
+                        from_utf8,
+                    ),
+                    Cow::Borrowed,
+                ),
+            )),
+        )),
+        |(_, mechanism, initial_response)| CommandBody::Authenticate {
+            mechanism,
+            initial_response,
+        },
+    )(i)
+}
+
+fn login(i: &[u8]) -> IResult<&[u8], CommandBody<'_>> {
+    map(
+        tuple((tag_no_case("LOGIN "), astring, tag(" "), astring)),
+        |(_, username, _, password)| CommandBody::Login { username, password },
+    )(i)
+}
+
+fn command_nonauth(i: &[u8]) -> IResult<&[u8], CommandBody<'_>> {
+    alt((
+        login,
+        authenticate,
+        value(CommandBody::StartTls, tag_no_case("STARTTLS")),
+    ))(i)
+}
+
+fn select_params(i: &[u8]) -> IResult<&[u8], bool> {
+    map(
+        opt(preceded(
+            tag(" "),
+            paren_delimited(tag_no_case("CONDSTORE")),
+        )),
+        |p| p.is_some(),
+    )(i)
+}
+
+fn select(i: &[u8]) -> IResult<&[u8], CommandBody<'_>> {
+    map(
+        tuple((tag_no_case("SELECT "), mailbox, select_params)),
+        |(_, mailbox, cond_store)| CommandBody::Select {
+            mailbox,
+            cond_store,
+        },
+    )(i)
+}
+
+fn examine(i: &[u8]) -> IResult<&[u8], CommandBody<'_>> {
+    map(
+        tuple((tag_no_case("EXAMINE "), mailbox, select_params)),
+        |(_, mailbox, cond_store)| CommandBody::Examine {
+            mailbox,
+            cond_store,
+        },
+    )(i)
+}
+
+fn create(i: &[u8]) -> IResult<&[u8], CommandBody<'_>> {
+    map(preceded(tag_no_case("CREATE "), mailbox), |mailbox| {
+        CommandBody::Create { mailbox }
+    })(i)
+}
+
+fn delete(i: &[u8]) -> IResult<&[u8], CommandBody<'_>> {
+    map(preceded(tag_no_case("DELETE "), mailbox), |mailbox| {
+        CommandBody::Delete { mailbox }
+    })(i)
+}
+
+fn rename(i: &[u8]) -> IResult<&[u8], CommandBody<'_>> {
+    map(
+        tuple((tag_no_case("RENAME "), mailbox, tag(" "), mailbox)),
+        |(_, from, _, to)| CommandBody::Rename { from, to },
+    )(i)
+}
+
+fn subscribe(i: &[u8]) -> IResult<&[u8], CommandBody<'_>> {
+    map(preceded(tag_no_case("SUBSCRIBE "), mailbox), |mailbox| {
+        CommandBody::Subscribe { mailbox }
+    })(i)
+}
+
+fn unsubscribe(i: &[u8]) -> IResult<&[u8], CommandBody<'_>> {
+    map(preceded(tag_no_case("UNSUBSCRIBE "), mailbox), |mailbox| {
+        CommandBody::Unsubscribe { mailbox }
+    })(i)
+}
+
+fn list(i: &[u8]) -> IResult<&[u8], CommandBody<'_>> {
+    map(
+        tuple((tag_no_case("LIST "), mailbox, tag(" "), list_mailbox)),
+        |(_, reference, _, pattern)| CommandBody::List { reference, pattern },
+    )(i)
+}
+
+fn lsub(i: &[u8]) -> IResult<&[u8], CommandBody<'_>> {
+    map(
+        tuple((tag_no_case("LSUB "), mailbox, tag(" "), list_mailbox)),
+        |(_, reference, _, pattern)| CommandBody::Lsub { reference, pattern },
+    )(i)
+}
+
+fn status_item(i: &[u8]) -> IResult<&[u8], StatusItem> {
+    alt((
+        value(StatusItem::HighestModSeq, tag_no_case("HIGHESTMODSEQ")),
+        value(StatusItem::Messages, tag_no_case("MESSAGES")),
+        value(StatusItem::Recent, tag_no_case("RECENT")),
+        value(StatusItem::UidNext, tag_no_case("UIDNEXT")),
+        value(StatusItem::UidValidity, tag_no_case("UIDVALIDITY")),
+        value(StatusItem::Unseen, tag_no_case("UNSEEN")),
+        value(StatusItem::DeletedStorage, tag_no_case("DELETED-STORAGE")),
+    ))(i)
+}
+
+fn status(i: &[u8]) -> IResult<&[u8], CommandBody<'_>> {
+    map(
+        tuple((
+            tag_no_case("STATUS "),
+            mailbox,
+            tag(" "),
+            paren_delimited(separated_list1(tag(" "), status_item)),
+        )),
+        |(_, mailbox, _, items)| CommandBody::Status { mailbox, items },
+    )(i)
+}
+
+// append = "APPEND" SP mailbox [SP flag-list] [SP date-time] SP literal
+fn append(i: &[u8]) -> IResult<&[u8], CommandBody<'_>> {
+    map(
+        tuple((
+            tag_no_case("APPEND "),
+            mailbox,
+            opt(preceded(tag(" "), flag_list)),
+            opt(preceded(tag(" "), date_time)),
+            tag(" "),
+            literal,
+        )),
+        |(_, mailbox, flags, date, _, message)| CommandBody::Append {
+            mailbox,
+            flags: flags.unwrap_or_default(),
+            date,
+            message: Cow::Borrowed(message),
+        },
+    )(i)
+}
+
+fn command_auth(i: &[u8]) -> IResult<&[u8], CommandBody<'_>> {
+    alt((
+        append,
+        create,
+        delete,
+        examine,
+        value(CommandBody::Idle, tag_no_case("IDLE")),
+        list,
+        lsub,
+        rename,
+        select,
+        status,
+        subscribe,
+        unsubscribe,
+    ))(i)
+}
+
+fn search_key_flag(i: &[u8]) -> IResult<&[u8], SearchKey<'_>> {
+    alt((
+        value(SearchKey::All, tag_no_case("ALL")),
+        value(SearchKey::Answered, tag_no_case("ANSWERED")),
+        value(SearchKey::Deleted, tag_no_case("DELETED")),
+        value(SearchKey::Draft, tag_no_case("DRAFT")),
+        value(SearchKey::Flagged, tag_no_case("FLAGGED")),
+        value(SearchKey::New, tag_no_case("NEW")),
+        value(SearchKey::Old, tag_no_case("OLD")),
+        value(SearchKey::Recent, tag_no_case("RECENT")),
+        value(SearchKey::Seen, tag_no_case("SEEN")),
+        value(SearchKey::Unanswered, tag_no_case("UNANSWERED")),
+        value(SearchKey::Undeleted, tag_no_case("UNDELETED")),
+        value(SearchKey::Undraft, tag_no_case("UNDRAFT")),
+        value(SearchKey::Unflagged, tag_no_case("UNFLAGGED")),
+        value(SearchKey::Unseen, tag_no_case("UNSEEN")),
+    ))(i)
+}
+
+fn search_key_string(i: &[u8]) -> IResult<&[u8], SearchKey<'_>> {
+    alt((
+        map(preceded(tag_no_case("BCC "), astring), SearchKey::Bcc),
+        map(preceded(tag_no_case("BODY "), astring), SearchKey::Body),
+        map(preceded(tag_no_case("CC "), astring), SearchKey::Cc),
+        map(preceded(tag_no_case("FROM "), astring), SearchKey::From),
+        map(
+            tuple((tag_no_case("HEADER "), astring, tag(" "), astring)),
+            |(_, name, _, value)| SearchKey::Header(name, value),
+        ),
+        map(preceded(tag_no_case("KEYWORD "), atom), SearchKey::Keyword),
+        map(
+            preceded(tag_no_case("SUBJECT "), astring),
+            SearchKey::Subject,
+        ),
+        map(preceded(tag_no_case("TEXT "), astring), SearchKey::Text),
+        map(preceded(tag_no_case("TO "), astring), SearchKey::To),
+        map(
+            preceded(tag_no_case("UNKEYWORD "), atom),
+            SearchKey::Unkeyword,
+        ),
+    ))(i)
+}
+
+fn search_key_date(i: &[u8]) -> IResult<&[u8], SearchKey<'_>> {
+    alt((
+        map(preceded(tag_no_case("BEFORE "), date), SearchKey::Before),
+        map(preceded(tag_no_case("ON "), date), SearchKey::On),
+        map(
+            preceded(tag_no_case("SENTBEFORE "), date),
+            SearchKey::SentBefore,
+        ),
+        map(preceded(tag_no_case("SENTON "), date), SearchKey::SentOn),
+        map(
+            preceded(tag_no_case("SENTSINCE "), date),
+            SearchKey::SentSince,
+        ),
+        map(preceded(tag_no_case("SINCE "), date), SearchKey::Since),
+    ))(i)
+}
+
+/// Maximum nesting of `NOT`, `OR` and parenthesized search keys
+const MAX_SEARCH_DEPTH: usize = 32;
+
+fn search_key(i: &[u8]) -> IResult<&[u8], SearchKey<'_>> {
+    search_key_nested(i, 0)
+}
+
+fn search_key_nested(i: &[u8], depth: usize) -> IResult<&[u8], SearchKey<'_>> {
+    if depth > MAX_SEARCH_DEPTH {
+        return Err(nom::Err::Failure(nom::error::Error::new(
+            i,
+            nom::error::ErrorKind::TooLarge,
+        )));
+    }
+    let nested = move |i| search_key_nested(i, depth + 1);
+    alt((
+        search_key_string,
+        search_key_date,
+        map(preceded(tag_no_case("LARGER "), number), SearchKey::Larger),
+        map(
+            preceded(tag_no_case("SMALLER "), number),
+            SearchKey::Smaller,
+        ),
+        map(preceded(tag_no_case("NOT "), nested), |k| {
+            SearchKey::Not(Box::new(k))
+        }),
+        map(
+            tuple((tag_no_case("OR "), nested, tag(" "), nested)),
+            |(_, a, _, b)| SearchKey::Or(Box::new(a), Box::new(b)),
+        ),
+        map(preceded(tag_no_case("UID "), sequence_set), SearchKey::Uid),
+        // Keep this after the prefixed keys, so that e.g. `UNSEEN` is not taken for `UN`...
+        search_key_flag,
+        map(sequence_set, SearchKey::SequenceSet),
+        map(
+            paren_delimited(separated_list1(tag(" "), nested)),
+            SearchKey::And,
+        ),
+    ))(i)
+}
+
+// search = "SEARCH" [SP "CHARSET" SP astring] 1*(SP search-key)
+#[allow(clippy::type_complexity)]
+fn search(i: &[u8]) -> IResult<&[u8], (Option<Cow<'_, str>>, Vec<SearchKey<'_>>)> {
+    preceded(
+        tag_no_case("SEARCH"),
+        pair(
+            opt(preceded(tag_no_case(" CHARSET "), astring)),
+            many1(preceded(tag(" "), search_key)),
+        ),
+    )(i)
+}
+
+// section-text = section-msgtext / "MIME"
+// section-msgtext = "HEADER" / "HEADER.FIELDS" [".NOT"] SP header-list / "TEXT"
+fn section_text(i: &[u8]) -> IResult<&[u8], SectionText<'_>> {
+    alt((
+        map(
+            preceded(
+                tag_no_case("HEADER.FIELDS.NOT "),
+                paren_delimited(separated_list1(tag(" "), astring)),
+            ),
+            SectionText::HeaderFieldsNot,
+        ),
+        map(
+            preceded(
+                tag_no_case("HEADER.FIELDS "),
+                paren_delimited(separated_list1(tag(" "), astring)),
+            ),
+            SectionText::HeaderFields,
+        ),
+        value(SectionText::Header, tag_no_case("HEADER")),
+        value(SectionText::Text, tag_no_case("TEXT")),
+        value(SectionText::Mime, tag_no_case("MIME")),
+    ))(i)
+}
+
+// section = "[" [section-spec] "]"
+fn section(i: &[u8]) -> IResult<&[u8], Section<'_>> {
+    delimited(
+        tag("["),
+        alt((
+            map(
+                pair(
+                    separated_list1(tag("."), number),
+                    opt(preceded(tag("."), section_text)),
+                ),
+                |(part, text)| Section { part, text },
+            ),
+            map(section_text, |text| Section {
+                part: vec![],
+                text: Some(text),
+            }),
+            map(tag(""), |_| Section::default()),
+        )),
+        tag("]"),
+    )(i)
+}
+
+fn fetch_att_body_section(i: &[u8]) -> IResult<&[u8], FetchAttribute<'_>> {
+    map(
+        tuple((
+            tag_no_case("BODY"),
+            opt(tag_no_case(".PEEK")),
+            section,
+            opt(delimited(
+                tag("<"),
+                tuple((number, tag("."), number)),
+                tag(">"),
+            )),
+        )),
+        |(_, peek, section, partial)| FetchAttribute::BodySection {
+            peek: peek.is_some(),
+            section,
+            partial: partial.map(|(origin, _, count)| (origin, count)),
+        },
+    )(i)
+}
+
+fn fetch_att(i: &[u8]) -> IResult<&[u8], FetchAttribute<'_>> {
+    alt((
+        fetch_att_body_section,
+        value(FetchAttribute::BodyStructure, tag_no_case("BODYSTRUCTURE")),
+        value(FetchAttribute::Body, tag_no_case("BODY")),
+        value(FetchAttribute::Envelope, tag_no_case("ENVELOPE")),
+        value(FetchAttribute::Flags, tag_no_case("FLAGS")),
+        value(FetchAttribute::InternalDate, tag_no_case("INTERNALDATE")),
+        value(FetchAttribute::ModSeq, tag_no_case("MODSEQ")),
+        value(FetchAttribute::Rfc822Header, tag_no_case("RFC822.HEADER")),
+        value(FetchAttribute::Rfc822Size, tag_no_case("RFC822.SIZE")),
+        value(FetchAttribute::Rfc822Text, tag_no_case("RFC822.TEXT")),
+        value(FetchAttribute::Rfc822, tag_no_case("RFC822")),
+        value(FetchAttribute::Uid, tag_no_case("UID")),
+    ))(i)
+}
+
+fn fetch_attributes(i: &[u8]) -> IResult<&[u8], FetchAttributes<'_>> {
+    alt((
+        value(FetchAttributes::Macro(AttrMacro::All), tag_no_case("ALL")),
+        value(FetchAttributes::Macro(AttrMacro::Fast), tag_no_case("FAST")),
+        value(FetchAttributes::Macro(AttrMacro::Full), tag_no_case("FULL")),
+        map(
+            paren_delimited(separated_list1(tag(" "), fetch_att)),
+            FetchAttributes::List,
+        ),
+        map(fetch_att, |att| FetchAttributes::List(vec![att])),
+    ))(i)
+}
+
+// fetch = "FETCH" SP sequence-set SP ("ALL" / "FULL" / "FAST" / fetch-att /
+//         "(" fetch-att *(SP fetch-att) ")") [SP "(" "CHANGEDSINCE" SP mod-sequence-value ")"]
+#[allow(clippy::type_complexity)]
+fn fetch(i: &[u8]) -> IResult<&[u8], (Vec<SequenceItem>, FetchAttributes<'_>, Option<u64>)> {
+    map(
+        tuple((
+            tag_no_case("FETCH "),
+            sequence_set,
+            tag(" "),
+            fetch_attributes,
+            opt(preceded(
+                tag(" "),
+                paren_delimited(preceded(tag_no_case("CHANGEDSINCE "), number_64)),
+            )),
+        )),
+        |(_, set, _, attributes, changed_since)| (set, attributes, changed_since),
+    )(i)
+}
+
+// store-att-flags = (["+" / "-"] "FLAGS" [".SILENT"]) SP (flag-list / (flag *(SP flag)))
+#[allow(clippy::type_complexity)]
+fn store(
+    i: &[u8],
+) -> IResult<
+    &[u8],
+    (
+        Vec<SequenceItem>,
+        Option<u64>,
+        StoreKind,
+        bool,
+        Vec<Cow<'_, str>>,
+    ),
+> {
+    map(
+        tuple((
+            tag_no_case("STORE "),
+            sequence_set,
+            tag(" "),
+            opt(terminated(
+                paren_delimited(preceded(tag_no_case("UNCHANGEDSINCE "), number_64)),
+                tag(" "),
+            )),
+            alt((
+                value(StoreKind::Add, tag("+")),
+                value(StoreKind::Remove, tag("-")),
+                value(StoreKind::Replace, tag("")),
+            )),
+            tag_no_case("FLAGS"),
+            opt(tag_no_case(".SILENT")),
+            tag(" "),
+            alt((
+                flag_list,
+                separated_list1(tag(" "), map(flag, Cow::Borrowed)),
+            )),
+        )),
+        |(_, set, _, unchanged_since, kind, _, silent, _, flags)| {
+            (set, unchanged_since, kind, silent.is_some(), flags)
+        },
+    )(i)
+}
+
+fn copy(i: &[u8]) -> IResult<&[u8], (Vec<SequenceItem>, Cow<'_, str>)> {
+    map(
+        tuple((tag_no_case("COPY "), sequence_set, tag(" "), mailbox)),
+        |(_, set, _, mailbox)| (set, mailbox),
+    )(i)
+}
+
+fn command_select(i: &[u8]) -> IResult<&[u8], CommandBody<'_>> {
+    let (i, uid) = map(opt(tag_no_case("UID ")), |u| u.is_some())(i)?;
+    let (i, body) = alt((
+        map(copy, |(sequence_set, mailbox)| CommandBody::Copy {
+            sequence_set,
+            mailbox,
+            uid,
+        }),
+        map(fetch, |(sequence_set, attributes, changed_since)| {
+            CommandBody::Fetch {
+                sequence_set,
+                attributes,
+                changed_since,
+                uid,
+            }
+        }),
+        map(search, |(charset, criteria)| CommandBody::Search {
+            charset,
+            criteria,
+            uid,
+        }),
+        map(
+            store,
+            |(sequence_set, unchanged_since, kind, silent, flags)| CommandBody::Store {
+                sequence_set,
+                kind,
+                silent,
+                flags,
+                unchanged_since,
+                uid,
+            },
+        ),
+    ))(i)?;
+    Ok((i, body))
+}
+
+fn command_select_only(i: &[u8]) -> IResult<&[u8], CommandBody<'_>> {
+    alt((
+        value(CommandBody::Check, tag_no_case("CHECK")),
+        value(CommandBody::Close, tag_no_case("CLOSE")),
+        value(CommandBody::Expunge, tag_no_case("EXPUNGE")),
+        command_select,
+    ))(i)
+}
+
+// command = tag SP (command-any / command-auth / command-nonauth /
+//           command-select) CRLF
+pub(crate) fn command(i: &[u8]) -> IResult<&[u8], Command<'_>> {
+    map(
+        tuple((
+            imap_tag,
+            tag(" "),
+            alt((
+                command_any,
+                command_auth,
+                command_nonauth,
+                command_select_only,
+            )),
+            tag("\r\n"),
+        )),
+        |(tag, _, body, _)| Command { tag, body },
+    )(i)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_matches::assert_matches;
+
+    #[test]
+    fn test_login() {
+        assert_matches!(
+            command(b"A1 LOGIN djc \"s3cr\\\"t\"\r\n"),
+            Ok((_, Command { tag, body: CommandBody::Login { username, password } })) => {
+                assert_eq!(tag, RequestId("A1".into()));
+                assert_eq!(username, "djc");
+                assert_eq!(password, "s3cr\"t");
+            }
+        );
+
+        assert_matches!(
+            command(b"A1 LOGIN {3}\r\ndjc {6+}\r\ns3cr3t\r\n"),
+            Ok((_, Command { body: CommandBody::Login { username, password }, .. })) => {
+                assert_eq!(username, "djc");
+                assert_eq!(password, "s3cr3t");
+            }
+        );
+    }
+
+    #[test]
+    fn test_literal_incomplete() {
+        assert_matches!(command(b"A1 LOGIN {3}\r\n"), Err(nom::Err::Incomplete(_)));
+    }
+
+    #[test]
+    fn test_select() {
+        assert_matches!(
+            command(b"A2 SELECT inbox\r\n"),
+            Ok((_, Command { body: CommandBody::Select { mailbox, cond_store: false }, .. })) => {
+                assert_eq!(mailbox, "INBOX");
+            }
+        );
+
+        assert_matches!(
+            command(b"A2 EXAMINE \"INBOX\" (CONDSTORE)\r\n"),
+            Ok((_, Command { body: CommandBody::Examine { mailbox, cond_store: true }, .. })) => {
+                assert_eq!(mailbox, "INBOX");
+            }
+        );
+    }
+
+    #[test]
+    fn test_list() {
+        assert_matches!(
+            command(b"A3 LIST \"\" %\r\n"),
+            Ok((_, Command { body: CommandBody::List { reference, pattern }, .. })) => {
+                assert_eq!(reference, "");
+                assert_eq!(pattern, "%");
+            }
+        );
+    }
+
+    #[test]
+    fn test_status() {
+        assert_matches!(
+            command(b"A4 STATUS blurdybloop (UIDNEXT MESSAGES DELETED-STORAGE)\r\n"),
+            Ok((_, Command { body: CommandBody::Status { mailbox, items }, .. })) => {
+                assert_eq!(mailbox, "blurdybloop");
+                assert_eq!(items, vec![
+                    StatusItem::UidNext,
+                    StatusItem::Messages,
+                    StatusItem::DeletedStorage,
+                ]);
+            }
+        );
+    }
+
+    #[test]
+    fn test_append() {
+        assert_matches!(
+            command(b"A5 APPEND saved-messages (\\Seen) \"07-Feb-1994 21:52:25 -0800\" {5}\r\nhello\r\n"),
+            Ok((_, Command { body: CommandBody::Append { mailbox, flags, date, message }, .. })) => {
+                assert_eq!(mailbox, "saved-messages");
+                assert_eq!(flags, vec!["\\Seen"]);
+                assert_eq!(date.as_deref(), Some("07-Feb-1994 21:52:25 -0800"));
+                assert_eq!(&*message, b"hello");
+            }
+        );
+
+        assert_matches!(
+            command(b"A5 APPEND INBOX {2}\r\nhi\r\n"),
+            Ok((_, Command { body: CommandBody::Append { flags, date: None, .. }, .. })) => {
+                assert!(flags.is_empty());
+            }
+        );
+    }
+
+    #[test]
+    fn test_fetch() {
+        assert_matches!(
+            command(b"A6 FETCH 1:* (UID MODSEQ) (CHANGEDSINCE 13)\r\n"),
+            Ok((_, Command { body: CommandBody::Fetch { sequence_set, attributes, changed_since, uid: false }, .. })) => {
+                assert_eq!(sequence_set, vec![SequenceItem::Range(
+                    SequenceNumber::Number(1),
+                    SequenceNumber::Largest,
+                )]);
+                assert_eq!(attributes, FetchAttributes::List(vec![
+                    FetchAttribute::Uid,
+                    FetchAttribute::ModSeq,
+                ]));
+                assert_eq!(changed_since, Some(13));
+            }
+        );
+
+        assert_matches!(
+            command(b"A7 UID FETCH 4,7 (FLAGS BODY.PEEK[1.2.HEADER.FIELDS (From Subject)]<0.512>)\r\n"),
+            Ok((_, Command { body: CommandBody::Fetch { attributes, uid: true, .. }, .. })) => {
+                assert_eq!(attributes, FetchAttributes::List(vec![
+                    FetchAttribute::Flags,
+                    FetchAttribute::BodySection {
+                        peek: true,
+                        section: Section {
+                            part: vec![1, 2],
+                            text: Some(SectionText::HeaderFields(vec![
+                                Cow::Borrowed("From"),
+                                Cow::Borrowed("Subject"),
+                            ])),
+                        },
+                        partial: Some((0, 512)),
+                    },
+                ]));
+            }
+        );
+
+        assert_matches!(
+            command(b"A8 FETCH 2 BODY[]\r\n"),
+            Ok((_, Command { body: CommandBody::Fetch { attributes, .. }, .. })) => {
+                assert_eq!(attributes, FetchAttributes::List(vec![FetchAttribute::BodySection {
+                    peek: false,
+                    section: Section::default(),
+                    partial: None,
+                }]));
+            }
+        );
+
+        assert_matches!(
+            command(b"A9 FETCH 2 full\r\n"),
+            Ok((
+                _,
+                Command {
+                    body: CommandBody::Fetch {
+                        attributes: FetchAttributes::Macro(AttrMacro::Full),
+                        ..
+                    },
+                    ..
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn test_store() {
+        assert_matches!(
+            command(b"A10 UID STORE 2:4 +FLAGS.SILENT (\\Deleted)\r\n"),
+            Ok((_, Command { body: CommandBody::Store { kind, silent, flags, uid, .. }, .. })) => {
+                assert_eq!(kind, StoreKind::Add);
+                assert!(silent);
+                assert!(uid);
+                assert_eq!(flags, vec!["\\Deleted"]);
+            }
+        );
+
+        assert_matches!(
+            command(b"A11 STORE 1 (UNCHANGEDSINCE 320) FLAGS \\Seen $Junk\r\n"),
+            Ok((_, Command { body: CommandBody::Store { kind, unchanged_since, flags, .. }, .. })) => {
+                assert_eq!(kind, StoreKind::Replace);
+                assert_eq!(unchanged_since, Some(320));
+                assert_eq!(flags, vec!["\\Seen", "$Junk"]);
+            }
+        );
+    }
+
+    #[test]
+    fn test_search() {
+        assert_matches!(
+            command(b"A12 SEARCH CHARSET UTF-8 FLAGGED SINCE 1-Feb-1994 NOT FROM \"Smith\"\r\n"),
+            Ok((_, Command { body: CommandBody::Search { charset, criteria, uid: false }, .. })) => {
+                assert_eq!(charset.as_deref(), Some("UTF-8"));
+                assert_eq!(criteria, vec![
+                    SearchKey::Flagged,
+                    SearchKey::Since(Cow::Borrowed("1-Feb-1994")),
+                    SearchKey::Not(Box::new(SearchKey::From(Cow::Borrowed("Smith")))),
+                ]);
+            }
+        );
+
+        assert_matches!(
+            command(b"A13 UID SEARCH OR (UNSEEN 1:5) UID 3,9:*\r\n"),
+            Ok((_, Command { body: CommandBody::Search { criteria, uid: true, .. }, .. })) => {
+                assert_eq!(criteria, vec![SearchKey::Or(
+                    Box::new(SearchKey::And(vec![
+                        SearchKey::Unseen,
+                        SearchKey::SequenceSet(vec![SequenceItem::Range(
+                            SequenceNumber::Number(1),
+                            SequenceNumber::Number(5),
+                        )]),
+                    ])),
+                    Box::new(SearchKey::Uid(vec![
+                        SequenceItem::Single(SequenceNumber::Number(3)),
+                        SequenceItem::Range(SequenceNumber::Number(9), SequenceNumber::Largest),
+                    ])),
+                )]);
+            }
+        );
+
+        // At least one search key is required
+        assert!(command(b"A14 SEARCH\r\n").is_err());
+        assert!(command(b"A15 SEARCH CHARSET UTF-8\r\n").is_err());
+
+        let deep = [&b"A16 SEARCH "[..], &b"NOT ".repeat(40), b"ALL\r\n"].concat();
+        assert_matches!(command(&deep), Err(nom::Err::Failure(_)));
+        let deep = [
+            &b"A17 SEARCH "[..],
+            &[b'('; 40],
+            b"ALL",
+            &[b')'; 40],
+            b"\r\n",
+        ]
+        .concat();
+        assert_matches!(command(&deep), Err(nom::Err::Failure(_)));
+    }
+
+    #[test]
+    fn test_simple_commands() {
+        for (input, expected) in [
+            (&b"a CAPABILITY\r\n"[..], CommandBody::Capability),
+            (b"a NOOP\r\n", CommandBody::Noop),
+            (b"a LOGOUT\r\n", CommandBody::Logout),
+            (b"a STARTTLS\r\n", CommandBody::StartTls),
+            (b"a CHECK\r\n", CommandBody::Check),
+            (b"a CLOSE\r\n", CommandBody::Close),
+            (b"a EXPUNGE\r\n", CommandBody::Expunge),
+            (b"a IDLE\r\n", CommandBody::Idle),
+        ] {
+            assert_matches!(command(input), Ok((rest, Command { body, .. })) => {
+                assert!(rest.is_empty());
+                assert_eq!(body, expected);
+            });
+        }
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert_matches!(command(b"DONE\r\n"), Err(nom::Err::Error(_)));
+        assert_matches!(command(b"A1 FROBNICATE\r\n"), Err(nom::Err::Error(_)));
+    }
+}
@@ -11,16 +11,16 @@ use nom::{
     branch::alt,
     bytes::streaming::{tag, tag_no_case, take_while, take_while1},
     character::streaming::char,
-    combinator::{map, map_res, opt, recognize, value},
-    multi::{many0, many1},
-    sequence::{delimited, pair, preceded, terminated, tuple},
+    combinator::{map, map_res, opt, peek, recognize},
+    multi::{many0, many1, separated_list1},
+    sequence::{delimited, pair, preceded, separated_pair, terminated, tuple},
     IResult,
 };
 
 use crate::{
     parser::{
-        core::*, rfc2087, rfc2971, rfc3501::body::*, rfc3501::body_structure::*, rfc4314, rfc4315,
-        rfc4551, rfc5161, rfc5256, rfc5464, rfc7162,
+        core::*, rfc2087, rfc2342, rfc2971, rfc3501::body::*, rfc3501::body_structure::*, rfc4314,
+        rfc4315, rfc4551, rfc5161, rfc5256, rfc5464, rfc7162,
     },
     types::*,
 };
@@ -29,6 +29,7 @@ use super::gmail;
 
 pub mod body;
 pub mod body_structure;
+pub mod command;
 
 fn is_tag_char(c: u8) -> bool {
     c != b'+' && is_astring_char(c)
@@ -173,6 +174,35 @@ fn resp_text_code_unseen(i: &[u8]) -> IResult<&[u8], ResponseCode<'_>> {
     )(i)
 }
 
+// Any other response code: `atom [SP 1*<any TEXT-CHAR except "]">]`
+fn resp_text_code_other(i: &[u8]) -> IResult<&[u8], ResponseCode<'_>> {
+    let (i, name) = atom(i)?;
+    let (i, values) = opt(terminated(
+        preceded(tag(b" "), separated_list1(tag(b" "), imap_value)),
+        peek(tag(b"]")),
+    ))(i)?;
+    // Fall back to the raw text if the arguments are not generic values
+    let (i, args) = match values {
+        Some(values) => (i, OtherArgs::Values(values)),
+        None => {
+            let raw = take_while1(|c| c != b']' && c != b'\r' && c != b'\n');
+            let (i, raw) = opt(preceded(tag(b" "), map_res(raw, from_utf8)))(i)?;
+            let args = match raw {
+                Some(raw) => OtherArgs::Raw(Cow::Borrowed(raw)),
+                None => OtherArgs::Values(Vec::new()),
+            };
+            (i, args)
+        }
+    };
+    Ok((
+        i,
+        ResponseCode::Other {
+            name: Cow::Borrowed(name),
+            args,
+        },
+    ))
+}
+
 fn resp_text_code(i: &[u8]) -> IResult<&[u8], ResponseCode<'_>> {
     // Per the spec, the closing tag should be "] ".
     // See `resp_text` for more on why this is done differently.
@@ -198,6 +228,7 @@ fn resp_text_code(i: &[u8]) -> IResult<&[u8], ResponseCode<'_>> {
             rfc5464::resp_text_code_metadata_max_size,
             rfc5464::resp_text_code_metadata_too_many,
             rfc5464::resp_text_code_metadata_no_private,
+            resp_text_code_other,
         )),
         tag(b"]"),
     )(i)
@@ -260,30 +291,32 @@ fn mailbox_data_exists(i: &[u8]) -> IResult<&[u8], MailboxDatum<'_>> {
     )(i)
 }
 
+// The whole attribute is read before matching it, so that attributes like
+// Gmail's `\AllMail` are not mistaken for `\All`.
 fn name_attribute(i: &[u8]) -> IResult<&[u8], NameAttribute<'_>> {
-    alt((
-        // RFC 3501
-        value(NameAttribute::NoInferiors, tag_no_case(b"\\Noinferiors")),
-        value(NameAttribute::NoSelect, tag_no_case(b"\\Noselect")),
-        value(NameAttribute::Marked, tag_no_case(b"\\Marked")),
-        value(NameAttribute::Unmarked, tag_no_case(b"\\Unmarked")),
-        // RFC 6154
-        value(NameAttribute::All, tag_no_case(b"\\All")),
-        value(NameAttribute::Archive, tag_no_case(b"\\Archive")),
-        value(NameAttribute::Drafts, tag_no_case(b"\\Drafts")),
-        value(NameAttribute::Flagged, tag_no_case(b"\\Flagged")),
-        value(NameAttribute::Junk, tag_no_case(b"\\Junk")),
-        value(NameAttribute::Sent, tag_no_case(b"\\Sent")),
-        value(NameAttribute::Trash, tag_no_case(b"\\Trash")),
-        // Extensions not supported by this crate
-        map(
-            map_res(
-                recognize(pair(tag(b"\\"), take_while(is_atom_char))),
-                from_utf8,
-            ),
-            |s| NameAttribute::Extension(Cow::Borrowed(s)),
+    map(
+        map_res(
+            recognize(pair(tag(b"\\"), take_while(is_atom_char))),
+            from_utf8,
         ),
-    ))(i)
+        |s| match s.to_ascii_lowercase().as_str() {
+            // RFC 3501
+            "\\noinferiors" => NameAttribute::NoInferiors,
+            "\\noselect" => NameAttribute::NoSelect,
+            "\\marked" => NameAttribute::Marked,
+            "\\unmarked" => NameAttribute::Unmarked,
+            // RFC 6154
+            "\\all" => NameAttribute::All,
+            "\\archive" => NameAttribute::Archive,
+            "\\drafts" => NameAttribute::Drafts,
+            "\\flagged" => NameAttribute::Flagged,
+            "\\junk" => NameAttribute::Junk,
+            "\\sent" => NameAttribute::Sent,
+            "\\trash" => NameAttribute::Trash,
+            // Extensions not supported by this crate
+            _ => NameAttribute::Extension(Cow::Borrowed(s)),
+        },
+    )(i)
 }
 
 #[allow(clippy::type_complexity)]
@@ -312,6 +345,17 @@ fn mailbox_data_list(i: &[u8]) -> IResult<&[u8], MailboxDatum<'_>> {
     })(i)
 }
 
+// XLIST is the non-standard predecessor of SPECIAL-USE, still offered by some servers
+fn mailbox_data_xlist(i: &[u8]) -> IResult<&[u8], MailboxDatum<'_>> {
+    map(preceded(tag_no_case("XLIST "), mailbox_list), |data| {
+        MailboxDatum::List {
+            name_attributes: data.0,
+            delimiter: data.1,
+            name: data.2,
+        }
+    })(i)
+}
+
 fn mailbox_data_lsub(i: &[u8]) -> IResult<&[u8], MailboxDatum<'_>> {
     map(preceded(tag_no_case("LSUB "), mailbox_list), |data| {
         MailboxDatum::List {
@@ -347,6 +391,10 @@ fn status_att(i: &[u8]) -> IResult<&[u8], StatusAttribute> {
             preceded(tag_no_case("UNSEEN "), number),
             StatusAttribute::Unseen,
         ),
+        map(
+            preceded(tag_no_case("DELETED-STORAGE "), number_64),
+            StatusAttribute::DeletedStorage,
+        ),
     ))(i)
 }
 
@@ -377,6 +425,7 @@ fn mailbox_data(i: &[u8]) -> IResult<&[u8], MailboxDatum<'_>> {
         mailbox_data_exists,
         mailbox_data_list,
         mailbox_data_lsub,
+        mailbox_data_xlist,
         mailbox_data_status,
         mailbox_data_recent,
         mailbox_data_search,
@@ -610,29 +659,19 @@ fn msg_att_threadid(i: &[u8]) -> IResult<&[u8], AttributeValue<'_>> {
 //   - any future extension this crate has not yet typed
 //
 // When all known parsers fail, this function consumes "name SP value" where
-// the value is one of:
-//   - a single-level parenthesised group `(...)`
-//   - an nstring (NIL / quoted-string / literal) — covers SAVEDATE and NIL forms
-//   - a bare atom or number — fallback for any remaining scalar value
-//
-// The name and value are discarded and `AttributeValue::Unknown` is returned so
-// that the rest of the `FETCH` attribute list can be parsed without error.
-// Note: deeply nested parenthesised values (beyond one level) are not handled
-// by the bare-paren arm; add a dedicated parser if a specific extension needs them.
+// the value is parsed generically (see `imap_value()`), so that callers can
+// still interpret it and the rest of the `FETCH` attribute list can be parsed.
 fn msg_att_unknown(i: &[u8]) -> IResult<&[u8], AttributeValue<'_>> {
     map(
-        pair(
-            map_res(take_while1(is_atom_char), from_utf8),
-            preceded(
-                tag(b" "),
-                alt((
-                    value((), paren_delimited(take_while(|c: u8| c != b')'))),
-                    value((), nstring),
-                    value((), take_while1(|c: u8| c != b' ' && c != b')')),
-                )),
-            ),
+        separated_pair(
+            map_res(take_while1(is_astring_char), from_utf8),
+            tag(b" "),
+            imap_value,
         ),
-        |_| AttributeValue::Unknown,
+        |(name, value)| AttributeValue::Unknown {
+            name: Cow::Borrowed(name),
+            value,
+        },
     )(i)
 }
 
@@ -676,7 +715,7 @@ fn message_data_expunge(i: &[u8]) -> IResult<&[u8], u32> {
 }
 
 // tag             = 1*<any ASTRING-CHAR except "+">
-fn imap_tag(i: &[u8]) -> IResult<&[u8], RequestId> {
+pub(crate) fn imap_tag(i: &[u8]) -> IResult<&[u8], RequestId> {
     map(map_res(take_while1(is_tag_char), from_utf8), |s| {
         RequestId(s.to_string())
     })(i)
@@ -782,6 +821,7 @@ pub(crate) fn response_data(i: &[u8]) -> IResult<&[u8], Response<'_>> {
             rfc7162::resp_vanished,
             rfc2087::quota,
             rfc2087::quota_root,
+            rfc2342::namespace,
             rfc2971::resp_id,
             rfc4314::acl,
             rfc4314::list_rights,
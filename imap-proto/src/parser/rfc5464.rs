@@ -14,6 +14,7 @@ use nom::{
 };
 use std::borrow::Cow;
 
+use crate::parser::rfc3501::mailbox;
 use crate::{parser::core::*, types::*};
 
 fn is_entry_component_char(c: u8) -> bool {
@@ -123,17 +124,19 @@ fn slice_to_str(i: &[u8]) -> &str {
     std::str::from_utf8(i).unwrap()
 }
 
-fn nil_value(i: &[u8]) -> IResult<&[u8], Option<String>> {
+fn nil_value(i: &[u8]) -> IResult<&[u8], Option<Cow<'_, [u8]>>> {
     map(tag_no_case("NIL"), |_| None)(i)
 }
 
-fn string_value(i: &[u8]) -> IResult<&[u8], Option<String>> {
-    map(alt((quoted, literal)), |s| {
-        Some(slice_to_str(s).to_string())
-    })(i)
+// Values may be binary, so they are not decoded as UTF-8
+fn string_value(i: &[u8]) -> IResult<&[u8], Option<Cow<'_, [u8]>>> {
+    alt((
+        map(quoted, |s| Some(unescape(s))),
+        map(literal, |s| Some(Cow::Borrowed(s))),
+    ))(i)
 }
 
-fn keyval_list(i: &[u8]) -> IResult<&[u8], Vec<Metadata>> {
+fn keyval_list(i: &[u8]) -> IResult<&[u8], Vec<Metadata<'_>>> {
     parenthesized_nonempty_list(map(
         tuple((
             map(entry_name, slice_to_str),
@@ -141,7 +144,7 @@ fn keyval_list(i: &[u8]) -> IResult<&[u8], Vec<Metadata>> {
             alt((nil_value, string_value)),
         )),
         |(key, _, value)| Metadata {
-            entry: key.to_string(),
+            entry: Cow::Borrowed(key),
             value,
         },
     ))(i)
@@ -151,8 +154,8 @@ fn entry_list(i: &[u8]) -> IResult<&[u8], Vec<Cow<'_, str>>> {
     separated_list0(tag(" "), map(map(entry_name, slice_to_str), Cow::Borrowed))(i)
 }
 
-fn metadata_common(i: &[u8]) -> IResult<&[u8], &[u8]> {
-    let (i, (_, mbox, _)) = tuple((tag_no_case("METADATA "), quoted, tag(" ")))(i)?;
+fn metadata_common(i: &[u8]) -> IResult<&[u8], Cow<'_, str>> {
+    let (i, (_, mbox, _)) = tuple((tag_no_case("METADATA "), mailbox, tag(" ")))(i)?;
     Ok((i, mbox))
 }
 
@@ -161,10 +164,7 @@ pub(crate) fn metadata_solicited(i: &[u8]) -> IResult<&[u8], Response<'_>> {
     let (i, (mailbox, values)) = tuple((metadata_common, keyval_list))(i)?;
     Ok((
         i,
-        Response::MailboxData(MailboxDatum::MetadataSolicited {
-            mailbox: Cow::Borrowed(slice_to_str(mailbox)),
-            values,
-        }),
+        Response::MailboxData(MailboxDatum::MetadataSolicited { mailbox, values }),
     ))
 }
 
@@ -173,10 +173,7 @@ pub(crate) fn metadata_unsolicited(i: &[u8]) -> IResult<&[u8], Response<'_>> {
     let (i, (mailbox, values)) = tuple((metadata_common, entry_list))(i)?;
     Ok((
         i,
-        Response::MailboxData(MailboxDatum::MetadataUnsolicited {
-            mailbox: Cow::Borrowed(slice_to_str(mailbox)),
-            values,
-        }),
+        Response::MailboxData(MailboxDatum::MetadataUnsolicited { mailbox, values }),
     ))
 }
 
@@ -271,21 +268,9 @@ mod tests {
                 assert_eq!(i, b"\r\n");
                 assert_eq!(values.len(), 2);
                 assert_eq!(values[0].entry, "/shared/vendor/vendorname");
-                assert_eq!(
-                    values[0]
-                        .value
-                        .as_ref()
-                        .expect("None value is not expected"),
-                    "asdf"
-                );
+                assert_eq!(values[0].value.as_deref(), Some(&b"asdf"[..]));
                 assert_eq!(values[1].entry, "/private/comment/a");
-                assert_eq!(
-                    values[1]
-                        .value
-                        .as_ref()
-                        .expect("None value is not expected"),
-                    "bbb"
-                );
+                assert_eq!(values[1].value.as_deref(), Some(&b"bbb"[..]));
             }
             _ => panic!("Correct METADATA response is not parsed properly."),
         }
@@ -300,19 +285,26 @@ mod tests {
                 assert_eq!(i, b"\r\n");
                 assert_eq!(values.len(), 1);
                 assert_eq!(values[0].entry, "/shared/vendor/vendor.coi/a");
-                assert_eq!(
-                    values[0]
-                        .value
-                        .as_ref()
-                        .expect("None value is not expected"),
-                    "AAA"
-                );
+                assert_eq!(values[0].value.as_deref(), Some(&b"AAA"[..]));
             }
             Err(e) => panic!("ERR: {e:?}"),
             _ => panic!("Strange failure"),
         }
     }
 
+    #[test]
+    fn test_binary_success() {
+        match metadata_solicited(
+            b"METADATA \"\" (/private/comment {3}\r\n\xff\x00\n /shared/comment \"a\\\"b\")\r\n",
+        ) {
+            Ok((_, Response::MailboxData(MailboxDatum::MetadataSolicited { values, .. }))) => {
+                assert_eq!(values[0].value.as_deref(), Some(&b"\xff\x00\n"[..]));
+                assert_eq!(values[1].value.as_deref(), Some(&b"a\"b"[..]));
+            }
+            rsp => panic!("unexpected response {rsp:?}"),
+        }
+    }
+
     #[test]
     fn test_nil_success() {
         match metadata_solicited(b"METADATA \"\" (/shared/comment NIL /shared/admin NIL)\r\n") {
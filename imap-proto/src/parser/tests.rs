@@ -49,6 +49,30 @@ fn test_name_attributes() {
     }
 }
 
+#[test]
+fn test_xlist() {
+    match parse_response(b"* XLIST (\\HasNoChildren \\AllMail) \"/\" \"[Gmail]/All Mail\"\r\n") {
+        Ok((
+            _,
+            Response::MailboxData(MailboxDatum::List {
+                name_attributes,
+                name,
+                ..
+            }),
+        )) => {
+            assert_eq!(name, "[Gmail]/All Mail");
+            assert_eq!(
+                name_attributes,
+                [
+                    NameAttribute::Extension(Cow::Borrowed("\\HasNoChildren")),
+                    NameAttribute::Extension(Cow::Borrowed("\\AllMail")),
+                ]
+            );
+        }
+        rsp => panic!("unexpected response {rsp:?}"),
+    }
+}
+
 /// Test the ACL response from RFC 4314/2086
 #[test]
 fn test_acl_response() {
@@ -519,6 +543,22 @@ fn test_status() {
     }
 }
 
+#[test]
+fn test_status_deleted_storage() {
+    match parse_response(b"* STATUS blurdybloop (MESSAGES 3 DELETED-STORAGE 5120)\r\n") {
+        Ok((_, Response::MailboxData(MailboxDatum::Status { status, .. }))) => {
+            assert_eq!(
+                status,
+                [
+                    StatusAttribute::Messages(3),
+                    StatusAttribute::DeletedStorage(5120),
+                ]
+            );
+        }
+        rsp => panic!("unexpected response {rsp:?}"),
+    }
+}
+
 #[test]
 fn test_notify() {
     match parse_response(b"* 3501 EXPUNGE\r\n") {
@@ -675,14 +715,72 @@ fn test_response_codes() {
         rsp => panic!("unexpected response {rsp:?}"),
     }
 
-    // Missing IMAP4rev1
-    match parse_response(b"* OK [CAPABILITY UIDPLUS IDLE] Logged in\r\n") {
+    // Missing IMAP4rev1, so not recognized as a capability list
+    let (_, rsp) = parse_response(b"* OK [CAPABILITY UIDPLUS IDLE] Logged in\r\n").unwrap();
+    assert_eq!(
+        rsp,
+        Response::Data {
+            status: Status::Ok,
+            code: Some(ResponseCode::Other {
+                name: Cow::Borrowed("CAPABILITY"),
+                args: OtherArgs::Values(vec![
+                    ImapValue::Atom(Cow::Borrowed("UIDPLUS")),
+                    ImapValue::Atom(Cow::Borrowed("IDLE")),
+                ]),
+            }),
+            information: Some(Cow::Borrowed("Logged in")),
+        }
+    );
+
+    match parse_response(b"* NO [OVERQUOTA] Quota exceeded\r\n") {
         Ok((
             _,
             Response::Data {
-                status: Status::Ok,
-                code: None,
-                information: Some(Cow::Borrowed("[CAPABILITY UIDPLUS IDLE] Logged in")),
+                status: Status::No,
+                code:
+                    Some(ResponseCode::Other {
+                        name: Cow::Borrowed("OVERQUOTA"),
+                        args: OtherArgs::Values(values),
+                    }),
+                information: Some(Cow::Borrowed("Quota exceeded")),
+            },
+        )) if values.is_empty() => {}
+        rsp => panic!("unexpected response {rsp:?}"),
+    }
+
+    let (_, rsp) = parse_response(b"A1 OK [X-VENDOR 12 (a b)] done\r\n").unwrap();
+    match rsp {
+        Response::Done {
+            code: Some(ResponseCode::Other { name, args }),
+            ..
+        } => {
+            assert_eq!(name, "X-VENDOR");
+            assert_eq!(
+                args,
+                OtherArgs::Values(vec![
+                    ImapValue::Number(12),
+                    ImapValue::List(vec![
+                        ImapValue::Atom(Cow::Borrowed("a")),
+                        ImapValue::Atom(Cow::Borrowed("b")),
+                    ]),
+                ])
+            );
+        }
+        rsp => panic!("unexpected response {rsp:?}"),
+    }
+
+    // Arguments that are not values are still kept as text
+    match parse_response(b"A1 NO [X-VENDOR (a \"b] c\r\n") {
+        Ok((
+            _,
+            Response::Done {
+                code:
+                    Some(ResponseCode::Other {
+                        name: Cow::Borrowed("X-VENDOR"),
+                        args: OtherArgs::Raw(Cow::Borrowed("(a \"b")),
+                    }),
+                information: Some(Cow::Borrowed("c")),
+                ..
             },
         )) => {}
         rsp => panic!("unexpected response {rsp:?}"),
@@ -1106,7 +1204,13 @@ fn test_fetch_unknown_savedate_fallback() {
         Ok((_, Response::Fetch(1, attrs))) => {
             assert_eq!(attrs.len(), 3);
             assert!(matches!(attrs[0], AttributeValue::Uid(9)));
-            assert!(matches!(attrs[1], AttributeValue::Unknown));
+            assert_eq!(
+                attrs[1],
+                AttributeValue::Unknown {
+                    name: Cow::Borrowed("SAVEDATE"),
+                    value: ImapValue::String(Cow::Borrowed(b"01-Jan-2025 12:00:00 +0000")),
+                }
+            );
             assert!(matches!(
                 attrs[2],
                 AttributeValue::BodySection { index: Some(0), .. }
@@ -1115,3 +1219,40 @@ fn test_fetch_unknown_savedate_fallback() {
         rsp => panic!("unexpected response {rsp:?}"),
     }
 }
+
+#[test]
+fn test_fetch_unknown_nested_value() {
+    match parse_response(
+        b"* 1 FETCH (X-FOO (bar 12 (NIL \"a\\\"b\" ({3}\r\nxyz \\Seen))) UID 9)\r\n",
+    ) {
+        Ok((_, Response::Fetch(1, attrs))) => {
+            assert_eq!(
+                attrs[0],
+                AttributeValue::Unknown {
+                    name: Cow::Borrowed("X-FOO"),
+                    value: ImapValue::List(vec![
+                        ImapValue::Atom(Cow::Borrowed("bar")),
+                        ImapValue::Number(12),
+                        ImapValue::List(vec![
+                            ImapValue::Nil,
+                            ImapValue::String(Cow::Borrowed(b"a\"b")),
+                            ImapValue::List(vec![
+                                ImapValue::String(Cow::Borrowed(b"xyz")),
+                                ImapValue::Atom(Cow::Borrowed("\\Seen")),
+                            ]),
+                        ]),
+                    ]),
+                }
+            );
+            assert_eq!(attrs[1], AttributeValue::Uid(9));
+        }
+        rsp => panic!("unexpected response {rsp:?}"),
+    }
+
+    // Nesting is bounded
+    let mut deep = b"* 1 FETCH (X-FOO ".to_vec();
+    deep.extend(vec![b'('; 100]);
+    deep.extend(vec![b')'; 100]);
+    deep.extend(b")\r\n");
+    assert!(parse_response(&deep).is_err());
+}
@@ -1,10 +1,15 @@
 use std::borrow::Cow;
-use std::collections::HashMap;
 use std::ops::RangeInclusive;
 
 pub mod acls;
 pub use acls::*;
 
+pub mod command;
+pub use command::*;
+
+pub mod special_use;
+pub use special_use::*;
+
 fn to_owned_cow<T: ?Sized + ToOwned>(c: Cow<'_, T>) -> Cow<'static, T> {
     Cow::Owned(c.into_owned())
 }
@@ -47,10 +52,24 @@ pub enum Response<'a> {
     MailboxData(MailboxDatum<'a>),
     Quota(Quota<'a>),
     QuotaRoot(QuotaRoot<'a>),
-    Id(Option<HashMap<Cow<'a, str>, Cow<'a, str>>>),
+    /// The ID parameters, in the order sent by the server; those with a NIL value are left out
+    Id(Option<Vec<(Cow<'a, str>, Cow<'a, str>)>>),
     Acl(Acl<'a>),
     ListRights(ListRights<'a>),
     MyRights(MyRights<'a>),
+    Namespace(Namespaces<'a>),
+    /// A response that could not be parsed
+    ///
+    /// Not produced by `Response::from_bytes()`, which fails on such input
+    /// instead. Consumers that know where a response ends (like tokio-imap's
+    /// codec, which accounts for literals) can use this to skip over responses
+    /// they don't understand, such as those from unknown extensions.
+    Unparsed {
+        /// The tag, if this looks like a tagged response
+        tag: Option<RequestId>,
+        /// The complete response, including any literals and the final CRLF
+        raw: Cow<'a, [u8]>,
+    },
 }
 
 impl<'a> Response<'a> {
@@ -99,7 +118,7 @@ impl<'a> Response<'a> {
             Response::MailboxData(datum) => Response::MailboxData(datum.into_owned()),
             Response::Quota(quota) => Response::Quota(quota.into_owned()),
             Response::QuotaRoot(quota_root) => Response::QuotaRoot(quota_root.into_owned()),
-            Response::Id(map) => Response::Id(map.map(|m| {
+            Response::Id(params) => Response::Id(params.map(|m| {
                 m.into_iter()
                     .map(|(k, v)| (to_owned_cow(k), to_owned_cow(v)))
                     .collect()
@@ -107,6 +126,11 @@ impl<'a> Response<'a> {
             Response::Acl(acl_list) => Response::Acl(acl_list.into_owned()),
             Response::ListRights(rights) => Response::ListRights(rights.into_owned()),
             Response::MyRights(rights) => Response::MyRights(rights.into_owned()),
+            Response::Namespace(namespaces) => Response::Namespace(namespaces.into_owned()),
+            Response::Unparsed { tag, raw } => Response::Unparsed {
+                tag,
+                raw: to_owned_cow(raw),
+            },
         }
     }
 }
@@ -142,6 +166,33 @@ pub enum ResponseCode<'a> {
     MetadataMaxSize(u64),     // RFC 5464, section 4.3
     MetadataTooMany,          // RFC 5464, section 4.3
     MetadataNoPrivate,        // RFC 5464, section 4.3
+    /// A response code that is not recognized, such as one from an extension
+    /// that is not supported yet or a vendor-specific one
+    Other {
+        name: Cow<'a, str>,
+        args: OtherArgs<'a>,
+    },
+}
+
+/// The arguments of a `ResponseCode::Other`
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum OtherArgs<'a> {
+    /// The space-separated arguments following the name; empty if there are none
+    Values(Vec<ImapValue<'a>>),
+    /// Everything following the name and a space, up to the closing bracket,
+    /// if it cannot be parsed as values
+    Raw(Cow<'a, str>),
+}
+
+impl<'a> OtherArgs<'a> {
+    pub fn into_owned(self) -> OtherArgs<'static> {
+        match self {
+            OtherArgs::Values(values) => {
+                OtherArgs::Values(values.into_iter().map(ImapValue::into_owned).collect())
+            }
+            OtherArgs::Raw(raw) => OtherArgs::Raw(to_owned_cow(raw)),
+        }
+    }
 }
 
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -190,6 +241,10 @@ impl<'a> ResponseCode<'a> {
             ResponseCode::MetadataMaxSize(v) => ResponseCode::MetadataMaxSize(v),
             ResponseCode::MetadataTooMany => ResponseCode::MetadataTooMany,
             ResponseCode::MetadataNoPrivate => ResponseCode::MetadataNoPrivate,
+            ResponseCode::Other { name, args } => ResponseCode::Other {
+                name: to_owned_cow(name),
+                args: args.into_owned(),
+            },
         }
     }
 }
@@ -203,12 +258,25 @@ pub enum StatusAttribute {
     UidNext(u32),
     UidValidity(u32),
     Unseen(u32),
+    /// Storage of messages marked `\Deleted`, in units of 1024 octets (RFC 9208)
+    DeletedStorage(u64),
 }
 
+/// An entry in a METADATA response (RFC 5464), with its value if any
 #[derive(Debug, Eq, PartialEq, Clone)]
-pub struct Metadata {
-    pub entry: String,
-    pub value: Option<String>,
+pub struct Metadata<'a> {
+    pub entry: Cow<'a, str>,
+    /// The value, which may be binary; `None` for NIL
+    pub value: Option<Cow<'a, [u8]>>,
+}
+
+impl<'a> Metadata<'a> {
+    pub fn into_owned(self) -> Metadata<'static> {
+        Metadata {
+            entry: to_owned_cow(self.entry),
+            value: self.value.map(to_owned_cow),
+        }
+    }
 }
 
 #[derive(Debug, Eq, PartialEq, Clone)]
@@ -230,7 +298,7 @@ pub enum MailboxDatum<'a> {
     Recent(u32),
     MetadataSolicited {
         mailbox: Cow<'a, str>,
-        values: Vec<Metadata>,
+        values: Vec<Metadata<'a>>,
     },
     MetadataUnsolicited {
         mailbox: Cow<'a, str>,
@@ -270,7 +338,7 @@ impl<'a> MailboxDatum<'a> {
             MailboxDatum::MetadataSolicited { mailbox, values } => {
                 MailboxDatum::MetadataSolicited {
                     mailbox: to_owned_cow(mailbox),
-                    values,
+                    values: values.into_iter().map(Metadata::into_owned).collect(),
                 }
             }
             MailboxDatum::MetadataUnsolicited { mailbox, values } => {
@@ -371,12 +439,43 @@ pub enum AttributeValue<'a> {
     /// An unknown or not-yet-supported FETCH attribute.
     ///
     /// Returned for any `msg-att` token that the parser does not explicitly
-    /// recognise (e.g. `SAVEDATE` from RFC 8514, or any future extension).
-    /// The name and raw value are consumed and discarded so that the rest
-    /// of the `FETCH` attribute list can be parsed without error.  Callers
-    /// that need the raw value should match on the specific RFC extension
-    /// and open a tracking issue or PR.
-    Unknown,
+    /// recognise (e.g. `SAVEDATE` from RFC 8514, or any future extension),
+    /// with the attribute's name and its value as a generic value tree, so
+    /// that callers can interpret it themselves.
+    Unknown {
+        name: Cow<'a, str>,
+        value: ImapValue<'a>,
+    },
+}
+
+/// A generic IMAP data item
+///
+/// Many extensions use the same basic grammar for their data: atoms, numbers,
+/// strings (quoted or literal), `NIL` and (nested) parenthesized lists of these.
+/// This is used where the specific grammar is not known to the parser.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ImapValue<'a> {
+    Nil,
+    Number(u64),
+    /// Any other bare token, like `FOO`, `\Seen` or `01-Jan-2025`
+    Atom(Cow<'a, str>),
+    /// A quoted string (without escapes) or a literal
+    String(Cow<'a, [u8]>),
+    List(Vec<ImapValue<'a>>),
+}
+
+impl<'a> ImapValue<'a> {
+    pub fn into_owned(self) -> ImapValue<'static> {
+        match self {
+            ImapValue::Nil => ImapValue::Nil,
+            ImapValue::Number(n) => ImapValue::Number(n),
+            ImapValue::Atom(atom) => ImapValue::Atom(to_owned_cow(atom)),
+            ImapValue::String(s) => ImapValue::String(to_owned_cow(s)),
+            ImapValue::List(items) => {
+                ImapValue::List(items.into_iter().map(ImapValue::into_owned).collect())
+            }
+        }
+    }
 }
 
 impl<'a> AttributeValue<'a> {
@@ -410,7 +509,10 @@ impl<'a> AttributeValue<'a> {
             AttributeValue::GmailThrId(v) => AttributeValue::GmailThrId(v),
             AttributeValue::EmailId(v) => AttributeValue::EmailId(to_owned_cow(v)),
             AttributeValue::ThreadId(v) => AttributeValue::ThreadId(v.map(to_owned_cow)),
-            AttributeValue::Unknown => AttributeValue::Unknown,
+            AttributeValue::Unknown { name, value } => AttributeValue::Unknown {
+                name: to_owned_cow(name),
+                value: value.into_owned(),
+            },
         }
     }
 }
@@ -884,15 +986,19 @@ impl<'a> NameAttribute<'a> {
     }
 }
 
-// IMAP4 QUOTA extension (rfc2087)
+// IMAP4 QUOTA extension (rfc2087, updated by rfc9208)
 
-/// https://tools.ietf.org/html/rfc2087#section-3
+/// https://tools.ietf.org/html/rfc9208#section-5
 #[derive(Debug, Eq, PartialEq, Hash, Clone)]
 pub enum QuotaResourceName<'a> {
     /// Sum of messages' RFC822.SIZE, in units of 1024 octets
     Storage,
     /// Number of messages
     Message,
+    /// Number of mailboxes (RFC 9208)
+    Mailbox,
+    /// Size of all annotations, in units of 1024 octets (RFC 9208)
+    AnnotationStorage,
     Atom(Cow<'a, str>),
 }
 
@@ -901,6 +1007,8 @@ impl<'a> QuotaResourceName<'a> {
         match self {
             QuotaResourceName::Message => QuotaResourceName::Message,
             QuotaResourceName::Storage => QuotaResourceName::Storage,
+            QuotaResourceName::Mailbox => QuotaResourceName::Mailbox,
+            QuotaResourceName::AnnotationStorage => QuotaResourceName::AnnotationStorage,
             QuotaResourceName::Atom(v) => QuotaResourceName::Atom(to_owned_cow(v)),
         }
     }
@@ -965,15 +1073,88 @@ impl<'a> QuotaRoot<'a> {
     }
 }
 
+/// NAMESPACE Response (https://tools.ietf.org/html/rfc2342#section-5)
+///
+/// A list that is sent as `NIL` is empty.
+#[derive(Debug, Eq, PartialEq, Hash, Clone)]
+pub struct Namespaces<'a> {
+    pub personal: Vec<Namespace<'a>>,
+    pub other_users: Vec<Namespace<'a>>,
+    pub shared: Vec<Namespace<'a>>,
+}
+
+impl<'a> Namespaces<'a> {
+    pub fn into_owned(self) -> Namespaces<'static> {
+        let into_owned = |namespaces: Vec<Namespace<'_>>| {
+            namespaces.into_iter().map(Namespace::into_owned).collect()
+        };
+        Namespaces {
+            personal: into_owned(self.personal),
+            other_users: into_owned(self.other_users),
+            shared: into_owned(self.shared),
+        }
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Hash, Clone)]
+pub struct Namespace<'a> {
+    pub prefix: Cow<'a, str>,
+    /// The hierarchy delimiter, if any
+    pub delimiter: Option<Cow<'a, str>>,
+    pub extensions: Vec<NamespaceExtension<'a>>,
+}
+
+impl<'a> Namespace<'a> {
+    pub fn into_owned(self) -> Namespace<'static> {
+        Namespace {
+            prefix: to_owned_cow(self.prefix),
+            delimiter: self.delimiter.map(to_owned_cow),
+            extensions: self
+                .extensions
+                .into_iter()
+                .map(NamespaceExtension::into_owned)
+                .collect(),
+        }
+    }
+}
+
+/// Extension data for a namespace, like `TRANSLATION` from RFC 5255
+#[derive(Debug, Eq, PartialEq, Hash, Clone)]
+pub struct NamespaceExtension<'a> {
+    pub name: Cow<'a, str>,
+    pub values: Vec<Cow<'a, str>>,
+}
+
+impl<'a> NamespaceExtension<'a> {
+    pub fn into_owned(self) -> NamespaceExtension<'static> {
+        NamespaceExtension {
+            name: to_owned_cow(self.name),
+            values: self.values.into_iter().map(to_owned_cow).collect(),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
     fn test_attribute_value_unknown_into_owned() {
+        let value = ImapValue::List(vec![
+            ImapValue::Atom(Cow::Borrowed("FOO")),
+            ImapValue::String(Cow::Borrowed(b"bar")),
+            ImapValue::List(vec![ImapValue::Nil, ImapValue::Number(42)]),
+        ]);
+        let attr = AttributeValue::Unknown {
+            name: Cow::Borrowed("X-FOO"),
+            value: value.clone(),
+        };
         assert_eq!(
-            AttributeValue::Unknown.into_owned(),
-            AttributeValue::Unknown
+            attr.into_owned(),
+            AttributeValue::Unknown {
+                name: Cow::Owned("X-FOO".into()),
+                value,
+            }
         );
     }
 
@@ -155,6 +155,56 @@ impl From<AclRight> for char {
     }
 }
 
+/// An identifier that rights are granted to (RFC 4314 section 2)
+///
+/// Negative rights are written as the identifier prefixed with `-`, and
+/// are subtracted from the rights granted to the identifier.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct AclIdentifier<'a> {
+    pub name: Cow<'a, str>,
+    pub negative: bool,
+}
+
+impl<'a> AclIdentifier<'a> {
+    pub fn new(name: impl Into<Cow<'a, str>>) -> Self {
+        AclIdentifier {
+            name: name.into(),
+            negative: false,
+        }
+    }
+
+    /// The negative rights identifier for `name`
+    pub fn negative(name: impl Into<Cow<'a, str>>) -> Self {
+        AclIdentifier {
+            name: name.into(),
+            negative: true,
+        }
+    }
+
+    /// The identifier that matches all users, including anonymous ones
+    pub fn anyone() -> Self {
+        Self::new("anyone")
+    }
+
+    pub fn into_owned(self) -> AclIdentifier<'static> {
+        AclIdentifier {
+            name: to_owned_cow(self.name),
+            negative: self.negative,
+        }
+    }
+}
+
+/// How SETACL changes the rights of an identifier (RFC 4314 section 3.1)
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum AclModifier {
+    /// Replace the rights with the given ones
+    Replace,
+    /// `+`: add the given rights
+    Add,
+    /// `-`: remove the given rights
+    Remove,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
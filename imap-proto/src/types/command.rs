@@ -0,0 +1,480 @@
+use super::{to_owned_cow, AttrMacro, RequestId};
+
+use std::borrow::Cow;
+
+// Client commands, as seen by a server (RFC 3501 section 6, plus IDLE from RFC 2177)
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Command<'a> {
+    pub tag: RequestId,
+    pub body: CommandBody<'a>,
+}
+
+impl<'a> Command<'a> {
+    pub fn from_bytes(buf: &'a [u8]) -> nom::IResult<&'a [u8], Command<'a>> {
+        crate::parser::parse_command(buf)
+    }
+
+    pub fn into_owned(self) -> Command<'static> {
+        Command {
+            tag: self.tag,
+            body: self.body.into_owned(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum CommandBody<'a> {
+    // Any state
+    Capability,
+    Noop,
+    Logout,
+    // Not authenticated state
+    StartTls,
+    Authenticate {
+        mechanism: Cow<'a, str>,
+        /// RFC 4959 (SASL-IR) initial response, still base64-encoded
+        initial_response: Option<Cow<'a, str>>,
+    },
+    Login {
+        username: Cow<'a, str>,
+        password: Cow<'a, str>,
+    },
+    // Authenticated state
+    Select {
+        mailbox: Cow<'a, str>,
+        /// RFC 4551 `(CONDSTORE)` select parameter
+        cond_store: bool,
+    },
+    Examine {
+        mailbox: Cow<'a, str>,
+        cond_store: bool,
+    },
+    Create {
+        mailbox: Cow<'a, str>,
+    },
+    Delete {
+        mailbox: Cow<'a, str>,
+    },
+    Rename {
+        from: Cow<'a, str>,
+        to: Cow<'a, str>,
+    },
+    Subscribe {
+        mailbox: Cow<'a, str>,
+    },
+    Unsubscribe {
+        mailbox: Cow<'a, str>,
+    },
+    List {
+        reference: Cow<'a, str>,
+        pattern: Cow<'a, str>,
+    },
+    Lsub {
+        reference: Cow<'a, str>,
+        pattern: Cow<'a, str>,
+    },
+    Status {
+        mailbox: Cow<'a, str>,
+        items: Vec<StatusItem>,
+    },
+    Append {
+        mailbox: Cow<'a, str>,
+        flags: Vec<Cow<'a, str>>,
+        date: Option<Cow<'a, str>>,
+        message: Cow<'a, [u8]>,
+    },
+    /// RFC 2177; the client ends the command by sending `DONE` on a line of its own
+    Idle,
+    // Selected state
+    Check,
+    Close,
+    Expunge,
+    Search {
+        charset: Option<Cow<'a, str>>,
+        criteria: Vec<SearchKey<'a>>,
+        uid: bool,
+    },
+    Fetch {
+        sequence_set: Vec<SequenceItem>,
+        attributes: FetchAttributes<'a>,
+        /// RFC 4551 `CHANGEDSINCE` fetch modifier
+        changed_since: Option<u64>,
+        uid: bool,
+    },
+    Store {
+        sequence_set: Vec<SequenceItem>,
+        kind: StoreKind,
+        silent: bool,
+        flags: Vec<Cow<'a, str>>,
+        /// RFC 4551 `UNCHANGEDSINCE` store modifier
+        unchanged_since: Option<u64>,
+        uid: bool,
+    },
+    Copy {
+        sequence_set: Vec<SequenceItem>,
+        mailbox: Cow<'a, str>,
+        uid: bool,
+    },
+}
+
+impl<'a> CommandBody<'a> {
+    pub fn into_owned(self) -> CommandBody<'static> {
+        match self {
+            CommandBody::Capability => CommandBody::Capability,
+            CommandBody::Noop => CommandBody::Noop,
+            CommandBody::Logout => CommandBody::Logout,
+            CommandBody::StartTls => CommandBody::StartTls,
+            CommandBody::Authenticate {
+                mechanism,
+                initial_response,
+            } => CommandBody::Authenticate {
+                mechanism: to_owned_cow(mechanism),
+                initial_response: initial_response.map(to_owned_cow),
+            },
+            CommandBody::Login { username, password } => CommandBody::Login {
+                username: to_owned_cow(username),
+                password: to_owned_cow(password),
+            },
+            CommandBody::Select {
+                mailbox,
+                cond_store,
+            } => CommandBody::Select {
+                mailbox: to_owned_cow(mailbox),
+                cond_store,
+            },
+            CommandBody::Examine {
+                mailbox,
+                cond_store,
+            } => CommandBody::Examine {
+                mailbox: to_owned_cow(mailbox),
+                cond_store,
+            },
+            CommandBody::Create { mailbox } => CommandBody::Create {
+                mailbox: to_owned_cow(mailbox),
+            },
+            CommandBody::Delete { mailbox } => CommandBody::Delete {
+                mailbox: to_owned_cow(mailbox),
+            },
+            CommandBody::Rename { from, to } => CommandBody::Rename {
+                from: to_owned_cow(from),
+                to: to_owned_cow(to),
+            },
+            CommandBody::Subscribe { mailbox } => CommandBody::Subscribe {
+                mailbox: to_owned_cow(mailbox),
+            },
+            CommandBody::Unsubscribe { mailbox } => CommandBody::Unsubscribe {
+                mailbox: to_owned_cow(mailbox),
+            },
+            CommandBody::List { reference, pattern } => CommandBody::List {
+                reference: to_owned_cow(reference),
+                pattern: to_owned_cow(pattern),
+            },
+            CommandBody::Lsub { reference, pattern } => CommandBody::Lsub {
+                reference: to_owned_cow(reference),
+                pattern: to_owned_cow(pattern),
+            },
+            CommandBody::Status { mailbox, items } => CommandBody::Status {
+                mailbox: to_owned_cow(mailbox),
+                items,
+            },
+            CommandBody::Append {
+                mailbox,
+                flags,
+                date,
+                message,
+            } => CommandBody::Append {
+                mailbox: to_owned_cow(mailbox),
+                flags: flags.into_iter().map(to_owned_cow).collect(),
+                date: date.map(to_owned_cow),
+                message: to_owned_cow(message),
+            },
+            CommandBody::Idle => CommandBody::Idle,
+            CommandBody::Check => CommandBody::Check,
+            CommandBody::Close => CommandBody::Close,
+            CommandBody::Expunge => CommandBody::Expunge,
+            CommandBody::Search {
+                charset,
+                criteria,
+                uid,
+            } => CommandBody::Search {
+                charset: charset.map(to_owned_cow),
+                criteria: criteria.into_iter().map(SearchKey::into_owned).collect(),
+                uid,
+            },
+            CommandBody::Fetch {
+                sequence_set,
+                attributes,
+                changed_since,
+                uid,
+            } => CommandBody::Fetch {
+                sequence_set,
+                attributes: attributes.into_owned(),
+                changed_since,
+                uid,
+            },
+            CommandBody::Store {
+                sequence_set,
+                kind,
+                silent,
+                flags,
+                unchanged_since,
+                uid,
+            } => CommandBody::Store {
+                sequence_set,
+                kind,
+                silent,
+                flags: flags.into_iter().map(to_owned_cow).collect(),
+                unchanged_since,
+                uid,
+            },
+            CommandBody::Copy {
+                sequence_set,
+                mailbox,
+                uid,
+            } => CommandBody::Copy {
+                sequence_set,
+                mailbox: to_owned_cow(mailbox),
+                uid,
+            },
+        }
+    }
+}
+
+/// A `seq-number`: either a number or `*`, the largest number in use
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SequenceNumber {
+    Number(u32),
+    Largest,
+}
+
+/// A member of a `sequence-set`
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SequenceItem {
+    Single(SequenceNumber),
+    Range(SequenceNumber, SequenceNumber),
+}
+
+/// Data item names for the STATUS command
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum StatusItem {
+    HighestModSeq, // RFC 4551
+    Messages,
+    Recent,
+    UidNext,
+    UidValidity,
+    Unseen,
+    DeletedStorage, // RFC 9208
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum StoreKind {
+    /// `FLAGS`
+    Replace,
+    /// `+FLAGS`
+    Add,
+    /// `-FLAGS`
+    Remove,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum FetchAttributes<'a> {
+    Macro(AttrMacro),
+    List(Vec<FetchAttribute<'a>>),
+}
+
+impl<'a> FetchAttributes<'a> {
+    pub fn into_owned(self) -> FetchAttributes<'static> {
+        match self {
+            FetchAttributes::Macro(m) => FetchAttributes::Macro(m),
+            FetchAttributes::List(v) => {
+                FetchAttributes::List(v.into_iter().map(FetchAttribute::into_owned).collect())
+            }
+        }
+    }
+}
+
+/// A `fetch-att` as requested by the client
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum FetchAttribute<'a> {
+    Body,
+    BodySection {
+        /// `BODY.PEEK[...]` instead of `BODY[...]`
+        peek: bool,
+        section: Section<'a>,
+        /// `<origin.count>`
+        partial: Option<(u32, u32)>,
+    },
+    BodyStructure,
+    Envelope,
+    Flags,
+    InternalDate,
+    ModSeq, // RFC 4551
+    Rfc822,
+    Rfc822Header,
+    Rfc822Size,
+    Rfc822Text,
+    Uid,
+}
+
+impl<'a> FetchAttribute<'a> {
+    pub fn into_owned(self) -> FetchAttribute<'static> {
+        match self {
+            FetchAttribute::Body => FetchAttribute::Body,
+            FetchAttribute::BodySection {
+                peek,
+                section,
+                partial,
+            } => FetchAttribute::BodySection {
+                peek,
+                section: section.into_owned(),
+                partial,
+            },
+            FetchAttribute::BodyStructure => FetchAttribute::BodyStructure,
+            FetchAttribute::Envelope => FetchAttribute::Envelope,
+            FetchAttribute::Flags => FetchAttribute::Flags,
+            FetchAttribute::InternalDate => FetchAttribute::InternalDate,
+            FetchAttribute::ModSeq => FetchAttribute::ModSeq,
+            FetchAttribute::Rfc822 => FetchAttribute::Rfc822,
+            FetchAttribute::Rfc822Header => FetchAttribute::Rfc822Header,
+            FetchAttribute::Rfc822Size => FetchAttribute::Rfc822Size,
+            FetchAttribute::Rfc822Text => FetchAttribute::Rfc822Text,
+            FetchAttribute::Uid => FetchAttribute::Uid,
+        }
+    }
+}
+
+/// The `section` of a `BODY[...]` fetch attribute; `BODY[]` has an empty
+/// `part` and no `text`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Section<'a> {
+    pub part: Vec<u32>,
+    pub text: Option<SectionText<'a>>,
+}
+
+impl<'a> Section<'a> {
+    pub fn into_owned(self) -> Section<'static> {
+        Section {
+            part: self.part,
+            text: self.text.map(SectionText::into_owned),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SectionText<'a> {
+    Header,
+    HeaderFields(Vec<Cow<'a, str>>),
+    HeaderFieldsNot(Vec<Cow<'a, str>>),
+    Text,
+    Mime,
+}
+
+impl<'a> SectionText<'a> {
+    pub fn into_owned(self) -> SectionText<'static> {
+        match self {
+            SectionText::Header => SectionText::Header,
+            SectionText::HeaderFields(v) => {
+                SectionText::HeaderFields(v.into_iter().map(to_owned_cow).collect())
+            }
+            SectionText::HeaderFieldsNot(v) => {
+                SectionText::HeaderFieldsNot(v.into_iter().map(to_owned_cow).collect())
+            }
+            SectionText::Text => SectionText::Text,
+            SectionText::Mime => SectionText::Mime,
+        }
+    }
+}
+
+/// A `search-key` from the SEARCH command. Dates are kept in their
+/// `date-text` form, e.g. `1-Feb-1994`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum SearchKey<'a> {
+    All,
+    And(Vec<SearchKey<'a>>),
+    Answered,
+    Bcc(Cow<'a, str>),
+    Before(Cow<'a, str>),
+    Body(Cow<'a, str>),
+    Cc(Cow<'a, str>),
+    Deleted,
+    Draft,
+    Flagged,
+    From(Cow<'a, str>),
+    Header(Cow<'a, str>, Cow<'a, str>),
+    Keyword(Cow<'a, str>),
+    Larger(u32),
+    New,
+    Not(Box<SearchKey<'a>>),
+    Old,
+    On(Cow<'a, str>),
+    Or(Box<SearchKey<'a>>, Box<SearchKey<'a>>),
+    Recent,
+    Seen,
+    SentBefore(Cow<'a, str>),
+    SentOn(Cow<'a, str>),
+    SentSince(Cow<'a, str>),
+    SequenceSet(Vec<SequenceItem>),
+    Since(Cow<'a, str>),
+    Smaller(u32),
+    Subject(Cow<'a, str>),
+    Text(Cow<'a, str>),
+    To(Cow<'a, str>),
+    Uid(Vec<SequenceItem>),
+    Unanswered,
+    Undeleted,
+    Undraft,
+    Unflagged,
+    Unkeyword(Cow<'a, str>),
+    Unseen,
+}
+
+impl<'a> SearchKey<'a> {
+    pub fn into_owned(self) -> SearchKey<'static> {
+        match self {
+            SearchKey::All => SearchKey::All,
+            SearchKey::And(v) => SearchKey::And(v.into_iter().map(SearchKey::into_owned).collect()),
+            SearchKey::Answered => SearchKey::Answered,
+            SearchKey::Bcc(v) => SearchKey::Bcc(to_owned_cow(v)),
+            SearchKey::Before(v) => SearchKey::Before(to_owned_cow(v)),
+            SearchKey::Body(v) => SearchKey::Body(to_owned_cow(v)),
+            SearchKey::Cc(v) => SearchKey::Cc(to_owned_cow(v)),
+            SearchKey::Deleted => SearchKey::Deleted,
+            SearchKey::Draft => SearchKey::Draft,
+            SearchKey::Flagged => SearchKey::Flagged,
+            SearchKey::From(v) => SearchKey::From(to_owned_cow(v)),
+            SearchKey::Header(k, v) => SearchKey::Header(to_owned_cow(k), to_owned_cow(v)),
+            SearchKey::Keyword(v) => SearchKey::Keyword(to_owned_cow(v)),
+            SearchKey::Larger(v) => SearchKey::Larger(v),
+            SearchKey::New => SearchKey::New,
+            SearchKey::Not(k) => SearchKey::Not(Box::new(k.into_owned())),
+            SearchKey::Old => SearchKey::Old,
+            SearchKey::On(v) => SearchKey::On(to_owned_cow(v)),
+            SearchKey::Or(a, b) => {
+                SearchKey::Or(Box::new(a.into_owned()), Box::new(b.into_owned()))
+            }
+            SearchKey::Recent => SearchKey::Recent,
+            SearchKey::Seen => SearchKey::Seen,
+            SearchKey::SentBefore(v) => SearchKey::SentBefore(to_owned_cow(v)),
+            SearchKey::SentOn(v) => SearchKey::SentOn(to_owned_cow(v)),
+            SearchKey::SentSince(v) => SearchKey::SentSince(to_owned_cow(v)),
+            SearchKey::SequenceSet(v) => SearchKey::SequenceSet(v),
+            SearchKey::Since(v) => SearchKey::Since(to_owned_cow(v)),
+            SearchKey::Smaller(v) => SearchKey::Smaller(v),
+            SearchKey::Subject(v) => SearchKey::Subject(to_owned_cow(v)),
+            SearchKey::Text(v) => SearchKey::Text(to_owned_cow(v)),
+            SearchKey::To(v) => SearchKey::To(to_owned_cow(v)),
+            SearchKey::Uid(v) => SearchKey::Uid(v),
+            SearchKey::Unanswered => SearchKey::Unanswered,
+            SearchKey::Undeleted => SearchKey::Undeleted,
+            SearchKey::Undraft => SearchKey::Undraft,
+            SearchKey::Unflagged => SearchKey::Unflagged,
+            SearchKey::Unkeyword(v) => SearchKey::Unkeyword(to_owned_cow(v)),
+            SearchKey::Unseen => SearchKey::Unseen,
+        }
+    }
+}
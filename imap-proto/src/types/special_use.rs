@@ -0,0 +1,265 @@
+use super::{MailboxDatum, NameAttribute};
+
+// IMAP LIST Extension for Special-Use Mailboxes (rfc6154)
+
+/// The special use of a mailbox, like holding sent messages
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum SpecialUse {
+    All,
+    Archive,
+    Drafts,
+    Flagged,
+    Junk,
+    Sent,
+    Trash,
+}
+
+impl SpecialUse {
+    /// The special use indicated by a name attribute in a LIST or XLIST response
+    pub fn from_attribute(attr: &NameAttribute<'_>) -> Option<Self> {
+        Some(match attr {
+            NameAttribute::All => SpecialUse::All,
+            NameAttribute::Archive => SpecialUse::Archive,
+            NameAttribute::Drafts => SpecialUse::Drafts,
+            NameAttribute::Flagged => SpecialUse::Flagged,
+            NameAttribute::Junk => SpecialUse::Junk,
+            NameAttribute::Sent => SpecialUse::Sent,
+            NameAttribute::Trash => SpecialUse::Trash,
+            // Attributes only used in XLIST responses
+            NameAttribute::Extension(name) => match name.to_ascii_lowercase().as_str() {
+                "\\allmail" => SpecialUse::All,
+                "\\spam" => SpecialUse::Junk,
+                "\\starred" => SpecialUse::Flagged,
+                _ => return None,
+            },
+            _ => return None,
+        })
+    }
+
+    /// The attribute for this special use, as used in `CREATE ... (USE (...))`
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SpecialUse::All => "\\All",
+            SpecialUse::Archive => "\\Archive",
+            SpecialUse::Drafts => "\\Drafts",
+            SpecialUse::Flagged => "\\Flagged",
+            SpecialUse::Junk => "\\Junk",
+            SpecialUse::Sent => "\\Sent",
+            SpecialUse::Trash => "\\Trash",
+        }
+    }
+
+    /// Find the mailbox for this special use in LIST, LSUB or XLIST responses
+    ///
+    /// A mailbox with the attribute for this special use is preferred. Failing
+    /// that, the mailbox is found by its name, if it has a well-known name for
+    /// this use in one of a few languages. Mailboxes that cannot be selected are
+    /// skipped.
+    pub fn find<'b, 'a: 'b, I>(self, mailboxes: I) -> Option<&'b str>
+    where
+        I: IntoIterator<Item = &'b MailboxDatum<'a>>,
+    {
+        let mailboxes = mailboxes
+            .into_iter()
+            .filter_map(|datum| match datum {
+                MailboxDatum::List {
+                    name_attributes,
+                    delimiter,
+                    name,
+                } if !name_attributes.contains(&NameAttribute::NoSelect) => {
+                    Some((name_attributes, delimiter.as_deref(), name.as_ref()))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        let marked = mailboxes.iter().find(|(attrs, _, _)| {
+            attrs
+                .iter()
+                .any(|attr| Self::from_attribute(attr) == Some(self))
+        });
+        if let Some((_, _, name)) = marked {
+            return Some(name);
+        }
+
+        // Compare the last level of the hierarchy, so that `INBOX.Sent` is found
+        let leaves = mailboxes
+            .iter()
+            .filter_map(|(_, delimiter, name)| {
+                let leaf = delimiter.and_then(|d| name.rsplit(d).next());
+                let leaf = leaf.unwrap_or(name);
+                Some((decode_utf7(leaf)?.to_lowercase(), *name))
+            })
+            .collect::<Vec<_>>();
+        self.names().iter().find_map(|known| {
+            leaves
+                .iter()
+                .find(|(leaf, _)| leaf == known)
+                .map(|(_, name)| *name)
+        })
+    }
+
+    // Well-known (lowercase) mailbox names for this special use, most common first
+    fn names(self) -> &'static [&'static str] {
+        match self {
+            SpecialUse::All => &["all mail", "alle nachrichten", "tous les messages"],
+            SpecialUse::Archive => &[
+                "archive",
+                "archives",
+                "archiv",
+                "archivo",
+                "archivio",
+                "archief",
+                "arkiv",
+                "архив",
+            ],
+            SpecialUse::Drafts => &[
+                "drafts",
+                "draft",
+                "entwürfe",
+                "brouillons",
+                "borradores",
+                "bozze",
+                "concepten",
+                "utkast",
+                "черновики",
+            ],
+            SpecialUse::Flagged => &["flagged", "starred"],
+            SpecialUse::Junk => &[
+                "junk",
+                "spam",
+                "junk e-mail",
+                "junk email",
+                "bulk mail",
+                "junk-e-mail",
+                "courrier indésirable",
+                "correo no deseado",
+                "posta indesiderata",
+                "ongewenste e-mail",
+                "skräppost",
+                "спам",
+            ],
+            SpecialUse::Sent => &[
+                "sent",
+                "sent items",
+                "sent mail",
+                "sent messages",
+                "gesendet",
+                "gesendete elemente",
+                "envoyés",
+                "éléments envoyés",
+                "enviados",
+                "elementos enviados",
+                "inviati",
+                "posta inviata",
+                "verzonden",
+                "verzonden items",
+                "skickat",
+                "отправленные",
+            ],
+            SpecialUse::Trash => &[
+                "trash",
+                "deleted items",
+                "deleted messages",
+                "bin",
+                "papierkorb",
+                "gelöschte elemente",
+                "corbeille",
+                "éléments supprimés",
+                "papelera",
+                "elementos eliminados",
+                "cestino",
+                "prullenbak",
+                "papperskorgen",
+                "корзина",
+            ],
+        }
+    }
+}
+
+// Decode a mailbox name in modified UTF-7 (RFC 3501 section 5.1.3)
+fn decode_utf7(name: &str) -> Option<String> {
+    let mut out = String::with_capacity(name.len());
+    let mut rest = name;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        let end = start + rest[start..].find('-')?;
+        let encoded = &rest[start + 1..end];
+        if encoded.is_empty() {
+            out.push('&');
+        } else {
+            let (mut bits, mut len) = (0u32, 0);
+            let mut units = Vec::new();
+            for c in encoded.bytes() {
+                let value = match c {
+                    b'A'..=b'Z' => c - b'A',
+                    b'a'..=b'z' => c - b'a' + 26,
+                    b'0'..=b'9' => c - b'0' + 52,
+                    b'+' => 62,
+                    b',' => 63,
+                    _ => return None,
+                };
+                bits = (bits << 6) | u32::from(value);
+                len += 6;
+                if len >= 16 {
+                    len -= 16;
+                    units.push((bits >> len) as u16);
+                    bits &= (1 << len) - 1;
+                }
+            }
+            out.push_str(&String::from_utf16(&units).ok()?);
+        }
+        rest = &rest[end + 1..];
+    }
+    out.push_str(rest);
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::borrow::Cow;
+
+    fn list(attrs: &[NameAttribute<'static>], name: &'static str) -> MailboxDatum<'static> {
+        MailboxDatum::List {
+            name_attributes: attrs.to_vec(),
+            delimiter: Some(Cow::Borrowed(".")),
+            name: Cow::Borrowed(name),
+        }
+    }
+
+    #[test]
+    fn test_find() {
+        let mailboxes = [
+            list(&[], "INBOX"),
+            list(&[], "INBOX.Sent"),
+            list(&[NameAttribute::Sent], "INBOX.Sent Items"),
+            list(&[NameAttribute::NoSelect], "INBOX.Trash"),
+            list(&[], "INBOX.Papierkorb"),
+            list(&[], "INBOX.Entw&APw-rfe"),
+            list(
+                &[NameAttribute::Extension(Cow::Borrowed("\\Spam"))],
+                "[Gmail].Spam",
+            ),
+        ];
+        assert_eq!(SpecialUse::Sent.find(&mailboxes), Some("INBOX.Sent Items"));
+        assert_eq!(SpecialUse::Trash.find(&mailboxes), Some("INBOX.Papierkorb"));
+        assert_eq!(
+            SpecialUse::Drafts.find(&mailboxes),
+            Some("INBOX.Entw&APw-rfe")
+        );
+        assert_eq!(SpecialUse::Junk.find(&mailboxes), Some("[Gmail].Spam"));
+        assert_eq!(SpecialUse::Archive.find(&mailboxes), None);
+    }
+
+    #[test]
+    fn test_decode_utf7() {
+        assert_eq!(decode_utf7("Entw&APw-rfe").unwrap(), "Entwürfe");
+        assert_eq!(
+            decode_utf7("&BB4EQgQ,BEAEMAQyBDsENQQ9BD0ESwQ1-").unwrap(),
+            "Отправленные"
+        );
+        assert_eq!(decode_utf7("Tom &- Jerry").unwrap(), "Tom & Jerry");
+        assert_eq!(decode_utf7("&APw"), None);
+    }
+}
@@ -1,34 +1,77 @@
 use std::borrow::Cow;
+use std::collections::hash_map::RandomState;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
 use std::io;
 use std::net::ToSocketAddrs;
 use std::pin::Pin;
 use std::sync::Arc;
 use std::task::{ready, Context, Poll};
+use std::time::Duration;
 
 use futures_sink::Sink;
-use futures_util::{Stream, StreamExt};
+use futures_util::{Stream, StreamExt, TryStreamExt};
 use pin_project_lite::pin_project;
 use rustls_pki_types::ServerName;
 use tokio::io::{AsyncRead, AsyncWrite};
 use tokio::net::TcpStream;
+use tokio::time::{Instant, Sleep};
 use tokio_rustls::rustls::ClientConfig;
 use tokio_rustls::{client::TlsStream, TlsConnector};
+#[cfg(feature = "compress")]
+use tokio_util::codec::FramedParts;
 use tokio_util::codec::{Decoder, Framed};
 
-use crate::codec::{ImapCodec, ResponseData};
-use imap_proto::builders::command::Command;
-use imap_proto::{Request, RequestId, State};
+use crate::codec::{ImapCodec, Limits, ResponseData, ResponseFrame};
+#[cfg(feature = "compress")]
+use crate::deflate::Deflate;
+use imap_proto::builders::command::{Command, CommandBuilder};
+use imap_proto::parser::core::is_astring_char;
+use imap_proto::{Request, RequestId, Response, State, Status};
 
 pub type TlsClient = Client<TlsStream<TcpStream>>;
 
 pub struct Client<T> {
     transport: Framed<T, ImapCodec>,
     state: State,
-    request_ids: IdGenerator,
+    timeouts: Timeouts,
+    /// When a command was last sent or a response received
+    last_activity: Instant,
+    request_ids: TagGenerator,
+    /// Commands that were sent, but not completed yet, in order
+    outstanding: Vec<Outstanding>,
+    /// Command whose synchronizing literal the server is still waiting for
+    literal_pending: Option<RequestId>,
+    literal_threshold: usize,
 }
 
 impl TlsClient {
     pub async fn connect(server: &str) -> io::Result<(ResponseData, Self)> {
+        Self::connect_with(server, Timeouts::default()).await
+    }
+
+    /// Connect to the server, using the given timeouts for the connection and
+    /// all later commands
+    pub async fn connect_with(
+        server: &str,
+        timeouts: Timeouts,
+    ) -> io::Result<(ResponseData, Self)> {
+        let connect = Self::connect_tls(server);
+        let stream = match timeouts.connect {
+            Some(duration) => match tokio::time::timeout(duration, connect).await {
+                Ok(stream) => stream?,
+                Err(_) => return Err(timed_out("connecting")),
+            },
+            None => connect.await?,
+        };
+
+        let mut client = Client::new(stream);
+        client.set_timeouts(timeouts);
+        let greeting = client.read_greeting().await?;
+        Ok((greeting, client))
+    }
+
+    async fn connect_tls(server: &str) -> io::Result<TlsStream<TcpStream>> {
         let addr = (server, 993)
             .to_socket_addrs()?
             .next()
@@ -44,82 +87,503 @@ impl TlsClient {
         ));
 
         let stream = TcpStream::connect(&addr).await?;
-        let stream = connector
+        connector
             .connect(ServerName::try_from(server).unwrap().to_owned(), stream)
-            .await?;
-        let mut transport = ImapCodec::default().framed(stream);
-
-        let greeting = match transport.next().await {
-            Some(greeting) => Ok(greeting),
-            None => Err(io::Error::other("no greeting found")),
-        }?;
-        let client = Client {
-            transport,
+            .await
+    }
+}
+
+impl<T> Client<T>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Set up a client without reading the server greeting
+    ///
+    /// Use this if the greeting has already been consumed, or to configure the
+    /// client (for example, with `set_limits()`) before calling `read_greeting()`.
+    /// The client starts out in the `NotAuthenticated` state.
+    pub fn new(stream: T) -> Self {
+        Client {
+            transport: ImapCodec::default().framed(stream),
             state: State::NotAuthenticated,
-            request_ids: IdGenerator::new(),
+            timeouts: Timeouts::default(),
+            last_activity: Instant::now(),
+            request_ids: TagGenerator::new(),
+            outstanding: Vec::new(),
+            literal_pending: None,
+            literal_threshold: DEFAULT_LITERAL_THRESHOLD,
+        }
+    }
+
+    /// Set up a client on a freshly opened connection, reading the server greeting
+    ///
+    /// This works with any transport: TLS or plaintext TCP sockets, Unix sockets,
+    /// proxy tunnels or in-memory streams. See `read_greeting()` for how the
+    /// greeting is handled.
+    pub async fn from_stream(stream: T) -> io::Result<(ResponseData, Self)> {
+        let mut client = Self::new(stream);
+        let greeting = client.read_greeting().await?;
+        Ok((greeting, client))
+    }
+
+    /// Read the server greeting
+    ///
+    /// A `PREAUTH` greeting puts the client in the `Authenticated` state; a `BYE`
+    /// greeting results in an error.
+    pub async fn read_greeting(&mut self) -> io::Result<ResponseData> {
+        let next = match self.timeouts.greeting {
+            Some(duration) => match tokio::time::timeout(duration, self.transport.next()).await {
+                Ok(next) => next,
+                Err(_) => return Err(timed_out("waiting for the greeting")),
+            },
+            None => self.transport.next().await,
+        };
+
+        self.last_activity = Instant::now();
+        let greeting = match next {
+            Some(Ok(ResponseFrame::Response(greeting))) => greeting,
+            Some(Ok(_)) => {
+                return Err(io::Error::other(
+                    "unexpected frame while waiting for greeting",
+                ))
+            }
+            Some(Err(e)) => return Err(e),
+            None => return Err(io::Error::other("no greeting found")),
+        };
+
+        match greeting.parsed() {
+            Response::Data {
+                status: Status::Ok, ..
+            } => {}
+            Response::Data {
+                status: Status::PreAuth,
+                ..
+            } => self.state = State::Authenticated,
+            Response::Data {
+                status: Status::Bye,
+                information,
+                ..
+            } => {
+                return Err(io::Error::new(
+                    io::ErrorKind::ConnectionRefused,
+                    format!(
+                        "server rejected connection: {}",
+                        information.as_deref().unwrap_or_default()
+                    ),
+                ))
+            }
+            rsp => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("unexpected greeting: {rsp:?}"),
+                ))
+            }
+        }
+
+        Ok(greeting)
+    }
+
+    /// Set limits on the size of responses accepted from the server
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.transport.codec_mut().limits = limits;
+    }
+
+    /// Set timeouts for commands and the idle time before `keepalive()` sends a NOOP
+    pub fn set_timeouts(&mut self, timeouts: Timeouts) {
+        self.timeouts = timeouts;
+    }
+
+    /// Keep the connection alive by sending a `NOOP` after the idle timeout
+    ///
+    /// Waits until nothing has been sent or received for the idle timeout, then
+    /// sends a `NOOP` and returns its responses, which may contain updates from
+    /// the server such as new messages. This does not run in the background:
+    /// since it borrows the client, the caller has to interleave it with other
+    /// commands, for example by awaiting it whenever there is no other work.
+    /// It is safe to cancel while waiting. If no idle timeout is set, it never
+    /// completes.
+    pub async fn keepalive(&mut self) -> io::Result<Vec<ResponseData>> {
+        let idle = match self.timeouts.idle {
+            Some(idle) => idle,
+            None => return std::future::pending().await,
         };
 
-        greeting.map(|greeting| (greeting, client))
+        loop {
+            let deadline = self.last_activity + idle;
+            if Instant::now() >= deadline {
+                break;
+            }
+            tokio::time::sleep_until(deadline).await;
+        }
+
+        self.call(CommandBuilder::noop()).try_collect().await
+    }
+
+    /// Set the size from which literals are streamed by `call_streaming()`
+    ///
+    /// Defaults to 1 MiB.
+    pub fn set_literal_threshold(&mut self, threshold: usize) {
+        self.literal_threshold = threshold;
     }
 
-    pub fn call<C: Into<Command>>(&mut self, cmd: C) -> ResponseStream<'_, TlsStream<TcpStream>> {
-        let request_id = self.request_ids.next().unwrap(); // safe: never returns Err,
+    /// Set how tags for subsequent commands are generated
+    pub fn set_tag_generator(&mut self, tags: TagGenerator) {
+        self.request_ids = tags;
+    }
+
+    /// Send a command and stream the responses to it
+    ///
+    /// Data for synchronizing literals (`{n}`) in the command is only sent after
+    /// the server asks for it with a continuation request. If a previous stream
+    /// was dropped while the server was waiting for such data, the connection
+    /// can't be used anymore, and the returned stream only yields an error.
+    pub fn call<C: Into<Command>>(&mut self, cmd: C) -> ResponseStream<'_, T> {
+        let request_id = (0..MAX_TAG_ATTEMPTS)
+            .map(|_| self.request_ids.next().unwrap()) // safe: never returns None
+            .find(|id| !self.outstanding.iter().any(|cmd| cmd.id == *id));
+        let (request_id, state) = match request_id {
+            _ if self.literal_pending.is_some() => (
+                RequestId(String::new()),
+                ResponseStreamState::Failed("server is still waiting for literal data"),
+            ),
+            Some(id) => (id, ResponseStreamState::Start),
+            None => (
+                RequestId(String::new()),
+                ResponseStreamState::Failed("no unused tag available for command"),
+            ),
+        };
         ResponseStream {
             client: self,
             request_id,
             cmd: cmd.into(),
-            state: ResponseStreamState::Start,
+            next: Some(0),
+            state,
+            streaming: false,
+            timer: None,
+        }
+    }
+
+    /// Like `call()`, but streams large literals in FETCH responses
+    ///
+    /// Literals of at least the literal threshold are yielded in chunks as they
+    /// arrive, instead of being buffered in memory in full. See `ResponseFrame`
+    /// for the sequence of frames produced.
+    pub fn call_streaming<C: Into<Command>>(&mut self, cmd: C) -> ResponseFrames<'_, T> {
+        let mut inner = self.call(cmd);
+        inner.streaming = true;
+        ResponseFrames { inner }
+    }
+
+    /// Enable `COMPRESS DEFLATE` (RFC 4978) on the connection
+    ///
+    /// After the server accepts the command, all further data is compressed in
+    /// both directions. If the command fails, the uncompressed client is handed
+    /// back along with the error; unless the error came from the connection
+    /// itself, the client can still be used.
+    #[cfg(feature = "compress")]
+    pub async fn compress(mut self) -> Result<Client<Deflate<T>>, (Self, io::Error)> {
+        let cmd = CommandBuilder::compress_deflate();
+        let responses = match self.call(cmd).try_collect::<Vec<_>>().await {
+            Ok(responses) => responses,
+            Err(e) => return Err((self, e)),
+        };
+        match responses.last().map(|rsp| rsp.parsed()) {
+            Some(Response::Done {
+                status: Status::Ok, ..
+            }) => {}
+            rsp => {
+                let e = io::Error::other(format!("COMPRESS DEFLATE failed: {rsp:?}"));
+                return Err((self, e));
+            }
+        }
+
+        // The server may have sent compressed data along with the completion
+        let parts = self.transport.into_parts();
+        let stream = Deflate::new(parts.io, parts.read_buf);
+        Ok(Client {
+            transport: Framed::from_parts(FramedParts::new(stream, parts.codec)),
+            state: self.state,
+            timeouts: self.timeouts,
+            last_activity: self.last_activity,
+            request_ids: self.request_ids,
+            outstanding: self.outstanding,
+            literal_pending: self.literal_pending,
+            literal_threshold: self.literal_threshold,
+        })
+    }
+}
+
+impl<T> Client<T> {
+    /// The protocol state, as of the last completed command
+    ///
+    /// This is `Logout` once the server has sent `BYE`.
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    /// Mark the command with the given tag as completed, updating the state
+    // The state only changes if the command succeeded (`ok`)
+    fn complete(&mut self, id: &RequestId, ok: bool) -> Option<Outstanding> {
+        let pos = self.outstanding.iter().position(|cmd| cmd.id == *id)?;
+        let cmd = self.outstanding.remove(pos);
+        if self.literal_pending.as_ref() == Some(id) {
+            self.literal_pending = None;
+        }
+        match cmd.next_state {
+            Some(next_state) if ok && self.state != State::Logout => self.state = next_state,
+            _ => {}
+        }
+        Some(cmd)
+    }
+}
+
+/// A command that was sent to the server, but has not completed yet
+///
+/// If its `ResponseStream` is dropped before completion, any remaining
+/// responses are discarded by the next `ResponseStream` polled, which still
+/// applies the state change on completion.
+struct Outstanding {
+    id: RequestId,
+    next_state: Option<State>,
+}
+
+const DEFAULT_LITERAL_THRESHOLD: usize = 1024 * 1024;
+// Tags to try before giving up on finding one that is not in use
+const MAX_TAG_ATTEMPTS: usize = 1000;
+
+// Find the end of the part of a command that starts at `start` and can be sent
+// right away: up to the CRLF after the next synchronizing literal header, or
+// up to the end. Also returns where the next part starts, if there is one.
+fn next_part(args: &[u8], start: usize) -> (usize, Option<usize>) {
+    let mut from = start;
+    while let Some(pos) = args[from..].windows(2).position(|w| w == b"\r\n") {
+        let end = from + pos;
+        let header = literal_header(&args[from..end]);
+        from = end + 2;
+        let (size, sync) = match header {
+            Some(header) => header,
+            None => continue,
+        };
+        match sync {
+            true => return (end, Some(from)),
+            // Skip over the data of non-synchronizing literals
+            false => from = from.saturating_add(size).min(args.len()),
         }
     }
+    (args.len(), None)
+}
+
+// The size of the literal announced at the end of `line`, and whether it is
+// synchronizing (`{n}`, as opposed to `{n+}`)
+fn literal_header(line: &[u8]) -> Option<(usize, bool)> {
+    let line = line.strip_suffix(b"}")?;
+    let header = &line[line.iter().rposition(|&b| b == b'{')? + 1..];
+    let (digits, sync) = match header.strip_suffix(b"+") {
+        Some(digits) => (digits, false),
+        None => (header, true),
+    };
+    if digits.is_empty() || !digits.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    let size = std::str::from_utf8(digits).ok()?.parse().ok()?;
+    Some((size, sync))
+}
+
+/// Timeouts used by the `Client`
+///
+/// No timeouts are set by default.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Timeouts {
+    connect: Option<Duration>,
+    greeting: Option<Duration>,
+    command: Option<Duration>,
+    idle: Option<Duration>,
+}
+
+impl Timeouts {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Time allowed to set up the connection, including the TLS handshake
+    pub fn connect(mut self, timeout: Duration) -> Self {
+        self.connect = Some(timeout);
+        self
+    }
+
+    /// Time allowed for the server to send its greeting
+    pub fn greeting(mut self, timeout: Duration) -> Self {
+        self.greeting = Some(timeout);
+        self
+    }
+
+    /// Time allowed for the server to respond while a command is in progress
+    ///
+    /// This applies to each response separately, so that commands returning
+    /// large amounts of data don't time out as long as data keeps coming in.
+    pub fn command(mut self, timeout: Duration) -> Self {
+        self.command = Some(timeout);
+        self
+    }
+
+    /// Time the connection may be idle before `Client::keepalive()` sends a NOOP
+    ///
+    /// Servers may disconnect clients that have been idle for 30 minutes, and
+    /// NAT devices tend to drop idle connections much sooner.
+    pub fn idle(mut self, timeout: Duration) -> Self {
+        self.idle = Some(timeout);
+        self
+    }
+}
+
+fn timed_out(what: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::TimedOut, format!("timed out {what}"))
 }
 
 pin_project! {
+    /// Stream of responses returned by `Client::call()`
+    ///
+    /// It is safe to drop the stream before the command completes, for example
+    /// when a timeout expires: the responses that are still to come are then
+    /// discarded by the next command's stream. The exception is a command with
+    /// synchronizing literals, while the server waits for the literal data: the
+    /// client then fails all later commands, since the server would take them
+    /// for that data.
     pub struct ResponseStream<'a, T> {
         #[pin]
         client: &'a mut Client<T>,
         request_id: RequestId,
         cmd: Command,
+        // Start of the part of the command that still has to be sent, if any
+        next: Option<usize>,
         state: ResponseStreamState,
+        streaming: bool,
+        // Expires when the server takes too long to respond
+        timer: Option<Pin<Box<Sleep>>>,
     }
 }
 
-impl<'a, T> Stream for ResponseStream<'a, T>
+impl<'a, T> ResponseStream<'a, T>
 where
     T: AsyncRead + AsyncWrite + Unpin,
 {
-    type Item = Result<ResponseData, io::Error>;
-
-    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+    ) -> Poll<Option<Result<ResponseFrame, io::Error>>> {
         let mut me = self.project();
         loop {
             match me.state {
                 ResponseStreamState::Start => {
                     ready!(Pin::new(&mut me.client.transport).poll_ready(cx))?;
+                    let start = me.next.unwrap_or_default();
+                    let (end, next) = next_part(&me.cmd.args, start);
+                    *me.next = next;
+                    me.client.literal_pending = next.map(|_| me.request_id.clone());
+                    if start > 0 {
+                        // The rest of the command, after a continuation request
+                        let buf = me.client.transport.write_buffer_mut();
+                        buf.extend_from_slice(&me.cmd.args[start..end]);
+                        buf.extend_from_slice(b"\r\n");
+                        *me.state = ResponseStreamState::Sending;
+                        continue;
+                    }
+
+                    let threshold = me.streaming.then_some(me.client.literal_threshold);
+                    me.client.transport.codec_mut().literal_threshold = threshold;
                     let pinned = Pin::new(&mut me.client.transport);
                     pinned.start_send(&Request(
                         Cow::Borrowed(me.request_id.as_bytes()),
-                        Cow::Borrowed(&me.cmd.args),
+                        Cow::Borrowed(&me.cmd.args[..end]),
                     ))?;
+                    me.client.outstanding.push(Outstanding {
+                        id: me.request_id.clone(),
+                        next_state: me.cmd.next_state,
+                    });
+                    if let Some(duration) = me.client.timeouts.command {
+                        *me.timer = Some(Box::pin(tokio::time::sleep(duration)));
+                    }
                     *me.state = ResponseStreamState::Sending;
                 }
                 ResponseStreamState::Sending => {
                     let pinned = Pin::new(&mut me.client.transport);
-                    ready!(pinned.poll_flush(cx))?;
+                    if pinned.poll_flush(cx)?.is_pending() {
+                        return poll_timer(me.timer, me.state, cx);
+                    }
+                    me.client.last_activity = Instant::now();
                     *me.state = ResponseStreamState::Receiving;
                 }
                 ResponseStreamState::Receiving => {
-                    match ready!(Pin::new(&mut me.client.transport).poll_next(cx)) {
-                        Some(Ok(rsp)) => {
+                    // Anything received before the completion of commands sent
+                    // earlier belongs to a `ResponseStream` that was dropped
+                    let stale = match me.client.outstanding.first() {
+                        Some(first) => first.id != *me.request_id,
+                        None => false,
+                    };
+
+                    let next = match Pin::new(&mut me.client.transport).poll_next(cx) {
+                        Poll::Ready(next) => next,
+                        Poll::Pending => return poll_timer(me.timer, me.state, cx),
+                    };
+
+                    me.client.last_activity = Instant::now();
+                    if let (Some(timer), Some(duration)) =
+                        (me.timer.as_mut(), me.client.timeouts.command)
+                    {
+                        timer.as_mut().reset(Instant::now() + duration);
+                    }
+
+                    match next {
+                        Some(Ok(ResponseFrame::Response(rsp)))
+                            if !stale
+                                && me.next.is_some()
+                                && matches!(rsp.parsed(), Response::Continue { .. }) =>
+                        {
+                            *me.state = ResponseStreamState::Start;
+                        }
+                        Some(Ok(ResponseFrame::Response(rsp))) => {
+                            // The server is about to close the connection
+                            if let Response::Data {
+                                status: Status::Bye,
+                                ..
+                            } = rsp.parsed()
+                            {
+                                me.client.state = State::Logout;
+                            }
+
+                            let ok = matches!(
+                                rsp.parsed(),
+                                Response::Done {
+                                    status: Status::Ok,
+                                    ..
+                                }
+                            );
                             match rsp.request_id() {
                                 Some(req_id) if req_id == me.request_id => {}
-                                Some(_) | None => return Poll::Ready(Some(Ok(rsp))),
+                                Some(req_id) => {
+                                    if me.client.complete(req_id, ok).is_some() {
+                                        continue;
+                                    }
+                                    return Poll::Ready(Some(Ok(ResponseFrame::Response(rsp))));
+                                }
+                                None if stale => continue,
+                                None => return Poll::Ready(Some(Ok(ResponseFrame::Response(rsp)))),
                             }
 
-                            if let Some(next_state) = me.cmd.next_state.as_ref() {
-                                me.client.state = *next_state;
-                            }
+                            me.client.complete(me.request_id, ok);
+                            *me.state = ResponseStreamState::Done;
+                            return Poll::Ready(Some(Ok(ResponseFrame::Response(rsp))));
+                        }
+                        Some(Ok(_)) if stale => continue,
+                        Some(Ok(frame)) => return Poll::Ready(Some(Ok(frame))),
+                        // After sending BYE in response to LOGOUT, servers may
+                        // close the connection without completing the command
+                        Some(Err(_)) | None
+                            if me.cmd.next_state == Some(State::Logout)
+                                && me.client.state == State::Logout =>
+                        {
+                            me.client.complete(me.request_id, true);
                             *me.state = ResponseStreamState::Done;
-                            return Poll::Ready(Some(Ok(rsp)));
                         }
                         Some(Err(e)) => return Poll::Ready(Some(Err(e))),
                         None => {
@@ -129,6 +593,11 @@ where
                         }
                     }
                 }
+                ResponseStreamState::Failed(msg) => {
+                    let e = io::Error::other(*msg);
+                    *me.state = ResponseStreamState::Done;
+                    return Poll::Ready(Some(Err(e)));
+                }
                 ResponseStreamState::Done => {
                     return Poll::Ready(None);
                 }
@@ -137,33 +606,629 @@ where
     }
 }
 
+// Fail the command if the timer expired; `Pending` otherwise
+fn poll_timer(
+    timer: &mut Option<Pin<Box<Sleep>>>,
+    state: &mut ResponseStreamState,
+    cx: &mut Context,
+) -> Poll<Option<Result<ResponseFrame, io::Error>>> {
+    let timer = match timer {
+        Some(timer) => timer,
+        None => return Poll::Pending,
+    };
+
+    ready!(timer.as_mut().poll(cx));
+    *state = ResponseStreamState::Done;
+    Poll::Ready(Some(Err(timed_out("waiting for the server"))))
+}
+
+impl<'a, T> Stream for ResponseStream<'a, T>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    type Item = Result<ResponseData, io::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        loop {
+            match ready!(self.as_mut().poll_frame(cx)) {
+                Some(Ok(ResponseFrame::Response(rsp))) => return Poll::Ready(Some(Ok(rsp))),
+                // Only yielded by the codec when finishing a dropped `call_streaming()`
+                Some(Ok(_)) => {}
+                Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+                None => return Poll::Ready(None),
+            }
+        }
+    }
+}
+
+/// Stream of frames returned by `Client::call_streaming()`
+pub struct ResponseFrames<'a, T> {
+    inner: ResponseStream<'a, T>,
+}
+
+impl<'a, T> Stream for ResponseFrames<'a, T>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    type Item = Result<ResponseFrame, io::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.inner).poll_frame(cx)
+    }
+}
+
 enum ResponseStreamState {
     Start,
     Sending,
     Receiving,
+    // The command can't be sent, for the given reason
+    Failed(&'static str),
     Done,
 }
 
-pub struct IdGenerator {
+/// Generates the tags that identify commands sent by the `Client`
+///
+/// By default, tags are a prefix (`A`) followed by a sequence number that is
+/// zero-padded to a width of 4 digits. The sequence does not wrap around; once
+/// it exceeds the width, tags simply get longer. Random tags (of `width`
+/// alphanumeric characters) can be used to make collisions unlikely when
+/// several clients share a connection, for example through a proxy.
+///
+/// Regardless of the strategy, the `Client` skips tags that are still in use.
+#[derive(Clone, Debug)]
+pub struct TagGenerator {
+    prefix: String,
+    width: usize,
+    random: Option<u64>,
     next: u64,
 }
 
-impl IdGenerator {
+impl TagGenerator {
     pub fn new() -> Self {
-        Self { next: 0 }
+        Self {
+            prefix: "A".to_owned(),
+            width: 4,
+            random: None,
+            next: 0,
+        }
+    }
+
+    /// Set the prefix for all tags
+    ///
+    /// Panics if the prefix contains characters that are not allowed in tags.
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        let prefix = prefix.into();
+        assert!(
+            prefix.bytes().all(|c| c != b'+' && is_astring_char(c)),
+            "invalid tag prefix {prefix:?}"
+        );
+        self.prefix = prefix;
+        self
+    }
+
+    /// Set the (minimum) number of characters following the prefix
+    ///
+    /// Random tags always use at least 4 characters.
+    pub fn width(mut self, width: usize) -> Self {
+        self.width = width;
+        self
+    }
+
+    /// Use random characters instead of a sequence number
+    pub fn random(mut self) -> Self {
+        // Seeded from the randomly keyed hasher in std, to avoid a dependency
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u64(self.next);
+        self.random = Some(hasher.finish() | 1);
+        self
     }
 }
 
-impl Default for IdGenerator {
+// Random tags shorter than this would collide too often
+const MIN_RANDOM_WIDTH: usize = 4;
+
+impl Default for TagGenerator {
     fn default() -> Self {
         Self::new()
     }
 }
 
-impl Iterator for IdGenerator {
+impl Iterator for TagGenerator {
     type Item = RequestId;
     fn next(&mut self) -> Option<Self::Item> {
-        self.next += 1;
-        Some(RequestId(format!("A{:04}", self.next % 10_000)))
+        let state = match &mut self.random {
+            Some(state) => state,
+            None => {
+                self.next += 1;
+                let (prefix, next, width) = (&self.prefix, self.next, self.width);
+                return Some(RequestId(format!("{prefix}{next:0width$}")));
+            }
+        };
+
+        const CHARS: &[u8; 36] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+        let mut tag = self.prefix.clone();
+        for _ in 0..self.width.max(MIN_RANDOM_WIDTH) {
+            // xorshift64*
+            *state ^= *state >> 12;
+            *state ^= *state << 25;
+            *state ^= *state >> 27;
+            let n = state.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 32;
+            tag.push(CHARS[(n % 36) as usize] as char);
+        }
+        Some(RequestId(tag))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockMailbox, MockMessage, MockServer};
+    use imap_proto::{Attribute, AttributeValue, MailboxDatum, Metadata, ResponseCode, Status};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn session() {
+        let server = MockServer::new().user("djc", "s3cr3t").mailbox(
+            MockMailbox::new("INBOX")
+                .message(MockMessage::new("Subject: one\r\n\r\nfirst"))
+                .message(MockMessage::new("Subject: two\r\n\r\nsecond")),
+        );
+        let (stream, handle) = server.connect();
+        let (_, mut client) = Client::from_stream(stream).await.unwrap();
+        assert_eq!(client.state(), State::NotAuthenticated);
+
+        let rsp = client
+            .call(CommandBuilder::login("djc", "s3cr3t"))
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(rsp.len(), 1);
+        assert_eq!(client.state(), State::Authenticated);
+
+        client
+            .call(CommandBuilder::select("INBOX"))
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(client.state(), State::Selected);
+
+        let cmd = CommandBuilder::uid_fetch()
+            .range_from(1_u32..)
+            .attr(Attribute::Uid)
+            .attr(Attribute::Rfc822Size);
+        let rsp = client.call(cmd).try_collect::<Vec<_>>().await.unwrap();
+        assert_eq!(rsp.len(), 3);
+        match rsp[1].parsed() {
+            Response::Fetch(2, attrs) => {
+                assert_eq!(attrs[0], AttributeValue::Uid(2));
+                assert_eq!(attrs[1], AttributeValue::Rfc822Size(22));
+            }
+            rsp => panic!("unexpected response: {rsp:?}"),
+        }
+
+        drop(client);
+        handle.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn streaming() {
+        let body = "Subject: large\r\n\r\n".to_owned() + &"x".repeat(100);
+        let server = MockServer::new()
+            .mailbox(MockMailbox::new("INBOX").message(MockMessage::new(body.clone())));
+        let (stream, _) = server.connect();
+        let (_, mut client) = Client::from_stream(stream).await.unwrap();
+        client.set_literal_threshold(64);
+        let login = CommandBuilder::login("djc", "s3cr3t");
+        client.call(login).try_collect::<Vec<_>>().await.unwrap();
+        let select = CommandBuilder::select("INBOX");
+        client.call(select).try_collect::<Vec<_>>().await.unwrap();
+
+        let cmd = CommandBuilder::uid_fetch().num(1).attr(Attribute::Rfc822);
+        let frames = client
+            .call_streaming(cmd)
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        match &frames[0] {
+            ResponseFrame::FetchLiteral {
+                message: 1,
+                attribute,
+                size,
+            } => {
+                assert_eq!(attribute, "RFC822");
+                assert_eq!(*size, body.len());
+            }
+            frame => panic!("unexpected frame {frame:?}"),
+        }
+        let mut data = Vec::new();
+        for frame in &frames[1..frames.len() - 2] {
+            match frame {
+                ResponseFrame::LiteralChunk(chunk) => data.extend_from_slice(chunk),
+                frame => panic!("unexpected frame {frame:?}"),
+            }
+        }
+        assert_eq!(data, body.as_bytes());
+        match &frames[frames.len() - 2] {
+            ResponseFrame::Response(rsp) => {
+                assert!(matches!(rsp.parsed(), Response::Fetch(1, _)));
+            }
+            frame => panic!("unexpected frame {frame:?}"),
+        }
+
+        // Literal streaming only applies to `call_streaming()`
+        let cmd = CommandBuilder::uid_fetch().num(1).attr(Attribute::Rfc822);
+        let rsp = client.call(cmd).try_collect::<Vec<_>>().await.unwrap();
+        assert_eq!(rsp.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn greeting() {
+        let (stream, _) = MockServer::new()
+            .greeting("* PREAUTH Welcome back")
+            .connect();
+        let (_, client) = Client::from_stream(stream).await.unwrap();
+        assert_eq!(client.state(), State::Authenticated);
+
+        let (stream, _) = MockServer::new()
+            .greeting("* BYE Too many connections")
+            .connect();
+        let err = Client::from_stream(stream).await.err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionRefused);
+    }
+
+    #[test]
+    fn tags() {
+        let tags = TagGenerator::new().prefix("X").width(2).skip(98);
+        let tags = tags.take(3).map(|id| id.0).collect::<Vec<_>>();
+        assert_eq!(tags, ["X99", "X100", "X101"]);
+
+        let mut tags = TagGenerator::new().prefix("P").width(8).random();
+        let (a, b) = (tags.next().unwrap().0, tags.next().unwrap().0);
+        assert_ne!(a, b);
+        assert_eq!(a.len(), 9);
+        assert!(a.starts_with('P'));
+        assert!(a[1..].bytes().all(|c| c.is_ascii_alphanumeric()));
+
+        let mut tags = TagGenerator::new().width(0).random();
+        assert_eq!(tags.next().unwrap().0.len(), 5);
+    }
+
+    #[tokio::test]
+    async fn outstanding_tags() {
+        let (stream, _) = MockServer::new().connect();
+        let (_, mut client) = Client::from_stream(stream).await.unwrap();
+        client.outstanding.push(Outstanding {
+            id: RequestId("A0001".to_owned()),
+            next_state: None,
+        });
+
+        let rsp = client
+            .call(CommandBuilder::login("djc", "s3cr3t"))
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let tag = rsp.last().unwrap().request_id();
+        assert_eq!(tag, Some(&RequestId("A0002".to_owned())));
+        assert_eq!(client.outstanding.len(), 1);
+        assert_eq!(client.outstanding[0].id.0, "A0001");
+
+        // Give up instead of looping when all candidate tags are in use
+        let next = client.request_ids.clone();
+        for id in next.take(MAX_TAG_ATTEMPTS) {
+            client.outstanding.push(Outstanding {
+                id,
+                next_state: None,
+            });
+        }
+        let err = client
+            .call(CommandBuilder::noop())
+            .try_next()
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "no unused tag available for command");
+    }
+
+    #[tokio::test]
+    async fn dropped_stream() {
+        let server = MockServer::new().mailbox(
+            MockMailbox::new("INBOX")
+                .message(MockMessage::new("Subject: one\r\n\r\nfirst"))
+                .message(MockMessage::new("Subject: two\r\n\r\nsecond")),
+        );
+        let (stream, _) = server.connect();
+        let (_, mut client) = Client::from_stream(stream).await.unwrap();
+        let login = CommandBuilder::login("djc", "s3cr3t");
+        client.call(login).try_collect::<Vec<_>>().await.unwrap();
+
+        // Drop a SELECT after the first untagged response
+        let mut select = client.call(CommandBuilder::select("INBOX"));
+        select.next().await.unwrap().unwrap();
+        drop(select);
+        assert_eq!(client.state(), State::Authenticated);
+
+        // Drop a FETCH before it has even been flushed
+        let cmd = CommandBuilder::fetch()
+            .range_from(1_u32..)
+            .attr(Attribute::Uid);
+        drop(client.call(cmd));
+
+        let cmd = CommandBuilder::fetch()
+            .range_from(1_u32..)
+            .attr(Attribute::Uid);
+        let mut fetch = client.call(cmd);
+        fetch.next().await.unwrap().unwrap();
+        drop(fetch);
+
+        let rsp = client
+            .call(CommandBuilder::check())
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(rsp.len(), 1);
+        assert!(matches!(
+            rsp[0].parsed(),
+            Response::Done {
+                status: Status::Ok,
+                ..
+            }
+        ));
+        assert_eq!(client.state(), State::Selected);
+        assert!(client.outstanding.is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn timeouts() {
+        let (stream, mut server) = tokio::io::duplex(1024);
+        let mut client = Client::new(stream);
+        client.set_timeouts(Timeouts::new().greeting(Duration::from_secs(5)));
+        let err = client.read_greeting().await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+        // The server accepts the command, but never responds
+        server.write_all(b"* OK ready\r\n").await.unwrap();
+        client.read_greeting().await.unwrap();
+        client.set_timeouts(Timeouts::new().command(Duration::from_secs(5)));
+        let start = Instant::now();
+        let mut check = client.call(CommandBuilder::check());
+        let err = check.next().await.unwrap().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert!(check.next().await.is_none());
+        assert_eq!(start.elapsed(), Duration::from_secs(5));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn keepalive() {
+        let (stream, _) = MockServer::new().connect();
+        let (_, mut client) = Client::from_stream(stream).await.unwrap();
+        client.set_timeouts(Timeouts::new().idle(Duration::from_secs(60)));
+
+        let start = Instant::now();
+        let rsp = client.keepalive().await.unwrap();
+        assert!(start.elapsed() >= Duration::from_secs(60));
+        assert!(matches!(
+            rsp.last().unwrap().parsed(),
+            Response::Done {
+                status: Status::Ok,
+                ..
+            }
+        ));
+
+        // Cancelling the keepalive doesn't affect later commands
+        let timeout = tokio::time::timeout(Duration::from_secs(30), client.keepalive()).await;
+        assert!(timeout.is_err());
+        let rsp = client
+            .call(CommandBuilder::login("djc", "s3cr3t"))
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(rsp.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn logout() {
+        let (stream, _) = MockServer::new().connect();
+        let (_, mut client) = Client::from_stream(stream).await.unwrap();
+        let rsp = client
+            .call(CommandBuilder::logout())
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(rsp.len(), 2);
+        assert_eq!(client.state(), State::Logout);
+
+        // Some servers close the connection right after sending BYE
+        let (stream, mut server) = tokio::io::duplex(4096);
+        let server = tokio::spawn(async move {
+            server.write_all(b"* OK ready\r\n").await.unwrap();
+            let mut buf = vec![0; 1024];
+            let n = server.read(&mut buf).await.unwrap();
+            assert_eq!(&buf[..n], b"A0001 LOGOUT\r\n");
+            server.write_all(b"* BYE Logging out\r\n").await.unwrap();
+        });
+        let (_, mut client) = Client::from_stream(stream).await.unwrap();
+        let rsp = client
+            .call(CommandBuilder::logout())
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(rsp.len(), 1);
+        assert_eq!(client.state(), State::Logout);
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn literals() {
+        let (stream, mut server) = tokio::io::duplex(4096);
+        let server = tokio::spawn(async move {
+            server.write_all(b"* OK ready\r\n").await.unwrap();
+            let mut buf = vec![0; 1024];
+            let n = server.read(&mut buf).await.unwrap();
+            assert_eq!(
+                &buf[..n],
+                b"A0001 SETMETADATA \"INBOX\" (\"/private/a\" {2}\r\n"
+            );
+            server.write_all(b"+ go ahead\r\n").await.unwrap();
+            let n = server.read(&mut buf).await.unwrap();
+            assert_eq!(&buf[..n], b"\r\n \"/private/b\" ~{1}\r\n");
+            server.write_all(b"+ go ahead\r\n").await.unwrap();
+            let n = server.read(&mut buf).await.unwrap();
+            assert_eq!(&buf[..n], b"\0)\r\n");
+            server.write_all(b"A0001 OK done\r\n").await.unwrap();
+        });
+
+        let (_, mut client) = Client::from_stream(stream).await.unwrap();
+        let values = [
+            Metadata {
+                entry: "/private/a".into(),
+                value: Some(Cow::Borrowed(b"\r\n")),
+            },
+            Metadata {
+                entry: "/private/b".into(),
+                value: Some(Cow::Borrowed(b"\0")),
+            },
+        ];
+        let rsp = client
+            .call(CommandBuilder::set_metadata("INBOX", &values))
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(rsp.len(), 1);
+        server.await.unwrap();
+
+        // Non-synchronizing literals are sent right away
+        assert_eq!(next_part(b"X {3+}\r\n{2} {2}\r\nab", 0), (15, Some(17)));
+        assert_eq!(next_part(b"X {2}\r\nab", 7), (9, None));
+    }
+
+    #[tokio::test]
+    async fn literal_dropped() {
+        let (stream, mut server) = tokio::io::duplex(4096);
+        server.write_all(b"* OK ready\r\n").await.unwrap();
+        let (_, mut client) = Client::from_stream(stream).await.unwrap();
+        let values = [Metadata {
+            entry: "/private/a".into(),
+            value: Some(Cow::Borrowed(b"\r\n")),
+        }];
+        let mut buf = vec![0; 1024];
+        let header = b"A0001 SETMETADATA \"INBOX\" (\"/private/a\" {2}\r\n";
+
+        // The server rejects the literal, so the next command can be sent
+        let mut responses = client.call(CommandBuilder::set_metadata("INBOX", &values));
+        tokio::select! {
+            _ = responses.next() => panic!("unexpected response"),
+            n = server.read(&mut buf) => assert_eq!(&buf[..n.unwrap()], header),
+        }
+        server.write_all(b"A0001 NO too large\r\n").await.unwrap();
+        assert!(responses.try_next().await.unwrap().is_some());
+        drop(responses);
+
+        // Dropped while the server waits for the literal data
+        let mut responses = client.call(CommandBuilder::set_metadata("INBOX", &values));
+        tokio::select! {
+            _ = responses.next() => panic!("unexpected response"),
+            n = server.read(&mut buf) => assert!(buf[..n.unwrap()].ends_with(b"{2}\r\n")),
+        }
+        drop(responses);
+
+        let err = client
+            .call(CommandBuilder::noop())
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "server is still waiting for literal data");
+    }
+
+    #[tokio::test]
+    async fn metadata() {
+        let server = MockServer::new().respond_with(
+            "GETMETADATA",
+            &["* METADATA \"INBOX\" (/private/comment {5}\r\nhello)"],
+            "OK [METADATA LONGENTRIES 2048] GETMETADATA complete",
+        );
+        let (stream, _) = server.connect();
+        let (_, mut client) = Client::from_stream(stream).await.unwrap();
+
+        let cmd = CommandBuilder::get_metadata("INBOX", &["/private/comment", "/private/blob"])
+            .max_size(1024);
+        let rsp = client.call(cmd).try_collect::<Vec<_>>().await.unwrap();
+        match rsp[0].parsed() {
+            Response::MailboxData(MailboxDatum::MetadataSolicited { values, .. }) => {
+                assert_eq!(values[0].value.as_deref(), Some(&b"hello"[..]));
+            }
+            rsp => panic!("unexpected response: {rsp:?}"),
+        }
+        assert!(matches!(
+            rsp[1].parsed(),
+            Response::Done {
+                status: Status::Ok,
+                code: Some(ResponseCode::MetadataLongEntries(2048)),
+                ..
+            }
+        ));
+    }
+
+    #[cfg(feature = "compress")]
+    #[tokio::test]
+    async fn compress() {
+        use flate2::{Compress, Compression, Decompress, FlushCompress, FlushDecompress};
+
+        let (stream, mut server) = tokio::io::duplex(4096);
+        let server = tokio::spawn(async move {
+            server.write_all(b"* OK ready\r\n").await.unwrap();
+            let mut buf = vec![0; 1024];
+            let n = server.read(&mut buf).await.unwrap();
+            assert_eq!(&buf[..n], b"A0001 COMPRESS DEFLATE\r\n");
+
+            // Send compressed data in the same write as the completion
+            let mut compress = Compress::new(Compression::default(), false);
+            let mut out = b"A0001 OK DEFLATE active\r\n".to_vec();
+            out.reserve(1024);
+            let data = b"* 3 EXISTS\r\n";
+            compress
+                .compress_vec(data, &mut out, FlushCompress::Sync)
+                .unwrap();
+            server.write_all(&out).await.unwrap();
+
+            let n = server.read(&mut buf).await.unwrap();
+            let mut decompress = Decompress::new(false);
+            let mut cmd = Vec::with_capacity(1024);
+            decompress
+                .decompress_vec(&buf[..n], &mut cmd, FlushDecompress::None)
+                .unwrap();
+            assert_eq!(cmd, b"A0002 CHECK\r\n");
+
+            out.clear();
+            let data = b"A0002 OK CHECK completed\r\n";
+            compress
+                .compress_vec(data, &mut out, FlushCompress::Sync)
+                .unwrap();
+            server.write_all(&out).await.unwrap();
+        });
+
+        let (_, client) = Client::from_stream(stream).await.unwrap();
+        let mut client = client.compress().await.map_err(|(_, e)| e).unwrap();
+        let rsp = client
+            .call(CommandBuilder::check())
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(rsp.len(), 2);
+        assert!(matches!(rsp[0].parsed(), Response::MailboxData(_)));
+        server.await.unwrap();
+
+        // The client can still be used if the server rejects the command
+        let server = MockServer::new().respond_with("COMPRESS", &[], "NO Not supported");
+        let (stream, _) = server.connect();
+        let (_, client) = Client::from_stream(stream).await.unwrap();
+        let (mut client, _) = match client.compress().await {
+            Ok(_) => panic!("COMPRESS DEFLATE accepted"),
+            Err(err) => err,
+        };
+        let rsp = client
+            .call(CommandBuilder::noop())
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(rsp.len(), 1);
     }
 }
@@ -1,52 +1,221 @@
+use std::borrow::Cow;
+use std::error::Error;
+use std::fmt;
 use std::io;
-use std::mem;
 
 use bytes::{BufMut, Bytes, BytesMut};
-use nom::{self, Needed};
+use self_cell::self_cell;
 use tokio_util::codec::{Decoder, Encoder};
 
-use imap_proto::types::{Request, RequestId, Response};
+use imap_proto::types::{Command, Request, RequestId, Response};
 
 #[derive(Default)]
 pub struct ImapCodec {
-    decode_need_message_bytes: usize,
+    scanner: Scanner,
+    // Literals in FETCH responses of at least this size are streamed
+    // as `ResponseFrame::LiteralChunk`s instead of being buffered.
+    pub(crate) literal_threshold: Option<usize>,
+    streaming: Option<Streaming>,
+    pub(crate) limits: Limits,
+}
+
+// State for a FETCH response with a literal that is being streamed
+struct Streaming {
+    // The response so far, with streamed literals replaced by empty ones
+    head: BytesMut,
+    // Literal bytes that still have to be passed through as chunks
+    remaining: usize,
+    message: u32,
 }
 
 impl Decoder for ImapCodec {
-    type Item = ResponseData;
+    type Item = ResponseFrame;
     type Error = io::Error;
     fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<Self::Item>, io::Error> {
-        if self.decode_need_message_bytes > buf.len() {
-            return Ok(None);
-        }
-        let (response, rsp_len) = match imap_proto::Response::from_bytes(buf) {
-            Ok((remaining, response)) => {
-                // This SHOULD be acceptable/safe: BytesMut storage memory is
-                // allocated on the heap and should not move. It will not be
-                // freed as long as we keep a reference alive, which we do
-                // by retaining a reference to the split buffer, below.
-                let response =
-                    unsafe { mem::transmute::<Response<'_>, Response<'static>>(response) };
-                (response, buf.len() - remaining.len())
-            }
-            Err(nom::Err::Incomplete(Needed::Size(min))) => {
-                self.decode_need_message_bytes = min.get();
-                return Ok(None);
-            }
-            Err(nom::Err::Incomplete(_)) => {
-                return Ok(None);
-            }
-            Err(nom::Err::Error(nom::error::Error { code, .. }))
-            | Err(nom::Err::Failure(nom::error::Error { code, .. })) => {
-                return Err(io::Error::other(format!(
-                    "{code:?} during parsing of {buf:?}"
+        if let Some(streaming) = &mut self.streaming {
+            if streaming.remaining > 0 {
+                if buf.is_empty() {
+                    return Ok(None);
+                }
+                let len = streaming.remaining.min(buf.len());
+                streaming.remaining -= len;
+                return Ok(Some(ResponseFrame::LiteralChunk(
+                    buf.split_to(len).freeze(),
                 )));
             }
+        }
+
+        let message = match (&self.streaming, self.literal_threshold) {
+            (Some(streaming), _) => Some(streaming.message),
+            (None, Some(_)) => fetch_message(buf),
+            (None, None) => None,
         };
-        let raw = buf.split_to(rsp_len).freeze();
-        self.decode_need_message_bytes = 0;
-        Ok(Some(ResponseData { raw, response }))
+        let threshold = message.and(self.literal_threshold);
+        let base = self.streaming.as_ref().map_or(0, |s| s.head.len());
+        // Responses are only parsed once they have been framed completely,
+        // so that parsing is not repeated every time more data comes in.
+        match self.scanner.scan(buf, threshold, &self.limits, base)? {
+            Scan::Incomplete => Ok(None),
+            Scan::Complete(len) => {
+                let mut raw = buf.split_to(len);
+                if let Some(mut streaming) = self.streaming.take() {
+                    streaming.head.unsplit(raw);
+                    raw = streaming.head;
+                }
+                Ok(Some(ResponseFrame::Response(parse_framed(raw))))
+            }
+            Scan::Literal { line_end, size } => {
+                let line = buf.split_to(line_end);
+                // safe: `scan()` only returns `Literal` for lines with a literal header
+                let brace = line.iter().rposition(|&b| b == b'{').unwrap();
+                let streaming = self.streaming.get_or_insert_with(|| Streaming {
+                    head: BytesMut::new(),
+                    remaining: 0,
+                    message: message.unwrap_or_default(),
+                });
+                streaming.head.extend_from_slice(&line[..brace]);
+                streaming.head.extend_from_slice(b"{0}\r\n");
+                streaming.remaining = size;
+                Ok(Some(ResponseFrame::FetchLiteral {
+                    message: streaming.message,
+                    attribute: attribute_name(&line[..brace]),
+                    size,
+                }))
+            }
+        }
+    }
+}
+
+// Parse a response that has been framed by `Scanner::scan()`
+//
+// Responses that cannot be parsed are returned as `Response::Unparsed`, so that
+// the connection remains usable when the server sends something unexpected.
+fn parse_framed(raw: BytesMut) -> ResponseData {
+    let cell = ResponseCell::new(raw.freeze(), |raw| {
+        match imap_proto::Response::from_bytes(raw) {
+            Ok((_, response)) => response,
+            Err(_) => Response::Unparsed {
+                tag: unparsed_tag(raw),
+                raw: Cow::Borrowed(raw),
+            },
+        }
+    });
+    ResponseData { cell }
+}
+
+// The tag of a tagged response, which is everything up to the first space
+fn unparsed_tag(raw: &[u8]) -> Option<RequestId> {
+    let end = raw.iter().position(|&b| b == b' ')?;
+    let tag = std::str::from_utf8(&raw[..end]).ok()?;
+    match tag {
+        "*" | "+" | "" => None,
+        _ => Some(RequestId(tag.to_owned())),
+    }
+}
+
+// Incrementally finds the boundaries of responses, based on CRLFs and literals
+//
+// Positions are relative to the start of the buffer, which must only be advanced
+// up to a boundary returned from `scan()`. Bytes that have been scanned already
+// are not looked at again when more data comes in.
+#[derive(Debug, Default)]
+struct Scanner {
+    // Start of the current line (which may be beyond the end of the buffer
+    // while a literal is coming in)
+    line_start: usize,
+    // Number of bytes of the current line known not to contain a CRLF
+    searched: usize,
+    // Start of the last literal that was skipped
+    literal_start: Option<usize>,
+}
+
+impl Scanner {
+    // Find the end of the response at the start of `buf`, skipping over literals.
+    // Stops at the first literal of at least `threshold` bytes, if given. `base` is
+    // the size of the part of the response that has already been consumed.
+    fn scan(
+        &mut self,
+        buf: &[u8],
+        threshold: Option<usize>,
+        limits: &Limits,
+        base: usize,
+    ) -> Result<Scan, LimitExceeded> {
+        loop {
+            if self.line_start > buf.len() {
+                return Ok(Scan::Incomplete);
+            }
+
+            // Back up one byte in case the last search ended in between CR and LF
+            let from = self.line_start + self.searched.saturating_sub(1);
+            let line_end = match buf[from..].windows(2).position(|w| w == b"\r\n") {
+                Some(i) => from + i + 2,
+                None => {
+                    self.searched = buf.len() - self.line_start;
+                    limits.check_line(self.searched)?;
+                    limits.check_response(base + buf.len())?;
+                    return Ok(Scan::Incomplete);
+                }
+            };
+
+            limits.check_line(line_end - self.line_start)?;
+            limits.check_response(base + line_end)?;
+            let size = match literal_size(&buf[self.line_start..line_end], limits)? {
+                Some(size) => size,
+                None => {
+                    *self = Self::default();
+                    return Ok(Scan::Complete(line_end));
+                }
+            };
+            if matches!(threshold, Some(threshold) if size >= threshold) {
+                *self = Self::default();
+                return Ok(Scan::Literal { line_end, size });
+            }
+
+            limits.check_literal(size)?;
+            self.literal_start = Some(line_end);
+            self.line_start = line_end + size;
+            self.searched = 0;
+            limits.check_response(base + self.line_start)?;
+        }
+    }
+}
+
+enum Scan {
+    Incomplete,
+    // Length of the complete response
+    Complete(usize),
+    // A literal of at least the threshold size starts after `line_end`
+    Literal { line_end: usize, size: usize },
+}
+
+// The message sequence number, if `buf` starts with an untagged FETCH response
+fn fetch_message(buf: &[u8]) -> Option<u32> {
+    let rest = buf.strip_prefix(b"* ")?;
+    let digits = rest.iter().position(|b| !b.is_ascii_digit())?;
+    let keyword = rest[digits..].get(..7)?;
+    if digits == 0 || !keyword.eq_ignore_ascii_case(b" FETCH ") {
+        return None;
     }
+    std::str::from_utf8(&rest[..digits]).ok()?.parse().ok()
+}
+
+// The name of the FETCH attribute (like `BODY[1.2]<0>`) that ends `prefix`
+fn attribute_name(prefix: &[u8]) -> String {
+    let prefix = prefix.strip_suffix(b" ").unwrap_or(prefix);
+    let mut depth = 0;
+    let start = prefix
+        .iter()
+        .rposition(|&b| {
+            match b {
+                b']' => depth += 1,
+                b'[' => depth -= 1,
+                b' ' | b'(' if depth == 0 => return true,
+                _ => {}
+            }
+            false
+        })
+        .map_or(0, |i| i + 1);
+    String::from_utf8_lossy(&prefix[start..]).into_owned()
 }
 
 impl<'a> Encoder<&'a Request<'a>> for ImapCodec {
@@ -60,31 +229,672 @@ impl<'a> Encoder<&'a Request<'a>> for ImapCodec {
     }
 }
 
+/// Limits on the size of responses received from the server
+///
+/// With `ServerCodec::with_limits()`, these limit the size of commands
+/// received from a client instead.
+///
+/// Without limits, a broken or hostile server can make the client buffer an
+/// arbitrary amount of data (for example, by announcing a huge literal). When a
+/// limit is exceeded, the response stream fails with an `io::Error` of kind
+/// `InvalidData` that wraps a `LimitExceeded`; the connection cannot be used
+/// after that.
+///
+/// Literals streamed by `Client::call_streaming()` are not buffered, so they
+/// do not count against these limits. By default, nothing is limited.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Limits {
+    literal_size: usize,
+    line_length: usize,
+    response_size: usize,
+}
+
+impl Limits {
+    pub fn new() -> Self {
+        Self {
+            literal_size: usize::MAX,
+            line_length: usize::MAX,
+            response_size: usize::MAX,
+        }
+    }
+
+    /// Maximum size of a single literal, in bytes
+    pub fn max_literal_size(mut self, size: usize) -> Self {
+        self.literal_size = size;
+        self
+    }
+
+    /// Maximum length of a line (not counting literal data), including the CRLF
+    pub fn max_line_length(mut self, length: usize) -> Self {
+        self.line_length = length;
+        self
+    }
+
+    /// Maximum size of a complete response, including any literals
+    pub fn max_response_size(mut self, size: usize) -> Self {
+        self.response_size = size;
+        self
+    }
+
+    fn check_literal(&self, size: usize) -> Result<(), LimitExceeded> {
+        match size > self.literal_size {
+            true => Err(LimitExceeded::LiteralSize {
+                size,
+                limit: self.literal_size,
+            }),
+            false => Ok(()),
+        }
+    }
+
+    fn check_line(&self, length: usize) -> Result<(), LimitExceeded> {
+        match length > self.line_length {
+            true => Err(LimitExceeded::LineLength {
+                limit: self.line_length,
+            }),
+            false => Ok(()),
+        }
+    }
+
+    fn check_response(&self, size: usize) -> Result<(), LimitExceeded> {
+        match size > self.response_size {
+            true => Err(LimitExceeded::ResponseSize {
+                limit: self.response_size,
+            }),
+            false => Ok(()),
+        }
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A response from the server exceeded one of the configured `Limits`
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum LimitExceeded {
+    /// The server announced a literal of `size` bytes
+    LiteralSize {
+        size: usize,
+        limit: usize,
+    },
+    LineLength {
+        limit: usize,
+    },
+    ResponseSize {
+        limit: usize,
+    },
+}
+
+impl fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::LiteralSize { size, limit } => {
+                write!(f, "literal of {size} bytes exceeds limit of {limit} bytes")
+            }
+            Self::LineLength { limit } => write!(f, "line exceeds limit of {limit} bytes"),
+            Self::ResponseSize { limit } => {
+                write!(f, "response exceeds limit of {limit} bytes")
+            }
+        }
+    }
+}
+
+impl Error for LimitExceeded {}
+
+impl From<LimitExceeded> for io::Error {
+    fn from(err: LimitExceeded) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, err)
+    }
+}
+
+/// Items yielded by `Client::call_streaming()`
+///
+/// Literals of at least the client's literal threshold in FETCH responses are
+/// not buffered. Instead, a `FetchLiteral` frame announces the literal, followed
+/// by `LiteralChunk` frames containing exactly `size` bytes in total. Once the
+/// whole FETCH response has been received, it is yielded as a `Response` in
+/// which the streamed literals are empty.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ResponseFrame {
+    /// A complete response
+    Response(ResponseData),
+    /// Start of a streamed literal
+    FetchLiteral {
+        /// Sequence number of the message being fetched
+        message: u32,
+        /// The attribute the literal belongs to, like `BODY[]` or `RFC822.TEXT`
+        attribute: String,
+        size: usize,
+    },
+    /// Part of the data of a streamed literal
+    LiteralChunk(Bytes),
+}
+
+self_cell!(
+    struct ResponseCell {
+        owner: Bytes,
+        #[covariant]
+        dependent: Response,
+    }
+    impl {Debug}
+);
+
+/// A parsed response, along with the buffer it borrows from
+///
+/// Parsing does not copy data out of the received bytes: strings and literals
+/// in the `Response` borrow from a reference-counted buffer owned by the
+/// `ResponseData`. `ResponseData` is `Send` and `Sync`, so it can be moved to
+/// and shared between other tasks and threads.
 #[derive(Debug)]
 pub struct ResponseData {
-    #[allow(dead_code)] // Contains data that `response` borrows
-    raw: Bytes,
-    // This reference is really scoped to the lifetime of the `raw`
-    // member, but unfortunately Rust does not allow that yet. It
-    // is transmuted to `'static` by the `Decoder`, instead, and
-    // references returned to callers of `ResponseData` are limited
-    // to the lifetime of the `ResponseData` struct.
-    //
-    // `raw` is never mutated during the lifetime of `ResponseData`,
-    // and `Response` does not not implement any specific drop glue.
-    response: Response<'static>,
+    cell: ResponseCell,
 }
 
 impl ResponseData {
     pub fn request_id(&self) -> Option<&RequestId> {
-        match self.response {
-            Response::Done { ref tag, .. } => Some(tag),
+        match self.parsed() {
+            Response::Done { tag, .. } => Some(tag),
+            Response::Unparsed { tag, .. } => tag.as_ref(),
             _ => None,
         }
     }
 
-    #[allow(clippy::needless_lifetimes)]
-    pub fn parsed<'a>(&'a self) -> &'a Response<'a> {
-        &self.response
+    pub fn parsed(&self) -> &Response<'_> {
+        self.cell.borrow_dependent()
+    }
+
+    /// The response as received, including any literals and the final CRLF
+    ///
+    /// For responses containing literals streamed by `Client::call_streaming()`,
+    /// the streamed literals are empty.
+    pub fn raw(&self) -> &[u8] {
+        self.cell.borrow_owner()
+    }
+}
+
+/// Codec for the server side of an IMAP connection
+///
+/// Decodes client commands and encodes `Response`s. When a command contains a
+/// synchronizing literal (`{n}`), the client waits for a continuation request
+/// before sending the literal data; the decoder signals this by yielding
+/// `ClientMessage::LiteralPending`, after which the server should send a
+/// `Response::Continue`.
+///
+/// Commands are checked against the `Limits` given to `with_limits()`, if any,
+/// before the client is asked to send a literal.
+#[derive(Debug, Default)]
+pub struct ServerCodec {
+    scanner: Scanner,
+    // Length of the buffered command prefix for which `LiteralPending` was last
+    // yielded, so that the client is not asked to continue more than once.
+    continued: usize,
+    limits: Limits,
+}
+
+impl ServerCodec {
+    pub fn with_limits(limits: Limits) -> Self {
+        Self {
+            limits,
+            ..Self::default()
+        }
+    }
+}
+
+impl Decoder for ServerCodec {
+    type Item = ClientMessage;
+    type Error = io::Error;
+    fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<Self::Item>, io::Error> {
+        let len = match self.scanner.scan(buf, None, &self.limits, 0)? {
+            Scan::Complete(len) => len,
+            _ => {
+                // Ask for the literal data if none of it has been received yet,
+                // unless the client doesn't wait for that
+                let scanner = &self.scanner;
+                if buf.len() > self.continued
+                    && scanner.literal_start == Some(buf.len())
+                    && !buf.ends_with(b"+}\r\n")
+                {
+                    self.continued = buf.len();
+                    // Literal sizes that don't fit in a `u32` are rejected by `scan()`
+                    let size = (scanner.line_start - buf.len()) as u32;
+                    return Ok(Some(ClientMessage::LiteralPending { size }));
+                }
+                return Ok(None);
+            }
+        };
+
+        self.continued = 0;
+        let raw = buf.split_to(len).freeze();
+        let cell = CommandCell::try_new(raw.clone(), |raw| match Command::from_bytes(raw) {
+            Ok((&[], command)) => Ok(command),
+            _ => Err(()),
+        });
+        Ok(Some(match cell {
+            Ok(cell) => ClientMessage::Command(CommandData { cell }),
+            // Not a command: hand the line to the caller as-is. This is what
+            // the `DONE` terminating an IDLE or a response to an AUTHENTICATE
+            // challenge looks like.
+            Err(()) => ClientMessage::Line(raw),
+        }))
+    }
+}
+
+impl<'a> Encoder<&'a Response<'a>> for ServerCodec {
+    type Error = io::Error;
+    fn encode(&mut self, msg: &Response<'a>, dst: &mut BytesMut) -> Result<(), io::Error> {
+        let mut buf = Vec::new();
+        msg.encode(&mut buf)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        dst.put_slice(&buf);
+        Ok(())
+    }
+}
+
+// If the buffer ends with a literal header (`{n}` CRLF, or `{n+}` CRLF for a
+// non-synchronizing literal sent by a client), return the announced size.
+//
+// A size that doesn't fit in a `number` (RFC 3501) is an error rather than
+// `None`: the literal data would otherwise be mistaken for the next line.
+fn literal_size(buf: &[u8], limits: &Limits) -> Result<Option<usize>, LimitExceeded> {
+    let line = match buf.strip_suffix(b"}\r\n") {
+        Some(line) => line,
+        None => return Ok(None),
+    };
+    let digits = match line.iter().rposition(|&b| b == b'{') {
+        Some(start) => &line[start + 1..],
+        None => return Ok(None),
+    };
+    let digits = digits.strip_suffix(b"+").unwrap_or(digits);
+    if digits.is_empty() || !digits.iter().all(u8::is_ascii_digit) {
+        return Ok(None);
+    }
+
+    let size = digits.iter().fold(0_usize, |size, digit| {
+        size.saturating_mul(10)
+            .saturating_add(usize::from(digit - b'0'))
+    });
+    let max = u32::MAX as usize;
+    match size > max {
+        true => Err(LimitExceeded::LiteralSize {
+            size,
+            limit: limits.literal_size.min(max),
+        }),
+        false => Ok(Some(size)),
+    }
+}
+
+/// Items yielded by `ServerCodec`
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ClientMessage {
+    /// A complete command
+    Command(CommandData),
+    /// The client sent a synchronizing literal of `size` bytes, and is waiting
+    /// for a continuation request before sending the literal data
+    LiteralPending { size: u32 },
+    /// A line that could not be parsed as a command, including any literals
+    /// and the final CRLF
+    Line(Bytes),
+}
+
+self_cell!(
+    struct CommandCell {
+        owner: Bytes,
+        #[covariant]
+        dependent: Command,
+    }
+    impl {Debug}
+);
+
+/// A parsed command, along with the buffer it borrows from
+///
+/// Like `ResponseData`, this is `Send` and `Sync`.
+#[derive(Debug)]
+pub struct CommandData {
+    cell: CommandCell,
+}
+
+impl CommandData {
+    pub fn request_id(&self) -> &RequestId {
+        &self.parsed().tag
+    }
+
+    pub fn parsed(&self) -> &Command<'_> {
+        self.cell.borrow_dependent()
+    }
+
+    /// The command as received, including any literals and the final CRLF
+    pub fn raw(&self) -> &[u8] {
+        self.cell.borrow_owner()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use imap_proto::types::{AttributeValue, CommandBody, MailboxDatum, Status};
+
+    #[test]
+    fn unparsed() {
+        let mut codec = ImapCodec::default();
+        let mut buf =
+            BytesMut::from(&b"* XFOO ({5}\r\nab\r\nc (BAR))\r\n* 3 EXISTS\r\nA1 XOK done\r\n"[..]);
+        let mut next = || match codec.decode(&mut buf).unwrap() {
+            Some(ResponseFrame::Response(rsp)) => rsp,
+            other => panic!("unexpected frame {other:?}"),
+        };
+
+        let rsp = next();
+        assert_eq!(
+            rsp.parsed(),
+            &Response::Unparsed {
+                tag: None,
+                raw: Cow::Borrowed(&b"* XFOO ({5}\r\nab\r\nc (BAR))\r\n"[..]),
+            }
+        );
+        assert_eq!(rsp.request_id(), None);
+        assert_eq!(
+            next().parsed(),
+            &Response::MailboxData(MailboxDatum::Exists(3))
+        );
+        let rsp = next();
+        assert!(matches!(rsp.parsed(), Response::Unparsed { .. }));
+        assert_eq!(rsp.request_id(), Some(&RequestId("A1".into())));
+    }
+
+    #[test]
+    fn send_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<ResponseData>();
+        assert_send_sync::<ResponseFrame>();
+        assert_send_sync::<CommandData>();
+    }
+
+    #[test]
+    fn trickle() {
+        let input = b"* 1 FETCH (BODY[1] {3}\r\nabc BODY[2] {2}\r\n\r\n)\r\n* 2 EXISTS\r\n";
+        let mut codec = ImapCodec::default();
+        let mut buf = BytesMut::new();
+        let mut responses = Vec::new();
+        for &b in input.iter() {
+            buf.put_u8(b);
+            if let Some(ResponseFrame::Response(rsp)) = codec.decode(&mut buf).unwrap() {
+                responses.push(rsp);
+            }
+            // Data that was scanned already is not scanned again
+            let scanner = &codec.scanner;
+            assert!(
+                scanner.line_start > buf.len()
+                    || scanner.searched <= buf.len() - scanner.line_start
+            );
+        }
+        assert!(buf.is_empty());
+        assert_eq!(responses.len(), 2);
+        assert!(matches!(responses[0].parsed(), Response::Fetch(1, attrs) if attrs.len() == 2));
+        assert_eq!(
+            responses[1].parsed(),
+            &Response::MailboxData(MailboxDatum::Exists(2))
+        );
+
+        let mut scanner = Scanner::default();
+        let limits = Limits::default();
+        let partial = b"* 1 FETCH (BODY[] {5}\r\nab";
+        assert!(matches!(
+            scanner.scan(partial, None, &limits, 0),
+            Ok(Scan::Incomplete)
+        ));
+        assert_eq!(scanner.line_start, 28);
+        let partial = b"* 1 FETCH (BODY[] {5}\r\nabcde FLAGS ()";
+        assert!(matches!(
+            scanner.scan(partial, None, &limits, 0),
+            Ok(Scan::Incomplete)
+        ));
+        assert_eq!((scanner.line_start, scanner.searched), (28, 9));
+    }
+
+    #[test]
+    fn limits() {
+        let limit_error = |codec: &mut ImapCodec, input: &[u8]| {
+            let mut buf = BytesMut::from(input);
+            let err = codec.decode(&mut buf).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+            *err.into_inner()
+                .unwrap()
+                .downcast::<LimitExceeded>()
+                .unwrap()
+        };
+
+        let mut codec = ImapCodec {
+            limits: Limits::new().max_literal_size(1024),
+            ..ImapCodec::default()
+        };
+        assert_eq!(
+            limit_error(&mut codec, b"* 1 FETCH (BODY[] {999999999}\r\n"),
+            LimitExceeded::LiteralSize {
+                size: 999_999_999,
+                limit: 1024
+            }
+        );
+
+        // Sizes that overflow a `number` are rejected even without limits
+        let mut codec = ImapCodec::default();
+        assert!(matches!(
+            limit_error(&mut codec, b"* 1 FETCH (BODY[] {99999999999}\r\nabc)\r\n"),
+            LimitExceeded::LiteralSize { .. }
+        ));
+
+        let mut codec = ImapCodec {
+            limits: Limits::new().max_line_length(16),
+            ..ImapCodec::default()
+        };
+        let mut buf = BytesMut::from(&b"* OK fits\r\n"[..]);
+        assert!(codec.decode(&mut buf).unwrap().is_some());
+        assert_eq!(
+            limit_error(&mut codec, b"* OK this line never ends"),
+            LimitExceeded::LineLength { limit: 16 }
+        );
+
+        let mut codec = ImapCodec {
+            limits: Limits::new().max_response_size(32),
+            literal_threshold: Some(16),
+            ..ImapCodec::default()
+        };
+        assert_eq!(
+            limit_error(
+                &mut codec,
+                b"* 1 FETCH (BODY[1] {10}\r\n0123456789 BODY[2] {10}\r\n"
+            ),
+            LimitExceeded::ResponseSize { limit: 32 }
+        );
+        // Streamed literals don't count against the limits
+        let mut codec = ImapCodec {
+            limits: Limits::new().max_response_size(32),
+            literal_threshold: Some(16),
+            ..ImapCodec::default()
+        };
+        let mut buf = BytesMut::from(&b"* 1 FETCH (BODY[] {100}\r\n"[..]);
+        assert!(matches!(
+            codec.decode(&mut buf).unwrap(),
+            Some(ResponseFrame::FetchLiteral { size: 100, .. })
+        ));
+    }
+
+    #[test]
+    fn stream_literals() {
+        let mut codec = ImapCodec {
+            literal_threshold: Some(8),
+            ..ImapCodec::default()
+        };
+        let mut buf = BytesMut::from(
+            &b"* 1 FETCH (UID 5 BODY[HEADER] {4}\r\nab\r\n BODY[1]<0> {10}\r\n0123"[..],
+        );
+        match codec.decode(&mut buf).unwrap() {
+            Some(ResponseFrame::FetchLiteral {
+                message: 1,
+                attribute,
+                size: 10,
+            }) => assert_eq!(attribute, "BODY[1]<0>"),
+            other => panic!("unexpected frame {other:?}"),
+        }
+        assert!(matches!(
+            codec.decode(&mut buf).unwrap(),
+            Some(ResponseFrame::LiteralChunk(chunk)) if chunk == "0123"
+        ));
+        assert!(codec.decode(&mut buf).unwrap().is_none());
+
+        buf.extend_from_slice(b"456789 FLAGS (\\Seen))\r\n* 2 EXISTS\r\n");
+        assert!(matches!(
+            codec.decode(&mut buf).unwrap(),
+            Some(ResponseFrame::LiteralChunk(chunk)) if chunk == "456789"
+        ));
+        match codec.decode(&mut buf).unwrap() {
+            Some(ResponseFrame::Response(rsp)) => match rsp.parsed() {
+                Response::Fetch(1, attrs) => {
+                    assert_eq!(attrs.len(), 4);
+                    assert_eq!(attrs[0], AttributeValue::Uid(5));
+                    assert!(matches!(
+                        &attrs[1],
+                        AttributeValue::BodySection { data: Some(data), .. } if data[..] == b"ab\r\n"[..]
+                    ));
+                    assert!(matches!(
+                        &attrs[2],
+                        AttributeValue::BodySection { data: Some(data), .. } if data.is_empty()
+                    ));
+                }
+                rsp => panic!("unexpected response {rsp:?}"),
+            },
+            other => panic!("unexpected frame {other:?}"),
+        }
+        match codec.decode(&mut buf).unwrap() {
+            Some(ResponseFrame::Response(rsp)) => {
+                assert_eq!(
+                    rsp.parsed(),
+                    &Response::MailboxData(MailboxDatum::Exists(2))
+                )
+            }
+            other => panic!("unexpected frame {other:?}"),
+        }
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn server_decode_literal() {
+        let mut codec = ServerCodec::default();
+        let mut buf = BytesMut::from(&b"A1 LOGIN {3}\r\n"[..]);
+        assert!(matches!(
+            codec.decode(&mut buf).unwrap(),
+            Some(ClientMessage::LiteralPending { size: 3 })
+        ));
+        assert!(codec.decode(&mut buf).unwrap().is_none());
+
+        buf.extend_from_slice(b"djc {6}\r\n");
+        assert!(matches!(
+            codec.decode(&mut buf).unwrap(),
+            Some(ClientMessage::LiteralPending { size: 6 })
+        ));
+
+        buf.extend_from_slice(b"s3cr3t\r\nA2 IDLE\r\nDONE\r\n");
+        match codec.decode(&mut buf).unwrap() {
+            Some(ClientMessage::Command(cmd)) => {
+                assert_eq!(cmd.request_id(), &RequestId("A1".into()));
+                assert!(matches!(cmd.parsed().body, CommandBody::Login { .. }));
+            }
+            other => panic!("unexpected item {other:?}"),
+        }
+        assert!(matches!(
+            codec.decode(&mut buf).unwrap(),
+            Some(ClientMessage::Command(cmd)) if cmd.parsed().body == CommandBody::Idle
+        ));
+        assert!(matches!(
+            codec.decode(&mut buf).unwrap(),
+            Some(ClientMessage::Line(line)) if line == "DONE\r\n"
+        ));
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn server_decode_unparsed() {
+        let mut codec = ServerCodec::default();
+        let mut buf = BytesMut::from(&b"A1 XFOO {5}\r\n"[..]);
+        assert!(matches!(
+            codec.decode(&mut buf).unwrap(),
+            Some(ClientMessage::LiteralPending { size: 5 })
+        ));
+
+        // The literal data is part of the line, even if it looks like a command
+        buf.extend_from_slice(b"A2 X\r\n\r\nA3 NOOP\r\n");
+        assert!(matches!(
+            codec.decode(&mut buf).unwrap(),
+            Some(ClientMessage::Line(line)) if line == "A1 XFOO {5}\r\nA2 X\r\n\r\n"
+        ));
+        assert!(matches!(
+            codec.decode(&mut buf).unwrap(),
+            Some(ClientMessage::Command(cmd)) if cmd.parsed().body == CommandBody::Noop
+        ));
+        assert!(buf.is_empty());
+
+        // Non-synchronizing literals are skipped without a continuation request
+        let mut buf = BytesMut::from(&b"A4 LOGIN {3+}\r\n"[..]);
+        assert!(codec.decode(&mut buf).unwrap().is_none());
+        buf.extend_from_slice(b"djc {1+}\r\nx\r\n");
+        match codec.decode(&mut buf).unwrap() {
+            Some(ClientMessage::Command(cmd)) => {
+                assert!(matches!(cmd.parsed().body, CommandBody::Login { .. }));
+            }
+            other => panic!("unexpected item {other:?}"),
+        }
+        assert!(buf.is_empty());
+
+        // Literal data that happens to end like a literal header
+        let mut buf = BytesMut::from(&b"A5 LOGIN {6}\r\n"[..]);
+        assert!(codec.decode(&mut buf).unwrap().is_some());
+        buf.extend_from_slice(b"{1}\r\n");
+        assert!(codec.decode(&mut buf).unwrap().is_none());
+    }
+
+    #[test]
+    fn server_limits() {
+        let mut codec = ServerCodec::with_limits(Limits::new().max_literal_size(1024));
+        let mut buf = BytesMut::from(&b"A1 LOGIN {2048}\r\n"[..]);
+        let err = codec.decode(&mut buf).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let err = err
+            .into_inner()
+            .unwrap()
+            .downcast::<LimitExceeded>()
+            .unwrap();
+        assert_eq!(
+            *err,
+            LimitExceeded::LiteralSize {
+                size: 2048,
+                limit: 1024
+            }
+        );
+    }
+
+    #[test]
+    fn server_encode() {
+        let mut codec = ServerCodec::default();
+        let mut buf = BytesMut::new();
+        let rsp = Response::Done {
+            tag: RequestId("A1".into()),
+            status: Status::Ok,
+            code: None,
+            information: Some("LOGIN completed".into()),
+        };
+        codec.encode(&rsp, &mut buf).unwrap();
+        assert_eq!(&buf[..], b"A1 OK LOGIN completed\r\n");
+
+        let rsp = Response::Data {
+            status: Status::Ok,
+            code: None,
+            information: Some("split\r\nline".into()),
+        };
+        let err = codec.encode(&rsp, &mut buf).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
     }
 }
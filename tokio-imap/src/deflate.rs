@@ -0,0 +1,233 @@
+use std::io;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+use bytes::{Buf, BytesMut};
+use flate2::{Compress, Compression, Decompress, FlushCompress, FlushDecompress};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// A stream compressed with raw DEFLATE in both directions, as used by
+/// `COMPRESS DEFLATE` (RFC 4978)
+///
+/// Returned by `Client::compress()`. Written data is flushed with a sync flush
+/// whenever the stream is flushed, so that the server can decompress every
+/// command as soon as it is sent.
+pub struct Deflate<T> {
+    inner: T,
+    decompress: Decompress,
+    /// Compressed data read from `inner`, not yet decompressed
+    read_buf: BytesMut,
+    compress: Compress,
+    /// Compressed data not yet written to `inner`
+    write_buf: BytesMut,
+    /// Whether data was compressed since the last sync flush
+    unflushed: bool,
+}
+
+impl<T> Deflate<T> {
+    /// Wrap `inner`, where `buffered` holds compressed data already read from it
+    pub(crate) fn new(inner: T, buffered: BytesMut) -> Self {
+        Self {
+            inner,
+            decompress: Decompress::new(false),
+            read_buf: buffered,
+            compress: Compress::new(Compression::default(), false),
+            write_buf: BytesMut::new(),
+            unflushed: false,
+        }
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    fn deflate(&mut self, mut input: &[u8], flush: FlushCompress) -> io::Result<()> {
+        loop {
+            let mut out = vec![0; input.len() / 2 + 1024];
+            let (before_in, before_out) = (self.compress.total_in(), self.compress.total_out());
+            self.compress
+                .compress(input, &mut out, flush)
+                .map_err(io::Error::other)?;
+            let consumed = (self.compress.total_in() - before_in) as usize;
+            let produced = (self.compress.total_out() - before_out) as usize;
+            self.write_buf.extend_from_slice(&out[..produced]);
+            input = &input[consumed..];
+            // All output was produced if there was room to spare
+            if input.is_empty() && produced < out.len() {
+                return Ok(());
+            }
+        }
+    }
+}
+
+impl<T: AsyncWrite + Unpin> Deflate<T> {
+    fn poll_write_buf(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while !self.write_buf.is_empty() {
+            let n = ready!(Pin::new(&mut self.inner).poll_write(cx, &self.write_buf))?;
+            if n == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.write_buf.advance(n);
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for Deflate<T> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let me = self.get_mut();
+        if buf.remaining() == 0 {
+            return Poll::Ready(Ok(()));
+        }
+
+        loop {
+            if !me.read_buf.is_empty() {
+                let (before_in, before_out) = (me.decompress.total_in(), me.decompress.total_out());
+                me.decompress
+                    .decompress(
+                        &me.read_buf,
+                        buf.initialize_unfilled(),
+                        FlushDecompress::None,
+                    )
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                let consumed = (me.decompress.total_in() - before_in) as usize;
+                let produced = (me.decompress.total_out() - before_out) as usize;
+                me.read_buf.advance(consumed);
+                buf.advance(produced);
+                if produced > 0 {
+                    return Poll::Ready(Ok(()));
+                } else if consumed > 0 {
+                    continue;
+                }
+            }
+
+            let mut chunk = [0; 8192];
+            let mut chunk_buf = ReadBuf::new(&mut chunk);
+            ready!(Pin::new(&mut me.inner).poll_read(cx, &mut chunk_buf))?;
+            if chunk_buf.filled().is_empty() {
+                return Poll::Ready(Ok(()));
+            }
+            me.read_buf.extend_from_slice(chunk_buf.filled());
+        }
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for Deflate<T> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let me = self.get_mut();
+        // Apply backpressure instead of buffering without bounds
+        if me.write_buf.len() >= MAX_BUFFERED {
+            ready!(me.poll_write_buf(cx))?;
+        }
+
+        me.deflate(buf, FlushCompress::None)?;
+        me.unflushed = true;
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let me = self.get_mut();
+        if me.unflushed {
+            me.deflate(&[], FlushCompress::Sync)?;
+            me.unflushed = false;
+        }
+        ready!(me.poll_write_buf(cx))?;
+        Pin::new(&mut me.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        ready!(self.as_mut().poll_flush(cx))?;
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+const MAX_BUFFERED: usize = 64 * 1024;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    // Incompressible data, so that the compressed size is close to `len`
+    fn data(len: usize) -> Vec<u8> {
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn large_write() {
+        let input = data(4 * MAX_BUFFERED);
+        let (stream, mut peer) = tokio::io::duplex(4096);
+        let expected = input.clone();
+        let peer = tokio::spawn(async move {
+            let mut decompress = Decompress::new(false);
+            let (mut buf, mut out) = (vec![0; 1024], Vec::new());
+            while out.len() < expected.len() {
+                let n = peer.read(&mut buf).await.unwrap();
+                assert_ne!(n, 0);
+                let mut input = &buf[..n];
+                while !input.is_empty() {
+                    out.reserve(64 * 1024);
+                    let before = decompress.total_in();
+                    decompress
+                        .decompress_vec(input, &mut out, FlushDecompress::None)
+                        .unwrap();
+                    input = &input[(decompress.total_in() - before) as usize..];
+                }
+            }
+            assert_eq!(out, expected);
+        });
+
+        let mut deflate = Deflate::new(stream, BytesMut::new());
+        for chunk in input.chunks(8192) {
+            deflate.write_all(chunk).await.unwrap();
+            // Compressed data is written out instead of piling up; at most one
+            // block of output is added on top of `MAX_BUFFERED`
+            assert!(deflate.write_buf.len() < 2 * MAX_BUFFERED);
+        }
+        deflate.flush().await.unwrap();
+        peer.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn chunked_read() {
+        let expected = data(3 * 8192 + 100);
+        let mut compress = Compress::new(Compression::default(), false);
+        let mut compressed = Vec::with_capacity(expected.len() + 1024);
+        compress
+            .compress_vec(&expected, &mut compressed, FlushCompress::Sync)
+            .unwrap();
+        assert_eq!(compress.total_in() as usize, expected.len());
+
+        // Part of the data was read before compression was enabled
+        let (stream, mut peer) = tokio::io::duplex(4096);
+        let buffered = BytesMut::from(&compressed[..100]);
+        let rest = compressed[100..].to_vec();
+        let peer = tokio::spawn(async move { peer.write_all(&rest).await.unwrap() });
+
+        let mut deflate = Deflate::new(stream, buffered);
+        let (mut buf, mut out) = (vec![0; 1000], Vec::new());
+        while out.len() < expected.len() {
+            let n = deflate.read(&mut buf).await.unwrap();
+            assert_ne!(n, 0);
+            out.extend_from_slice(&buf[..n]);
+        }
+        assert_eq!(out, expected);
+        peer.await.unwrap();
+    }
+}
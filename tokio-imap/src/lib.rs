@@ -1,11 +1,29 @@
+#![deny(unsafe_code)]
+
 mod client;
 mod codec;
+#[cfg(feature = "compress")]
+mod deflate;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
+mod pool;
+mod reconnect;
 
-pub use crate::client::{Client, TlsClient};
-pub use crate::codec::ResponseData;
+pub use crate::client::{
+    Client, ResponseFrames, ResponseStream, TagGenerator, Timeouts, TlsClient,
+};
+pub use crate::codec::{
+    ClientMessage, CommandData, LimitExceeded, Limits, ResponseData, ResponseFrame, ServerCodec,
+};
+#[cfg(feature = "compress")]
+pub use crate::deflate::Deflate;
+pub use crate::pool::{Pool, PoolKey, PooledClient};
+pub use crate::reconnect::{Backoff, Reconnected, ReconnectingClient};
 
 pub mod builders {
-    pub use imap_proto::builders::command::{fetch, CommandBuilder, FetchCommand};
+    pub use imap_proto::builders::command::{
+        fetch, CommandBuilder, CreateCommand, FetchCommand, GetMetadataCommand, MetadataDepth,
+    };
 }
 
 pub mod types {
@@ -0,0 +1,1005 @@
+//! An in-memory IMAP server for testing clients without a network connection
+//!
+//! `MockServer` speaks enough IMAP4rev1 to log in, list and select mailboxes,
+//! fetch and store messages and IDLE. Responses to specific commands can be
+//! scripted, which also allows testing commands the server does not implement.
+//!
+//! ```no_run
+//! # async fn example() -> std::io::Result<()> {
+//! use tokio_imap::mock::{MockMailbox, MockMessage, MockServer};
+//!
+//! let server = MockServer::new()
+//!     .user("djc", "s3cr3t")
+//!     .mailbox(MockMailbox::new("INBOX").message(MockMessage::new("Subject: hi\r\n\r\nhello")))
+//!     .respond("NOOP", &["* 2 EXISTS"]);
+//! let (stream, handle) = server.connect();
+//! let (_greeting, mut client) = tokio_imap::Client::from_stream(stream).await?;
+//! // ... exercise the client ...
+//! # drop(client);
+//! handle.await.unwrap()
+//! # }
+//! ```
+
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::io;
+use std::pin::Pin;
+
+use futures_sink::Sink;
+use futures_util::future::poll_fn;
+use futures_util::StreamExt;
+use tokio::io::{AsyncRead, AsyncWrite, DuplexStream};
+use tokio::task::JoinHandle;
+use tokio_util::codec::{Decoder, Framed};
+
+use crate::codec::{ClientMessage, ServerCodec};
+use imap_proto::types::{
+    AttrMacro, AttributeValue, Capability, CommandBody, FetchAttribute, FetchAttributes,
+    MailboxDatum, MessageSection, NameAttribute, RequestId, Response, ResponseCode, Section,
+    SectionPath, SectionText, SequenceItem, SequenceNumber, State, Status, StatusAttribute,
+    StatusItem, StoreKind,
+};
+
+/// Configuration and contents of an in-memory IMAP server
+///
+/// Every connection starts out with its own copy of the configured mailboxes,
+/// so changes made by one connection (like `STORE`) are not visible to others.
+#[derive(Clone, Debug)]
+pub struct MockServer {
+    greeting: String,
+    capabilities: Vec<String>,
+    users: Vec<(String, String)>,
+    mailboxes: Vec<MockMailbox>,
+    scripts: VecDeque<Script>,
+    idle_events: Vec<String>,
+}
+
+impl MockServer {
+    pub fn new() -> Self {
+        Self {
+            greeting: "* OK [CAPABILITY IMAP4rev1 IDLE] Mock IMAP server ready".into(),
+            capabilities: vec!["IMAP4rev1".into(), "IDLE".into()],
+            users: Vec::new(),
+            mailboxes: Vec::new(),
+            scripts: VecDeque::new(),
+            idle_events: Vec::new(),
+        }
+    }
+
+    /// Replace the greeting line (without CRLF) sent when a client connects
+    pub fn greeting(mut self, line: &str) -> Self {
+        self.greeting = line.to_owned();
+        self
+    }
+
+    /// Add a capability to the `CAPABILITY` response
+    pub fn capability(mut self, capability: &str) -> Self {
+        self.capabilities.push(capability.to_owned());
+        self
+    }
+
+    /// Add an account that can `LOGIN`
+    ///
+    /// If no users are configured, any user name and password are accepted.
+    pub fn user(mut self, name: &str, password: &str) -> Self {
+        self.users.push((name.to_owned(), password.to_owned()));
+        self
+    }
+
+    pub fn mailbox(mut self, mailbox: MockMailbox) -> Self {
+        self.mailboxes.push(mailbox);
+        self
+    }
+
+    /// Script the response to the next `command` sent by the client
+    ///
+    /// `command` is matched case-insensitively against the command name
+    /// (like `NOOP` or `UID FETCH`). The `untagged` lines are sent as-is
+    /// (without CRLF), followed by a tagged `OK`. Scripts for the same command
+    /// are used in the order they were added; once they are used up, the
+    /// built-in behavior applies again. Any command can be scripted, including
+    /// ones the server does not otherwise understand.
+    pub fn respond(self, command: &str, untagged: &[&str]) -> Self {
+        let completion = format!("OK {} completed", command.to_ascii_uppercase());
+        self.respond_with(command, untagged, &completion)
+    }
+
+    /// Like `respond()`, but with the text following the tag in the tagged
+    /// response, for example `NO [TRYCREATE] No such mailbox`
+    pub fn respond_with(mut self, command: &str, untagged: &[&str], completion: &str) -> Self {
+        self.scripts.push_back(Script {
+            command: command.to_ascii_uppercase(),
+            action: Action::Respond {
+                untagged: untagged.iter().map(|s| (*s).to_owned()).collect(),
+                completion: completion.to_owned(),
+            },
+        });
+        self
+    }
+
+    /// Close the connection without responding when the client next sends `command`
+    pub fn disconnect(mut self, command: &str) -> Self {
+        self.scripts.push_back(Script {
+            command: command.to_ascii_uppercase(),
+            action: Action::Disconnect,
+        });
+        self
+    }
+
+    /// Lines (without CRLF) to send to the client after it starts to `IDLE`
+    pub fn idle_events(mut self, lines: &[&str]) -> Self {
+        self.idle_events = lines.iter().map(|s| (*s).to_owned()).collect();
+        self
+    }
+
+    /// Start serving a new connection in a background task
+    ///
+    /// Returns the client end of an in-memory connection and a handle for the
+    /// server task. Must be called from within a Tokio runtime.
+    pub fn connect(&self) -> (DuplexStream, JoinHandle<io::Result<()>>) {
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        let handle = tokio::spawn(self.clone().serve(server));
+        (client, handle)
+    }
+
+    /// Serve a single connection on `stream` until the client logs out or disconnects
+    pub async fn serve<T>(self, stream: T) -> io::Result<()>
+    where
+        T: AsyncRead + AsyncWrite + Unpin,
+    {
+        let mut conn = Connection {
+            transport: ServerCodec::default().framed(stream),
+            state: State::NotAuthenticated,
+            selected: None,
+            server: self,
+        };
+        conn.run().await
+    }
+}
+
+impl Default for MockServer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct MockMailbox {
+    name: String,
+    uid_validity: u32,
+    uid_next: u32,
+    messages: Vec<MockMessage>,
+}
+
+impl MockMailbox {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            uid_validity: 1,
+            uid_next: 1,
+            messages: Vec::new(),
+        }
+    }
+
+    pub fn uid_validity(mut self, uid_validity: u32) -> Self {
+        self.uid_validity = uid_validity;
+        self
+    }
+
+    /// Add a message, assigning it the next UID
+    pub fn message(mut self, mut message: MockMessage) -> Self {
+        message.uid = self.uid_next;
+        self.uid_next += 1;
+        self.messages.push(message);
+        self
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct MockMessage {
+    uid: u32,
+    flags: Vec<String>,
+    internal_date: String,
+    body: Vec<u8>,
+}
+
+impl MockMessage {
+    /// Create a message from its full RFC 5322 text
+    pub fn new(body: impl Into<Vec<u8>>) -> Self {
+        Self {
+            uid: 0,
+            flags: Vec::new(),
+            internal_date: "01-Jan-2020 00:00:00 +0000".into(),
+            body: body.into(),
+        }
+    }
+
+    pub fn flag(mut self, flag: &str) -> Self {
+        self.flags.push(flag.to_owned());
+        self
+    }
+
+    pub fn internal_date(mut self, date: &str) -> Self {
+        self.internal_date = date.to_owned();
+        self
+    }
+
+    fn header(&self) -> &[u8] {
+        match self.body.windows(4).position(|w| w == b"\r\n\r\n") {
+            Some(pos) => &self.body[..pos + 4],
+            None => &self.body,
+        }
+    }
+
+    fn text(&self) -> &[u8] {
+        &self.body[self.header().len()..]
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Script {
+    command: String,
+    action: Action,
+}
+
+#[derive(Clone, Debug)]
+enum Action {
+    Respond {
+        untagged: Vec<String>,
+        completion: String,
+    },
+    Disconnect,
+}
+
+struct Connection<T> {
+    transport: Framed<T, ServerCodec>,
+    state: State,
+    selected: Option<Selected>,
+    server: MockServer,
+}
+
+struct Selected {
+    index: usize,
+    read_only: bool,
+}
+
+impl<T> Connection<T>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    async fn run(&mut self) -> io::Result<()> {
+        let greeting = self.server.greeting.clone();
+        self.raw(&greeting);
+        self.flush().await?;
+
+        while let Some(msg) = self.transport.next().await {
+            let (tag, name, body) = match msg? {
+                ClientMessage::LiteralPending { .. } => {
+                    self.send(&Response::Continue {
+                        code: None,
+                        information: Some(Cow::Borrowed("Ready for literal data")),
+                    })
+                    .await?;
+                    self.flush().await?;
+                    continue;
+                }
+                ClientMessage::Command(cmd) => {
+                    let name = command_name(cmd.raw());
+                    (cmd.request_id().clone(), name, Some(cmd))
+                }
+                ClientMessage::Line(line) => match line.split(|&b| b == b' ').next() {
+                    Some(tag) if line.contains(&b' ') => (
+                        RequestId(String::from_utf8_lossy(tag).into_owned()),
+                        command_name(&line),
+                        None,
+                    ),
+                    _ => {
+                        self.raw("* BAD Invalid command");
+                        self.flush().await?;
+                        continue;
+                    }
+                },
+            };
+
+            let script = self
+                .server
+                .scripts
+                .iter()
+                .position(|s| s.command == name)
+                .and_then(|i| self.server.scripts.remove(i));
+            let done = match script {
+                Some(script) => match script.action {
+                    Action::Respond {
+                        untagged,
+                        completion,
+                    } => {
+                        for line in &untagged {
+                            self.raw(line);
+                        }
+                        self.raw(&format!("{} {}", tag.0, completion));
+                        untagged.iter().any(|l| l.starts_with("* BYE"))
+                    }
+                    Action::Disconnect => return Ok(()),
+                },
+                None => match body {
+                    Some(cmd) => self.handle(&tag, &cmd.parsed().body).await?,
+                    None => {
+                        self.done(&tag, Status::Bad, "Unknown command").await?;
+                        false
+                    }
+                },
+            };
+            self.flush().await?;
+            if done {
+                return Ok(());
+            }
+        }
+        Ok(())
+    }
+
+    // Returns `true` if the connection should be closed
+    async fn handle(&mut self, tag: &RequestId, body: &CommandBody<'_>) -> io::Result<bool> {
+        let authenticated = self.state != State::NotAuthenticated;
+        match body {
+            CommandBody::Capability => {
+                let caps = self.capabilities();
+                self.send(&Response::Capabilities(caps)).await?;
+                self.done(tag, Status::Ok, "CAPABILITY completed").await?;
+            }
+            CommandBody::Noop => {
+                self.done(tag, Status::Ok, "NOOP completed").await?;
+            }
+            CommandBody::Logout => {
+                self.send(&Response::Data {
+                    status: Status::Bye,
+                    code: None,
+                    information: Some(Cow::Borrowed("Logging out")),
+                })
+                .await?;
+                self.done(tag, Status::Ok, "LOGOUT completed").await?;
+                self.state = State::Logout;
+                return Ok(true);
+            }
+            CommandBody::Login { username, password } => {
+                let valid = self.server.users.is_empty()
+                    || self
+                        .server
+                        .users
+                        .iter()
+                        .any(|(u, p)| u == username && p == password);
+                if authenticated {
+                    self.done(tag, Status::Bad, "Already authenticated").await?;
+                } else if valid {
+                    self.state = State::Authenticated;
+                    let code = ResponseCode::Capabilities(self.capabilities());
+                    self.done_with_code(tag, Status::Ok, Some(code), "LOGIN completed")
+                        .await?;
+                } else {
+                    self.done(tag, Status::No, "Invalid credentials").await?;
+                }
+            }
+            CommandBody::Authenticate { .. } | CommandBody::StartTls => {
+                self.done(tag, Status::No, "Not supported").await?;
+            }
+            _ if !authenticated => {
+                self.done(tag, Status::Bad, "Not authenticated").await?;
+            }
+            CommandBody::Select { mailbox, .. } | CommandBody::Examine { mailbox, .. } => {
+                let read_only = matches!(body, CommandBody::Examine { .. });
+                self.select(tag, mailbox, read_only).await?;
+            }
+            CommandBody::List { pattern, .. } | CommandBody::Lsub { pattern, .. } => {
+                let names = self
+                    .server
+                    .mailboxes
+                    .iter()
+                    .filter(|mb| matches_pattern(pattern, &mb.name))
+                    .map(|mb| mb.name.clone())
+                    .collect::<Vec<_>>();
+                for name in names {
+                    self.send(&Response::MailboxData(MailboxDatum::List {
+                        name_attributes: vec![NameAttribute::Unmarked],
+                        delimiter: Some(Cow::Borrowed("/")),
+                        name: Cow::Owned(name),
+                    }))
+                    .await?;
+                }
+                self.done(tag, Status::Ok, "LIST completed").await?;
+            }
+            CommandBody::Status { mailbox, items } => {
+                let mb = match self.server.mailboxes.iter().find(|mb| mb.name == *mailbox) {
+                    Some(mb) => mb,
+                    None => {
+                        self.done(tag, Status::No, "No such mailbox").await?;
+                        return Ok(false);
+                    }
+                };
+                let unseen = mb
+                    .messages
+                    .iter()
+                    .filter(|m| !m.flags.iter().any(|f| f == "\\Seen"))
+                    .count() as u32;
+                // In units of 1024 octets, rounded up
+                let deleted_storage = mb
+                    .messages
+                    .iter()
+                    .filter(|m| m.flags.iter().any(|f| f == "\\Deleted"))
+                    .map(|m| (m.body.len() as u64).div_ceil(1024))
+                    .sum();
+                let status = items
+                    .iter()
+                    .filter_map(|item| {
+                        Some(match item {
+                            StatusItem::HighestModSeq => StatusAttribute::HighestModSeq(1),
+                            StatusItem::Messages => {
+                                StatusAttribute::Messages(mb.messages.len() as u32)
+                            }
+                            StatusItem::Recent => StatusAttribute::Recent(0),
+                            StatusItem::UidNext => StatusAttribute::UidNext(mb.uid_next),
+                            StatusItem::UidValidity => {
+                                StatusAttribute::UidValidity(mb.uid_validity)
+                            }
+                            StatusItem::Unseen => StatusAttribute::Unseen(unseen),
+                            StatusItem::DeletedStorage => {
+                                StatusAttribute::DeletedStorage(deleted_storage)
+                            }
+                            _ => return None,
+                        })
+                    })
+                    .collect();
+                let rsp = Response::MailboxData(MailboxDatum::Status {
+                    mailbox: Cow::Owned(mb.name.clone()),
+                    status,
+                });
+                self.send(&rsp).await?;
+                self.done(tag, Status::Ok, "STATUS completed").await?;
+            }
+            CommandBody::Idle => {
+                self.idle(tag).await?;
+            }
+            _ if self.selected.is_none() => {
+                self.done(tag, Status::Bad, "No mailbox selected").await?;
+            }
+            CommandBody::Check => {
+                self.done(tag, Status::Ok, "CHECK completed").await?;
+            }
+            CommandBody::Close => {
+                self.selected = None;
+                self.state = State::Authenticated;
+                self.done(tag, Status::Ok, "CLOSE completed").await?;
+            }
+            CommandBody::Expunge => {
+                let index = self.selected.as_ref().unwrap().index;
+                let mut expunged = Vec::new();
+                let messages = &mut self.server.mailboxes[index].messages;
+                let mut seq = 1;
+                messages.retain(|m| {
+                    let deleted = m.flags.iter().any(|f| f == "\\Deleted");
+                    if deleted {
+                        expunged.push(seq);
+                    } else {
+                        seq += 1;
+                    }
+                    !deleted
+                });
+                for seq in expunged {
+                    self.send(&Response::Expunge(seq)).await?;
+                }
+                self.done(tag, Status::Ok, "EXPUNGE completed").await?;
+            }
+            CommandBody::Fetch {
+                sequence_set,
+                attributes,
+                uid,
+                ..
+            } => {
+                self.fetch(sequence_set, attributes, *uid).await?;
+                self.done(tag, Status::Ok, "FETCH completed").await?;
+            }
+            CommandBody::Store {
+                sequence_set,
+                kind,
+                silent,
+                flags,
+                uid,
+                ..
+            } => {
+                if self.selected.as_ref().unwrap().read_only {
+                    self.done(tag, Status::No, "Mailbox is read-only").await?;
+                    return Ok(false);
+                }
+                let index = self.selected.as_ref().unwrap().index;
+                let matched = resolve(&self.server.mailboxes[index].messages, sequence_set, *uid);
+                for i in matched {
+                    let message = &mut self.server.mailboxes[index].messages[i];
+                    for flag in flags {
+                        let present = message.flags.iter().position(|f| f == flag);
+                        match (kind, present) {
+                            (StoreKind::Remove, Some(pos)) => {
+                                message.flags.remove(pos);
+                            }
+                            (StoreKind::Add, None) => message.flags.push(flag.to_string()),
+                            _ => {}
+                        }
+                    }
+                    if *kind == StoreKind::Replace {
+                        message.flags = flags.iter().map(|f| f.to_string()).collect();
+                    }
+                    if *silent {
+                        continue;
+                    }
+                    let mut attrs = vec![AttributeValue::Flags(
+                        message.flags.iter().cloned().map(Cow::Owned).collect(),
+                    )];
+                    if *uid {
+                        attrs.push(AttributeValue::Uid(message.uid));
+                    }
+                    self.send(&Response::Fetch(i as u32 + 1, attrs)).await?;
+                }
+                self.done(tag, Status::Ok, "STORE completed").await?;
+            }
+            _ => {
+                self.done(tag, Status::Bad, "Command not supported").await?;
+            }
+        }
+        Ok(false)
+    }
+
+    async fn select(&mut self, tag: &RequestId, mailbox: &str, read_only: bool) -> io::Result<()> {
+        let index = match self
+            .server
+            .mailboxes
+            .iter()
+            .position(|mb| mb.name == mailbox)
+        {
+            Some(index) => index,
+            None => {
+                self.selected = None;
+                self.state = State::Authenticated;
+                return self.done(tag, Status::No, "No such mailbox").await;
+            }
+        };
+
+        let mb = &self.server.mailboxes[index];
+        let (exists, uid_validity, uid_next) =
+            (mb.messages.len() as u32, mb.uid_validity, mb.uid_next);
+        let flags = ["\\Answered", "\\Flagged", "\\Deleted", "\\Seen", "\\Draft"];
+        self.send(&Response::MailboxData(MailboxDatum::Flags(
+            flags.iter().map(|f| Cow::Borrowed(*f)).collect(),
+        )))
+        .await?;
+        self.send(&Response::MailboxData(MailboxDatum::Exists(exists)))
+            .await?;
+        self.send(&Response::MailboxData(MailboxDatum::Recent(0)))
+            .await?;
+        for code in [
+            ResponseCode::UidValidity(uid_validity),
+            ResponseCode::UidNext(uid_next),
+        ] {
+            self.send(&Response::Data {
+                status: Status::Ok,
+                code: Some(code),
+                information: None,
+            })
+            .await?;
+        }
+
+        self.selected = Some(Selected { index, read_only });
+        self.state = State::Selected;
+        let code = match read_only {
+            true => ResponseCode::ReadOnly,
+            false => ResponseCode::ReadWrite,
+        };
+        self.done_with_code(tag, Status::Ok, Some(code), "SELECT completed")
+            .await
+    }
+
+    async fn fetch(
+        &mut self,
+        sequence_set: &[SequenceItem],
+        attributes: &FetchAttributes<'_>,
+        uid: bool,
+    ) -> io::Result<()> {
+        let macro_attrs;
+        let attributes = match attributes {
+            FetchAttributes::List(list) => &list[..],
+            FetchAttributes::Macro(m) => {
+                macro_attrs = match m {
+                    AttrMacro::All | AttrMacro::Fast | AttrMacro::Full => [
+                        FetchAttribute::Flags,
+                        FetchAttribute::InternalDate,
+                        FetchAttribute::Rfc822Size,
+                    ],
+                };
+                &macro_attrs[..]
+            }
+        };
+
+        let index = self.selected.as_ref().unwrap().index;
+        let messages = &self.server.mailboxes[index].messages;
+        let mut responses = Vec::new();
+        for i in resolve(messages, sequence_set, uid) {
+            let message = &messages[i];
+            let mut values = Vec::new();
+            if uid && !attributes.contains(&FetchAttribute::Uid) {
+                values.push(AttributeValue::Uid(message.uid));
+            }
+            for attr in attributes {
+                let value = match attr {
+                    FetchAttribute::Uid => AttributeValue::Uid(message.uid),
+                    FetchAttribute::Flags => AttributeValue::Flags(
+                        message.flags.iter().cloned().map(Cow::Owned).collect(),
+                    ),
+                    FetchAttribute::InternalDate => {
+                        AttributeValue::InternalDate(Cow::Owned(message.internal_date.clone()))
+                    }
+                    FetchAttribute::Rfc822Size => {
+                        AttributeValue::Rfc822Size(message.body.len() as u32)
+                    }
+                    FetchAttribute::Rfc822 => {
+                        AttributeValue::Rfc822(Some(Cow::Owned(message.body.clone())))
+                    }
+                    FetchAttribute::Rfc822Header => {
+                        AttributeValue::Rfc822Header(Some(Cow::Owned(message.header().to_vec())))
+                    }
+                    FetchAttribute::Rfc822Text => {
+                        AttributeValue::Rfc822Text(Some(Cow::Owned(message.text().to_vec())))
+                    }
+                    FetchAttribute::BodySection {
+                        section, partial, ..
+                    } => body_section(message, section, *partial),
+                    // Not implemented: ENVELOPE, BODY, BODYSTRUCTURE, MODSEQ
+                    _ => continue,
+                };
+                values.push(value);
+            }
+            responses.push(Response::Fetch(i as u32 + 1, values));
+        }
+
+        for rsp in &responses {
+            self.send(rsp).await?;
+        }
+        Ok(())
+    }
+
+    async fn idle(&mut self, tag: &RequestId) -> io::Result<()> {
+        self.send(&Response::Continue {
+            code: None,
+            information: Some(Cow::Borrowed("idling")),
+        })
+        .await?;
+        for line in self.server.idle_events.clone() {
+            self.raw(&line);
+        }
+        self.flush().await?;
+
+        match self.transport.next().await {
+            Some(Ok(ClientMessage::Line(line))) if line.eq_ignore_ascii_case(b"DONE\r\n") => {
+                self.done(tag, Status::Ok, "IDLE terminated").await
+            }
+            Some(Ok(_)) => self.done(tag, Status::Bad, "Expected DONE").await,
+            Some(Err(e)) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    fn capabilities(&self) -> Vec<Capability<'static>> {
+        self.server
+            .capabilities
+            .iter()
+            .map(|cap| match cap.as_str() {
+                "IMAP4rev1" => Capability::Imap4rev1,
+                cap => match cap.strip_prefix("AUTH=") {
+                    Some(mechanism) => Capability::Auth(Cow::Owned(mechanism.to_owned())),
+                    None => Capability::Atom(Cow::Owned(cap.to_owned())),
+                },
+            })
+            .collect()
+    }
+
+    async fn done(&mut self, tag: &RequestId, status: Status, text: &str) -> io::Result<()> {
+        self.done_with_code(tag, status, None, text).await
+    }
+
+    async fn done_with_code(
+        &mut self,
+        tag: &RequestId,
+        status: Status,
+        code: Option<ResponseCode<'_>>,
+        text: &str,
+    ) -> io::Result<()> {
+        self.send(&Response::Done {
+            tag: tag.clone(),
+            status,
+            code,
+            information: Some(Cow::Borrowed(text)),
+        })
+        .await
+    }
+
+    async fn send(&mut self, rsp: &Response<'_>) -> io::Result<()> {
+        poll_fn(|cx| Pin::new(&mut self.transport).poll_ready(cx)).await?;
+        Pin::new(&mut self.transport).start_send(rsp)
+    }
+
+    // Scripted lines are sent as-is, so that they can contain anything
+    // (including things the encoder would refuse to produce).
+    fn raw(&mut self, line: &str) {
+        let buf = self.transport.write_buffer_mut();
+        buf.extend_from_slice(line.as_bytes());
+        buf.extend_from_slice(b"\r\n");
+    }
+
+    async fn flush(&mut self) -> io::Result<()> {
+        poll_fn(|cx| Sink::<&Response<'_>>::poll_flush(Pin::new(&mut self.transport), cx)).await
+    }
+}
+
+// The command name following the tag, including the `UID` prefix if present
+fn command_name(line: &[u8]) -> String {
+    let line = String::from_utf8_lossy(line);
+    let mut words = line
+        .trim_end_matches("\r\n")
+        .split(' ')
+        .skip(1)
+        .map(|w| w.to_ascii_uppercase());
+    match words.next() {
+        Some(first) if first == "UID" => match words.next() {
+            Some(second) => format!("UID {second}"),
+            None => first,
+        },
+        Some(first) => first,
+        None => String::new(),
+    }
+}
+
+// Only supports patterns that match everything, or exact names
+fn matches_pattern(pattern: &str, name: &str) -> bool {
+    pattern == "*" || pattern == "%" || pattern == name
+}
+
+// Indices into `messages` for the messages matching `set`
+fn resolve(messages: &[MockMessage], set: &[SequenceItem], uid: bool) -> Vec<usize> {
+    let key = |i: usize| match uid {
+        true => messages[i].uid,
+        false => i as u32 + 1,
+    };
+    let largest = match messages.len() {
+        0 => return Vec::new(),
+        n => key(n - 1),
+    };
+    let value = |num: &SequenceNumber| match num {
+        SequenceNumber::Number(n) => *n,
+        SequenceNumber::Largest => largest,
+    };
+
+    (0..messages.len())
+        .filter(|&i| {
+            let k = key(i);
+            set.iter().any(|item| match item {
+                SequenceItem::Single(n) => value(n) == k,
+                SequenceItem::Range(a, b) => {
+                    let (a, b) = (value(a), value(b));
+                    a.min(b) <= k && k <= a.max(b)
+                }
+            })
+        })
+        .collect()
+}
+
+fn body_section(
+    message: &MockMessage,
+    section: &Section<'_>,
+    partial: Option<(u32, u32)>,
+) -> AttributeValue<'static> {
+    let (path, data) = match (&section.part[..], &section.text) {
+        ([], None) => (None, Some(message.body.clone())),
+        ([], Some(SectionText::Header)) => (
+            Some(SectionPath::Full(MessageSection::Header)),
+            Some(message.header().to_vec()),
+        ),
+        ([], Some(SectionText::HeaderFields(fields))) => (
+            Some(SectionPath::Full(MessageSection::Header)),
+            Some(header_fields(message.header(), fields, true)),
+        ),
+        ([], Some(SectionText::HeaderFieldsNot(fields))) => (
+            Some(SectionPath::Full(MessageSection::Header)),
+            Some(header_fields(message.header(), fields, false)),
+        ),
+        ([], Some(SectionText::Text)) => (
+            Some(SectionPath::Full(MessageSection::Text)),
+            Some(message.text().to_vec()),
+        ),
+        // Messages are treated as single-part, so part 1 is the body text
+        ([1], None) => (
+            Some(SectionPath::Part(vec![1], None)),
+            Some(message.text().to_vec()),
+        ),
+        (part, _) => (Some(SectionPath::Part(part.to_vec(), None)), None),
+    };
+
+    let (index, data) = match (partial, data) {
+        (Some((start, len)), Some(data)) => {
+            let start = (start as usize).min(data.len());
+            let end = start.saturating_add(len as usize).min(data.len());
+            (Some(start as u32), Some(data[start..end].to_vec()))
+        }
+        (_, data) => (None, data),
+    };
+
+    AttributeValue::BodySection {
+        section: path,
+        index,
+        data: data.map(Cow::Owned),
+    }
+}
+
+// Select header fields (including their continuation lines) by name
+fn header_fields(header: &[u8], fields: &[Cow<'_, str>], include: bool) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut keep = false;
+    for line in header.split_inclusive(|&b| b == b'\n') {
+        if line == b"\r\n" {
+            break;
+        }
+        if !line.starts_with(b" ") && !line.starts_with(b"\t") {
+            let name = line.split(|&b| b == b':').next().unwrap_or_default();
+            let listed = fields
+                .iter()
+                .any(|f| f.as_bytes().eq_ignore_ascii_case(name));
+            keep = listed == include;
+        }
+        if keep {
+            out.extend_from_slice(line);
+        }
+    }
+    out.extend_from_slice(b"\r\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::{ImapCodec, ResponseData, ResponseFrame};
+    use tokio::io::AsyncWriteExt;
+
+    async fn send(transport: &mut Framed<DuplexStream, ImapCodec>, line: &str) {
+        let line = format!("{line}\r\n");
+        transport
+            .get_mut()
+            .write_all(line.as_bytes())
+            .await
+            .unwrap();
+    }
+
+    async fn next(transport: &mut Framed<DuplexStream, ImapCodec>) -> Option<ResponseData> {
+        match transport.next().await? {
+            Ok(ResponseFrame::Response(rsp)) => Some(rsp),
+            frame => panic!("unexpected frame {frame:?}"),
+        }
+    }
+
+    async fn call(
+        transport: &mut Framed<DuplexStream, ImapCodec>,
+        tag: &str,
+        cmd: &str,
+    ) -> Vec<String> {
+        send(transport, &format!("{tag} {cmd}")).await;
+        let mut lines = Vec::new();
+        while let Some(rsp) = next(transport).await {
+            let done = rsp.request_id().is_some();
+            lines.push(format!("{:?}", rsp.parsed()));
+            if done {
+                break;
+            }
+        }
+        lines
+    }
+
+    fn server() -> MockServer {
+        MockServer::new().user("djc", "s3cr3t").mailbox(
+            MockMailbox::new("INBOX")
+                .uid_validity(42)
+                .message(MockMessage::new("Subject: one\r\nFrom: a@b\r\n\r\nfirst").flag("\\Seen"))
+                .message(MockMessage::new("Subject: two\r\n\r\nsecond")),
+        )
+    }
+
+    #[tokio::test]
+    async fn session() {
+        let (stream, handle) = server().respond("NOOP", &["* 3 EXISTS"]).connect();
+        let mut transport = ImapCodec::default().framed(stream);
+        let greeting = next(&mut transport).await.unwrap();
+        assert!(matches!(
+            greeting.parsed(),
+            Response::Data {
+                status: Status::Ok,
+                ..
+            }
+        ));
+
+        let rsp = call(&mut transport, "A1", "LOGIN djc wrong").await;
+        assert!(rsp[0].contains("No"), "{rsp:?}");
+        let rsp = call(&mut transport, "A2", "LOGIN djc s3cr3t").await;
+        assert!(rsp[0].contains("Ok"), "{rsp:?}");
+
+        let rsp = call(&mut transport, "A3", "SELECT INBOX").await;
+        assert!(rsp.iter().any(|l| l.contains("Exists(2)")), "{rsp:?}");
+        assert!(rsp.iter().any(|l| l.contains("UidValidity(42)")), "{rsp:?}");
+
+        let rsp = call(
+            &mut transport,
+            "A4",
+            "UID FETCH 2:* (FLAGS BODY.PEEK[HEADER.FIELDS (SUBJECT)])",
+        )
+        .await;
+        assert_eq!(rsp.len(), 2, "{rsp:?}");
+        assert!(rsp[0].contains("Uid(2)"), "{rsp:?}");
+        let header = format!("{:?}", b"Subject: two\r\n\r\n".to_vec());
+        assert!(rsp[0].contains(&header), "{rsp:?}");
+
+        let rsp = call(&mut transport, "A5", "STORE 1:2 +FLAGS (\\Deleted)").await;
+        assert_eq!(rsp.len(), 3, "{rsp:?}");
+        let rsp = call(&mut transport, "A6", "STATUS INBOX (DELETED-STORAGE)").await;
+        assert!(rsp[0].contains("DeletedStorage(2)"), "{rsp:?}");
+        let rsp = call(&mut transport, "A7", "EXPUNGE").await;
+        assert_eq!(rsp[..2], ["Expunge(1)", "Expunge(1)"], "{rsp:?}");
+
+        let rsp = call(&mut transport, "A8", "NOOP").await;
+        assert_eq!(rsp[0], "MailboxData(Exists(3))");
+        let rsp = call(&mut transport, "A9", "LOGOUT").await;
+        assert!(rsp[0].contains("Bye"), "{rsp:?}");
+        handle.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn idle() {
+        let (stream, handle) = server().idle_events(&["* 3 EXISTS"]).connect();
+        let mut transport = ImapCodec::default().framed(stream);
+        next(&mut transport).await.unwrap();
+        call(&mut transport, "A1", "LOGIN djc s3cr3t").await;
+        call(&mut transport, "A2", "SELECT INBOX").await;
+
+        send(&mut transport, "A3 IDLE").await;
+        let rsp = next(&mut transport).await.unwrap();
+        assert!(matches!(rsp.parsed(), Response::Continue { .. }));
+        let rsp = next(&mut transport).await.unwrap();
+        assert!(matches!(
+            rsp.parsed(),
+            Response::MailboxData(MailboxDatum::Exists(3))
+        ));
+
+        send(&mut transport, "DONE").await;
+        let rsp = next(&mut transport).await.unwrap();
+        assert_eq!(rsp.request_id(), Some(&RequestId("A3".into())));
+
+        drop(transport);
+        handle.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn scripted() {
+        let (stream, handle) = MockServer::new()
+            .respond_with(
+                "GETQUOTAROOT",
+                &["* QUOTAROOT INBOX \"\""],
+                "NO [ALERT] Over quota",
+            )
+            .disconnect("NOOP")
+            .connect();
+        let mut transport = ImapCodec::default().framed(stream);
+        next(&mut transport).await.unwrap();
+        call(&mut transport, "A1", "LOGIN djc s3cr3t").await;
+
+        let rsp = call(&mut transport, "A2", "GETQUOTAROOT INBOX").await;
+        assert!(rsp[0].starts_with("QuotaRoot"), "{rsp:?}");
+        assert!(rsp[1].contains("No"), "{rsp:?}");
+
+        let rsp = call(&mut transport, "A3", "FROBNICATE").await;
+        assert!(rsp[0].contains("Bad"), "{rsp:?}");
+
+        send(&mut transport, "A4 NOOP").await;
+        assert!(next(&mut transport).await.is_none());
+        handle.await.unwrap().unwrap();
+    }
+}
@@ -0,0 +1,330 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::io;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures_util::TryStreamExt;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::Notify;
+use tokio::time::Instant;
+
+use crate::client::Client;
+use imap_proto::builders::command::CommandBuilder;
+use imap_proto::{Response, State, Status};
+
+/// A pool of authenticated clients, keyed by server and account
+///
+/// Connections are opened by the `connect` function passed to `Pool::new()`,
+/// which should return a client that is authenticated for the given account.
+/// Clients are returned to the pool when the `PooledClient` is dropped, unless
+/// the server sent `BYE` or the client was discarded with `PooledClient::discard()`.
+///
+/// The number of connections to a single server (for all accounts together) is
+/// limited; once the limit is reached, `get()` closes an idle connection for
+/// another account, or waits until a connection is returned to the pool.
+/// Connections that have been idle for a while are checked with a `NOOP`
+/// before they are handed out again.
+pub struct Pool<T, C> {
+    shared: Arc<Shared<T>>,
+    connect: Arc<C>,
+    max_per_server: usize,
+    check_after: Duration,
+}
+
+impl<T, C, F> Pool<T, C>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+    C: Fn(PoolKey) -> F,
+    F: Future<Output = io::Result<Client<T>>>,
+{
+    pub fn new(connect: C) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                servers: Mutex::new(HashMap::new()),
+                released: Notify::new(),
+            }),
+            connect: Arc::new(connect),
+            max_per_server: 10,
+            check_after: Duration::from_secs(60),
+        }
+    }
+
+    /// Limit the number of connections to a single server (10 by default)
+    pub fn max_per_server(mut self, max: usize) -> Self {
+        self.max_per_server = max;
+        self
+    }
+
+    /// Check connections that have been idle for this long (60 seconds by default)
+    pub fn check_after(mut self, idle: Duration) -> Self {
+        self.check_after = idle;
+        self
+    }
+
+    /// Get a client for `account` on `server`, connecting if necessary
+    pub async fn get(&self, server: &str, account: &str) -> io::Result<PooledClient<T>> {
+        let key = PoolKey {
+            server: server.to_owned(),
+            account: account.to_owned(),
+        };
+
+        let mut pooled = loop {
+            match self.reserve(&key) {
+                Reserved::Idle(idle) => {
+                    let mut pooled = self.pooled(key, Some(idle.client));
+                    if idle.since.elapsed() < self.check_after || check(&mut pooled).await {
+                        return Ok(pooled);
+                    }
+                    // Replace the broken connection, keeping its slot
+                    pooled.client = None;
+                    break pooled;
+                }
+                Reserved::Slot => break self.pooled(key, None),
+                Reserved::Full(released) => released.await,
+            }
+        };
+
+        // If this fails, dropping `pooled` releases the slot
+        pooled.client = Some((self.connect)(pooled.key.clone()).await?);
+        Ok(pooled)
+    }
+
+    fn pooled(&self, key: PoolKey, client: Option<Client<T>>) -> PooledClient<T> {
+        PooledClient {
+            client,
+            key,
+            shared: self.shared.clone(),
+        }
+    }
+
+    // Take an idle client or a slot for a new connection
+    fn reserve(&self, key: &PoolKey) -> Reserved<'_, T> {
+        let mut servers = self.shared.servers.lock().unwrap();
+        let server = servers.entry(key.server.clone()).or_default();
+        if let Some(pos) = server.idle.iter().position(|i| i.account == key.account) {
+            return Reserved::Idle(Box::new(server.idle.remove(pos)));
+        }
+
+        if server.open < self.max_per_server {
+            server.open += 1;
+            return Reserved::Slot;
+        }
+
+        // Close the connection that has been idle the longest, if any
+        match server.idle.is_empty() {
+            false => {
+                server.idle.remove(0);
+                Reserved::Slot
+            }
+            true => Reserved::Full(self.shared.released.notified()),
+        }
+    }
+}
+
+impl<T, C> Clone for Pool<T, C> {
+    fn clone(&self) -> Self {
+        Self {
+            shared: self.shared.clone(),
+            connect: self.connect.clone(),
+            max_per_server: self.max_per_server,
+            check_after: self.check_after,
+        }
+    }
+}
+
+/// The server and account a pooled connection belongs to
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PoolKey {
+    pub server: String,
+    pub account: String,
+}
+
+/// A client borrowed from a `Pool`
+///
+/// Dereferences to the `Client`, and returns it to the pool when dropped.
+pub struct PooledClient<T> {
+    client: Option<Client<T>>,
+    key: PoolKey,
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> PooledClient<T> {
+    pub fn key(&self) -> &PoolKey {
+        &self.key
+    }
+
+    /// Close the connection instead of returning it to the pool
+    ///
+    /// Use this after errors that may have left the connection in a bad state.
+    pub fn discard(mut self) {
+        self.client = None;
+    }
+}
+
+impl<T> Deref for PooledClient<T> {
+    type Target = Client<T>;
+
+    fn deref(&self) -> &Client<T> {
+        self.client.as_ref().unwrap()
+    }
+}
+
+impl<T> DerefMut for PooledClient<T> {
+    fn deref_mut(&mut self) -> &mut Client<T> {
+        self.client.as_mut().unwrap()
+    }
+}
+
+impl<T> Drop for PooledClient<T> {
+    fn drop(&mut self) {
+        let mut servers = self.shared.servers.lock().unwrap();
+        let server = servers.entry(self.key.server.clone()).or_default();
+        match self.client.take() {
+            Some(client) if client.state() != State::Logout => server.idle.push(Idle {
+                account: self.key.account.clone(),
+                client,
+                since: Instant::now(),
+            }),
+            _ => server.open -= 1,
+        }
+        self.shared.released.notify_waiters();
+    }
+}
+
+struct Shared<T> {
+    servers: Mutex<HashMap<String, Server<T>>>,
+    /// Notified when a connection is returned to the pool or closed
+    released: Notify,
+}
+
+struct Server<T> {
+    /// Number of connections, including idle ones
+    open: usize,
+    /// Idle connections, oldest first
+    idle: Vec<Idle<T>>,
+}
+
+impl<T> Default for Server<T> {
+    fn default() -> Self {
+        Self {
+            open: 0,
+            idle: Vec::new(),
+        }
+    }
+}
+
+struct Idle<T> {
+    account: String,
+    client: Client<T>,
+    since: Instant,
+}
+
+enum Reserved<'a, T> {
+    Idle(Box<Idle<T>>),
+    Slot,
+    Full(tokio::sync::futures::Notified<'a>),
+}
+
+// Check that an idle connection still works
+async fn check<T>(client: &mut Client<T>) -> bool
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    let responses = match client
+        .call(CommandBuilder::noop())
+        .try_collect::<Vec<_>>()
+        .await
+    {
+        Ok(responses) => responses,
+        Err(_) => return false,
+    };
+
+    let ok = matches!(
+        responses.last().map(|rsp| rsp.parsed()),
+        Some(Response::Done {
+            status: Status::Ok,
+            ..
+        })
+    );
+    ok && client.state() != State::Logout
+}
+
+#[cfg(test)]
+mod tests {
+    use std::pin::Pin;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use tokio::io::DuplexStream;
+
+    use super::*;
+    use crate::mock::MockServer;
+
+    type Connecting = Pin<Box<dyn Future<Output = io::Result<Client<DuplexStream>>> + Send>>;
+
+    fn pool(connects: Arc<AtomicU32>) -> Pool<DuplexStream, impl Fn(PoolKey) -> Connecting> {
+        Pool::new(move |key: PoolKey| -> Connecting {
+            connects.fetch_add(1, Ordering::SeqCst);
+            Box::pin(async move {
+                let (stream, _) = MockServer::new().connect();
+                let (_, mut client) = Client::from_stream(stream).await?;
+                let login = CommandBuilder::login(&key.account, "s3cr3t");
+                client.call(login).try_collect::<Vec<_>>().await?;
+                Ok(client)
+            })
+        })
+    }
+
+    #[tokio::test]
+    async fn reuse() {
+        let connects = Arc::new(AtomicU32::new(0));
+        let pool = pool(connects.clone());
+
+        let client = pool.get("imap.example.com", "djc").await.unwrap();
+        assert_eq!(client.state(), State::Authenticated);
+        drop(client);
+        let mut client = pool.get("imap.example.com", "djc").await.unwrap();
+        assert_eq!(connects.load(Ordering::SeqCst), 1);
+
+        // Connections that received BYE are not reused
+        let logout = CommandBuilder::logout();
+        client.call(logout).try_collect::<Vec<_>>().await.unwrap();
+        assert_eq!(client.state(), State::Logout);
+        drop(client);
+        let client = pool.get("imap.example.com", "djc").await.unwrap();
+        assert_eq!(connects.load(Ordering::SeqCst), 2);
+
+        // Idle connections are checked before reuse
+        drop(client);
+        let pool = pool.check_after(Duration::ZERO);
+        let client = pool.get("imap.example.com", "djc").await.unwrap();
+        assert_eq!(client.state(), State::Authenticated);
+        assert_eq!(connects.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn limit() {
+        let connects = Arc::new(AtomicU32::new(0));
+        let pool = pool(connects.clone()).max_per_server(1);
+
+        let first = pool.get("imap.example.com", "djc").await.unwrap();
+        let other = pool.get("imap.example.org", "djc").await.unwrap();
+        let waiting = tokio::spawn({
+            let pool = pool.clone();
+            async move {
+                let client = pool.get("imap.example.com", "other").await.unwrap();
+                client.key().clone()
+            }
+        });
+        tokio::task::yield_now().await;
+        assert!(!waiting.is_finished());
+
+        // Returning the connection to the pool makes room by closing it
+        drop(first);
+        let key = waiting.await.unwrap();
+        assert_eq!(key.account, "other");
+        assert_eq!(connects.load(Ordering::SeqCst), 3);
+        drop(other);
+    }
+}
@@ -0,0 +1,477 @@
+use std::future::Future;
+use std::io;
+use std::time::Duration;
+
+use futures_util::TryStreamExt;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::client::Client;
+use crate::codec::ResponseData;
+use imap_proto::builders::command::{Command, CommandBuilder};
+use imap_proto::{Response, ResponseCode, State, Status};
+
+/// A `Client` that can restore its session on a new connection
+///
+/// The session consists of the authentication, the capabilities enabled with
+/// `ENABLE` and the selected mailbox. After a connection fails, call
+/// `reconnect()`: it connects again (backing off between attempts), replays
+/// the session and reports whether the mailbox's `UIDVALIDITY` changed, in
+/// which case any UIDs cached for it are no longer valid, or whether the
+/// mailbox could not be selected at all.
+///
+/// ```no_run
+/// # async fn run() -> std::io::Result<()> {
+/// use tokio_imap::{Backoff, ReconnectingClient, TlsClient};
+///
+/// let mut session = ReconnectingClient::new(|| async {
+///     TlsClient::connect("imap.example.com").await.map(|(_, client)| client)
+/// })
+/// .login("user", "password")
+/// .enable("CONDSTORE")
+/// .backoff(Backoff::new().attempts(5));
+/// session.reconnect().await?;
+/// session.select("INBOX").await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct ReconnectingClient<T, C> {
+    connect: C,
+    backoff: Backoff,
+    login: Option<(String, String)>,
+    enable: Vec<String>,
+    mailbox: Option<String>,
+    uid_validity: Option<u32>,
+    client: Option<Client<T>>,
+}
+
+impl<T, C, F> ReconnectingClient<T, C>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+    C: FnMut() -> F,
+    F: Future<Output = io::Result<Client<T>>>,
+{
+    /// Set up a session that uses `connect` to open connections
+    ///
+    /// `connect` should return a client that has read the server greeting, like
+    /// `TlsClient::connect()` does. No connection is made until `reconnect()`.
+    pub fn new(connect: C) -> Self {
+        Self {
+            connect,
+            backoff: Backoff::new(),
+            login: None,
+            enable: Vec::new(),
+            mailbox: None,
+            uid_validity: None,
+            client: None,
+        }
+    }
+
+    /// Set the policy for retrying failed connection attempts
+    pub fn backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Authenticate with `LOGIN` on every new connection
+    ///
+    /// Skipped if the server greets the client with `PREAUTH`.
+    pub fn login(mut self, user: &str, password: &str) -> Self {
+        self.login = Some((user.to_owned(), password.to_owned()));
+        self
+    }
+
+    /// Enable an extension on every new connection, after authenticating
+    pub fn enable(mut self, capability: &str) -> Self {
+        self.enable.push(capability.to_owned());
+        self
+    }
+
+    /// The client for the current connection, if connected
+    pub fn client(&mut self) -> Option<&mut Client<T>> {
+        self.client.as_mut()
+    }
+
+    /// Select a mailbox, which is selected again after reconnecting
+    ///
+    /// If the server rejects the command, no mailbox is selected anymore.
+    pub async fn select(&mut self, mailbox: &str) -> io::Result<Vec<ResponseData>> {
+        let client = self
+            .client
+            .as_mut()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotConnected, "not connected"))?;
+        let responses = match command(client, CommandBuilder::select(mailbox).into()).await {
+            Ok(responses) => responses,
+            Err(Error::Rejected(e)) => {
+                self.mailbox = None;
+                self.uid_validity = None;
+                return Err(e);
+            }
+            Err(Error::Connection(e)) => return Err(e),
+        };
+        self.mailbox = Some(mailbox.to_owned());
+        self.uid_validity = uid_validity(&responses);
+        Ok(responses)
+    }
+
+    /// Close the selected mailbox, so that none is selected after reconnecting
+    ///
+    /// Like any `CLOSE`, this expunges messages marked `\Deleted`.
+    pub async fn close(&mut self) -> io::Result<Vec<ResponseData>> {
+        let client = self
+            .client
+            .as_mut()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotConnected, "not connected"))?;
+        let responses = command(client, CommandBuilder::close())
+            .await
+            .map_err(Error::into_inner)?;
+        self.mailbox = None;
+        self.uid_validity = None;
+        Ok(responses)
+    }
+
+    /// Drop the current connection (if any), then connect and restore the session
+    ///
+    /// Connecting is retried according to the backoff policy. Errors that a new
+    /// connection won't fix, like rejected credentials, are returned right away.
+    /// If the mailbox can no longer be selected, the session continues without
+    /// a selected mailbox and `Reconnected::mailbox_lost` is set.
+    pub async fn reconnect(&mut self) -> io::Result<Reconnected> {
+        self.client = None;
+        let mut delay = self.backoff.initial;
+        let mut attempt = 1;
+        loop {
+            match self.restore().await {
+                Ok((client, selected)) => {
+                    let mailbox_lost = self.mailbox.is_some() && selected.is_err();
+                    let uid_validity = selected.unwrap_or(None);
+                    let reconnected = Reconnected {
+                        attempts: attempt,
+                        uid_validity,
+                        uid_validity_changed: uid_validity != self.uid_validity,
+                        mailbox_lost,
+                    };
+                    self.client = Some(client);
+                    self.uid_validity = uid_validity;
+                    if mailbox_lost {
+                        self.mailbox = None;
+                    }
+                    return Ok(reconnected);
+                }
+                Err(Error::Rejected(e)) => return Err(e),
+                Err(Error::Connection(e)) => {
+                    if self.backoff.attempts.is_some_and(|max| attempt >= max) {
+                        return Err(e);
+                    }
+                }
+            }
+
+            tokio::time::sleep(delay).await;
+            delay = self.backoff.next(delay);
+            attempt += 1;
+        }
+    }
+
+    // On success, also returns the mailbox's `UIDVALIDITY` or, if selecting the
+    // mailbox was rejected, the error
+    #[allow(clippy::type_complexity)]
+    async fn restore(&mut self) -> Result<(Client<T>, io::Result<Option<u32>>), Error> {
+        let mut client = (self.connect)().await.map_err(Error::Connection)?;
+
+        if client.state() == State::NotAuthenticated {
+            if let Some((user, password)) = &self.login {
+                command(&mut client, CommandBuilder::login(user, password)).await?;
+            }
+        }
+
+        if !self.enable.is_empty() {
+            let cmd = CommandBuilder::enable(self.enable.iter().map(String::as_str));
+            command(&mut client, cmd).await?;
+        }
+
+        let selected = match &self.mailbox {
+            Some(mailbox) => {
+                let cmd = CommandBuilder::select(mailbox).into();
+                match command(&mut client, cmd).await {
+                    Ok(responses) => Ok(uid_validity(&responses)),
+                    Err(Error::Rejected(e)) => Err(e),
+                    Err(e) => return Err(e),
+                }
+            }
+            None => Ok(None),
+        };
+
+        Ok((client, selected))
+    }
+}
+
+/// The result of restoring a session on a new connection
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Reconnected {
+    /// Number of connection attempts made
+    pub attempts: u32,
+    /// `UIDVALIDITY` of the selected mailbox, if any
+    pub uid_validity: Option<u32>,
+    /// Whether `UIDVALIDITY` differs from the one seen before reconnecting
+    ///
+    /// If so, any state cached for the mailbox must be discarded.
+    pub uid_validity_changed: bool,
+    /// Whether the server rejected selecting the mailbox again (for example,
+    /// because it was deleted), so that no mailbox is selected
+    pub mailbox_lost: bool,
+}
+
+/// Policy for retrying failed connection attempts
+///
+/// The delay between attempts starts at `initial` (1 second by default) and is
+/// multiplied by `factor` (2 by default) after every attempt, up to `max` (5
+/// minutes by default). By default, attempts continue indefinitely.
+#[derive(Clone, Copy, Debug)]
+pub struct Backoff {
+    initial: Duration,
+    max: Duration,
+    factor: f64,
+    attempts: Option<u32>,
+}
+
+impl Backoff {
+    pub fn new() -> Self {
+        Self {
+            initial: Duration::from_secs(1),
+            max: Duration::from_secs(300),
+            factor: 2.0,
+            attempts: None,
+        }
+    }
+
+    pub fn initial(mut self, delay: Duration) -> Self {
+        self.initial = delay;
+        self
+    }
+
+    pub fn max(mut self, delay: Duration) -> Self {
+        self.max = delay;
+        self
+    }
+
+    /// Panics if the factor is less than 1.
+    pub fn factor(mut self, factor: f64) -> Self {
+        assert!(factor >= 1.0, "invalid backoff factor {factor}");
+        self.factor = factor;
+        self
+    }
+
+    /// Give up after this many failed attempts
+    pub fn attempts(mut self, attempts: u32) -> Self {
+        self.attempts = Some(attempts);
+        self
+    }
+
+    // The delay after `delay`, which saturates at `max` instead of overflowing
+    fn next(&self, delay: Duration) -> Duration {
+        Duration::try_from_secs_f64(delay.as_secs_f64() * self.factor)
+            .map_or(self.max, |next| next.min(self.max))
+    }
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+enum Error {
+    /// The connection failed; a new connection may fix it
+    Connection(io::Error),
+    /// The server rejected a command
+    Rejected(io::Error),
+}
+
+impl Error {
+    fn into_inner(self) -> io::Error {
+        match self {
+            Error::Connection(e) | Error::Rejected(e) => e,
+        }
+    }
+}
+
+// Run a command, failing if it does not complete successfully
+async fn command<T>(client: &mut Client<T>, cmd: Command) -> Result<Vec<ResponseData>, Error>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    let responses = client
+        .call(cmd)
+        .try_collect::<Vec<_>>()
+        .await
+        .map_err(Error::Connection)?;
+
+    match responses.last().map(|rsp| rsp.parsed()) {
+        Some(Response::Done {
+            status: Status::Ok, ..
+        }) => Ok(responses),
+        Some(Response::Done { information, .. }) => {
+            Err(Error::Rejected(io::Error::other(format!(
+                "command failed: {}",
+                information.as_deref().unwrap_or_default()
+            ))))
+        }
+        _ => Err(Error::Connection(io::Error::other(
+            "missing command completion",
+        ))),
+    }
+}
+
+fn uid_validity(responses: &[ResponseData]) -> Option<u32> {
+    responses.iter().find_map(|rsp| match rsp.parsed() {
+        Response::Data {
+            code: Some(ResponseCode::UidValidity(v)),
+            ..
+        } => Some(*v),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::mock::{MockMailbox, MockServer};
+
+    #[tokio::test(start_paused = true)]
+    async fn reconnect() {
+        let attempts = Arc::new(AtomicU32::new(0));
+        let counter = attempts.clone();
+        let mut session = ReconnectingClient::new(move || {
+            let attempt = counter.fetch_add(1, Ordering::SeqCst);
+            async move {
+                // The second connection fails, the third one has a new UIDVALIDITY
+                let uid_validity = match attempt {
+                    0 => 1,
+                    1 => return Err(io::ErrorKind::ConnectionRefused.into()),
+                    _ => 2,
+                };
+                let (stream, _) = MockServer::new()
+                    .user("djc", "s3cr3t")
+                    .mailbox(MockMailbox::new("INBOX").uid_validity(uid_validity))
+                    .respond("ENABLE", &["* ENABLED CONDSTORE"])
+                    .connect();
+                Client::from_stream(stream).await.map(|(_, client)| client)
+            }
+        })
+        .login("djc", "s3cr3t")
+        .enable("CONDSTORE")
+        .backoff(Backoff::new().attempts(3));
+
+        let reconnected = session.reconnect().await.unwrap();
+        assert_eq!(reconnected.attempts, 1);
+        assert_eq!(reconnected.uid_validity, None);
+        session.select("INBOX").await.unwrap();
+        assert_eq!(session.client().unwrap().state(), State::Selected);
+
+        let reconnected = session.reconnect().await.unwrap();
+        assert_eq!(reconnected.attempts, 2);
+        assert_eq!(reconnected.uid_validity, Some(2));
+        assert!(reconnected.uid_validity_changed);
+        assert_eq!(session.client().unwrap().state(), State::Selected);
+
+        // Rejected credentials are not retried
+        let mut session = ReconnectingClient::new(|| async {
+            let (stream, _) = MockServer::new().user("djc", "s3cr3t").connect();
+            Client::from_stream(stream).await.map(|(_, client)| client)
+        })
+        .login("djc", "wrong");
+        assert!(session.reconnect().await.is_err());
+        assert!(session.client().is_none());
+    }
+
+    #[tokio::test]
+    async fn mailbox_lost() {
+        let attempts = Arc::new(AtomicU32::new(0));
+        let counter = attempts.clone();
+        let mut session = ReconnectingClient::new(move || {
+            // The mailbox is gone after the first connection
+            let mut server = MockServer::new().user("djc", "s3cr3t");
+            if counter.fetch_add(1, Ordering::SeqCst) == 0 {
+                server = server.mailbox(MockMailbox::new("Archive").uid_validity(7));
+            }
+            async move {
+                let (stream, _) = server.connect();
+                Client::from_stream(stream).await.map(|(_, client)| client)
+            }
+        })
+        .login("djc", "s3cr3t");
+
+        session.reconnect().await.unwrap();
+        session.select("Archive").await.unwrap();
+        let reconnected = session.reconnect().await.unwrap();
+        assert!(reconnected.mailbox_lost);
+        assert_eq!(reconnected.uid_validity, None);
+        assert_eq!(session.client().unwrap().state(), State::Authenticated);
+
+        // Nothing is selected on later connections
+        let reconnected = session.reconnect().await.unwrap();
+        assert!(!reconnected.mailbox_lost);
+        assert!(!reconnected.uid_validity_changed);
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn close() {
+        let mut session = ReconnectingClient::new(|| async {
+            let (stream, _) = MockServer::new()
+                .user("djc", "s3cr3t")
+                .mailbox(MockMailbox::new("INBOX"))
+                .connect();
+            Client::from_stream(stream).await.map(|(_, client)| client)
+        })
+        .login("djc", "s3cr3t");
+
+        session.reconnect().await.unwrap();
+        session.select("INBOX").await.unwrap();
+        session.close().await.unwrap();
+        session.reconnect().await.unwrap();
+        assert_eq!(session.client().unwrap().state(), State::Authenticated);
+    }
+
+    #[tokio::test]
+    async fn select_rejected() {
+        let mut session = ReconnectingClient::new(|| async {
+            let (stream, _) = MockServer::new()
+                .user("djc", "s3cr3t")
+                .mailbox(MockMailbox::new("INBOX").uid_validity(3))
+                .connect();
+            Client::from_stream(stream).await.map(|(_, client)| client)
+        })
+        .login("djc", "s3cr3t");
+
+        session.reconnect().await.unwrap();
+        session.select("INBOX").await.unwrap();
+        assert!(session.select("Missing").await.is_err());
+        let reconnected = session.reconnect().await.unwrap();
+        assert!(!reconnected.mailbox_lost);
+        assert_eq!(reconnected.uid_validity, None);
+        assert_eq!(session.client().unwrap().state(), State::Authenticated);
+    }
+
+    #[test]
+    fn backoff() {
+        let backoff = Backoff::new().max(Duration::from_secs(60)).factor(1e300);
+        assert_eq!(
+            backoff.next(Duration::from_secs(1)),
+            Duration::from_secs(60)
+        );
+        let backoff = Backoff::new().factor(f64::MAX);
+        assert_eq!(backoff.next(Duration::MAX), Duration::from_secs(300));
+        let backoff = Backoff::new().factor(1.5);
+        assert_eq!(backoff.next(Duration::from_secs(2)), Duration::from_secs(3));
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid backoff factor")]
+    fn backoff_factor() {
+        let _ = Backoff::new().factor(0.5);
+    }
+}